@@ -0,0 +1,190 @@
+//! Abbreviations module for Fool Shell
+//! Fish-style abbreviations: a name typed in command position expands in the
+//! edit buffer, so history and the AI see the full command, not the name
+
+use crate::completion::{classify, WordKind};
+use crate::config::Config;
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Abbreviations shared by the line editor and the `abbr` builtin
+pub type SharedAbbreviations = Arc<Mutex<Abbreviations>>;
+
+/// Lock shared abbreviations, recovering from a poisoned lock
+pub fn lock(abbreviations: &SharedAbbreviations) -> MutexGuard<'_, Abbreviations> {
+    abbreviations.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Name to expansion, backed by the `[abbreviations]` table of config.toml
+#[derive(Debug, Default)]
+pub struct Abbreviations {
+    entries: BTreeMap<String, String>,
+    path: Option<PathBuf>, // None keeps changes in memory (-c mode)
+}
+
+impl Abbreviations {
+    pub fn new(entries: BTreeMap<String, String>, path: PathBuf) -> Self {
+        Self {
+            entries,
+            path: Some(path),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.get(name).map(String::as_str)
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, String> {
+        &self.entries
+    }
+
+    /// Add or replace an abbreviation and save the table
+    pub fn set(&mut self, name: &str, expansion: &str) -> Result<()> {
+        check_name(name)?;
+        if expansion.trim().is_empty() {
+            bail!("expansion of '{}' cannot be empty", name);
+        }
+        let previous = self
+            .entries
+            .insert(name.to_string(), expansion.trim().to_string());
+        if let Err(e) = self.save() {
+            match previous {
+                Some(previous) => self.entries.insert(name.to_string(), previous),
+                None => self.entries.remove(name),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Remove an abbreviation and save the table; false if it didn't exist
+    pub fn erase(&mut self, name: &str) -> Result<bool> {
+        let Some(previous) = self.entries.remove(name) else {
+            return Ok(false);
+        };
+        if let Err(e) = self.save() {
+            self.entries.insert(name.to_string(), previous);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => Config::save_abbreviations_to(path, &self.entries),
+            None => Ok(()),
+        }
+    }
+
+    /// The abbreviation whose name ends at `pos` in command position, as the
+    /// start of the name and its expansion
+    pub fn expansion_at(&self, line: &str, pos: usize) -> Option<(usize, &str)> {
+        // Only a whole word expands, never the front of a longer one
+        if line[pos..].starts_with(|c: char| !c.is_whitespace()) {
+            return None;
+        }
+        let (start, kind) = classify(line, pos);
+        if kind != WordKind::Command {
+            return None;
+        }
+        self.get(&line[start..pos])
+            .map(|expansion| (start, expansion))
+    }
+
+    /// `line` with the abbreviation at its end expanded, as Enter does
+    pub fn expand_line(&self, line: &str) -> Option<String> {
+        let (start, expansion) = self.expansion_at(line, line.len())?;
+        Some(format!("{}{}", &line[..start], expansion))
+    }
+}
+
+/// Names are single words that can't be mistaken for shell syntax
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("abbreviation name cannot be empty");
+    }
+    if name
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '|' | '<' | '>' | '\'' | '"' | '\\' | '$' | '='))
+    {
+        bail!("'{}' is not a valid abbreviation name", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abbreviations() -> Abbreviations {
+        let mut abbreviations = Abbreviations::default();
+        abbreviations.set("gco", "git checkout").unwrap();
+        abbreviations.set("l", "ls -la").unwrap();
+        abbreviations
+    }
+
+    #[test]
+    fn test_expansion_at() {
+        let abbreviations = abbreviations();
+        assert_eq!(
+            abbreviations.expansion_at("gco", 3),
+            Some((0, "git checkout"))
+        );
+        assert_eq!(
+            abbreviations.expansion_at("cat x | l", 9),
+            Some((8, "ls -la"))
+        );
+        assert_eq!(
+            abbreviations.expansion_at("gco main", 3),
+            Some((0, "git checkout"))
+        );
+        // Arguments, partial words and unknown names stay as typed
+        assert_eq!(abbreviations.expansion_at("echo gco", 8), None);
+        assert_eq!(abbreviations.expansion_at("gcox", 3), None);
+        assert_eq!(abbreviations.expansion_at("gc", 2), None);
+    }
+
+    #[test]
+    fn test_expand_line() {
+        let abbreviations = abbreviations();
+        assert_eq!(
+            abbreviations.expand_line("ps aux | l").as_deref(),
+            Some("ps aux | ls -la")
+        );
+        assert_eq!(abbreviations.expand_line("gco main"), None);
+    }
+
+    #[test]
+    fn test_set_and_erase() {
+        let mut abbreviations = abbreviations();
+        assert!(abbreviations.set("bad name", "x").is_err());
+        assert!(abbreviations.set("x", "  ").is_err());
+        assert!(abbreviations.erase("gco").unwrap());
+        assert!(!abbreviations.erase("gco").unwrap());
+        assert_eq!(abbreviations.entries().len(), 1);
+    }
+
+    #[test]
+    fn test_persists_to_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "# my settings\n[ui]\ntheme = \"nord\" # favourite\n").unwrap();
+
+        let mut abbreviations = Abbreviations::new(BTreeMap::new(), path.clone());
+        abbreviations.set("gst", "git status").unwrap();
+        abbreviations.set("gco", "git checkout").unwrap();
+        abbreviations.erase("gst").unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("# my settings"));
+        assert!(content.contains("theme = \"nord\" # favourite"));
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.ui.theme, "nord");
+        assert_eq!(
+            config.abbreviations.into_iter().collect::<Vec<_>>(),
+            [("gco".to_string(), "git checkout".to_string())]
+        );
+    }
+}
@@ -1,15 +1,18 @@
 //! AI module for Fool Shell
 //! Handles OpenAI API integration with streaming support
 
-use crate::config::AiConfig;
+use crate::config::{expand_path, AiConfig, AiHttpConfig};
+use crate::context::ContextBuilder;
 use crate::history::History;
+use crate::theme::{self, paint, ColorSupport, Role};
 use anyhow::{anyhow, Context, Result};
 use crossterm::{
     cursor, execute,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::{Color, PrintStyledContent},
 };
 use futures::StreamExt;
-use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy};
 use serde::{Deserialize, Serialize};
 use std::io::{stdout, Write};
 use std::time::Duration;
@@ -48,59 +51,242 @@ struct Delta {
     content: Option<String>,
 }
 
+/// Marks the text after the AI trigger as a builtin (`!:save FILE`), so no
+/// question can run one by accident
+pub const BUILTIN_PREFIX: char = ':';
+
+/// Built-in AI commands answered locally instead of by the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AiBuiltin {
+    /// `!context` or `!:context`: show the environment snapshot sent with queries
+    Context,
+    /// `!:last [N]`: re-display the Nth most recent answer
+    Last(usize),
+    /// `!:save [-f] [BLOCK] FILE`: write the last answer, or one of its code blocks, to a file
+    Save {
+        block: Option<usize>,
+        path: String,
+        force: bool,
+    },
+    /// `!:answers [QUERY]`: list past questions and answers, optionally filtered
+    Answers(Option<String>),
+}
+
+impl AiBuiltin {
+    /// Recognise a builtin from the text after the AI trigger
+    /// Builtins need the `:` prefix and their exact shape; anything else is a
+    /// normal question
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim();
+        // A lone `!context` only displays, and predates the prefix
+        if query == "context" {
+            return Some(Self::Context);
+        }
+        let mut words = query.strip_prefix(BUILTIN_PREFIX)?.split_whitespace();
+        let command = words.next()?;
+        let args: Vec<&str> = words.collect();
+
+        match (command, args.as_slice()) {
+            ("context", []) => Some(Self::Context),
+            ("last", []) => Some(Self::Last(1)),
+            ("last", [n]) => n.parse().ok().filter(|n| *n > 0).map(Self::Last),
+            ("save", args) => {
+                let (force, args) = match args {
+                    ["-f", rest @ ..] => (true, rest),
+                    _ => (false, args),
+                };
+                match args {
+                    [path] => Some(Self::Save {
+                        block: None,
+                        path: path.to_string(),
+                        force,
+                    }),
+                    [block, path] => block.parse().ok().filter(|b| *b > 0).map(|b| Self::Save {
+                        block: Some(b),
+                        path: path.to_string(),
+                        force,
+                    }),
+                    _ => None,
+                }
+            }
+            ("answers", []) => Some(Self::Answers(None)),
+            ("answers", words) => Some(Self::Answers(Some(words.join(" ")))),
+            _ => None,
+        }
+    }
+}
+
 /// AI Agent for handling queries
+#[derive(Clone)]
 pub struct AiAgent {
-    client: Client,
+    client: Result<Client, String>, // Err: why `[ai.http]` couldn't be applied
     config: AiConfig,
 }
 
 impl AiAgent {
     /// Create a new AI agent with the given configuration
     /// M-05: Properly handle client build errors and preserve timeout settings
+    ///
+    /// A client that ignored `[ai.http]` could bypass a required proxy or
+    /// certificate, so a bad setting leaves the agent unconfigured instead.
     pub fn new(config: AiConfig) -> Self {
-        let client = Self::build_client();
+        let client = Self::build_client(&config.http).map_err(|e| {
+            // M-05: Log the error instead of silently swallowing it
+            eprintln!("Warning: {:#}", e);
+            eprintln!("Warning: AI is disabled until ai.http is fixed");
+            format!("{:#}", e)
+        });
         Self { client, config }
     }
 
-    /// Build HTTP client with appropriate timeouts
-    /// Falls back gracefully but preserves timeout configuration
-    fn build_client() -> Client {
-        let connect_timeout = Duration::from_secs(10);
-        let request_timeout = Duration::from_secs(60);
+    /// Build HTTP client from `[ai.http]`
+    /// Each error names the setting that caused it
+    fn build_client(http: &AiHttpConfig) -> Result<Client> {
+        let mut builder =
+            Client::builder().connect_timeout(Duration::from_secs(http.connect_timeout_secs));
+
+        if !http.proxy.is_empty() {
+            let mut proxy = Proxy::all(&http.proxy)
+                .with_context(|| format!("ai.http.proxy: invalid proxy URL '{}'", http.proxy))?;
+            if !http.no_proxy.is_empty() {
+                proxy = proxy.no_proxy(NoProxy::from_string(&http.no_proxy.join(",")));
+            }
+            builder = builder.proxy(proxy);
+        } else if !http.no_proxy.is_empty() {
+            eprintln!("Warning: ai.http.no_proxy has no effect without ai.http.proxy");
+        }
+
+        if !http.ca_bundle.is_empty() {
+            let path = expand_path(&http.ca_bundle);
+            let pem = std::fs::read(&path)
+                .with_context(|| format!("ai.http.ca_bundle: failed to read {:?}", path))?;
+            let certs = Certificate::from_pem_bundle(&pem).with_context(|| {
+                format!("ai.http.ca_bundle: {:?} is not a valid PEM bundle", path)
+            })?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
 
-        match Client::builder()
-            .connect_timeout(connect_timeout)
-            .timeout(request_timeout)
-            .build()
-        {
-            Ok(client) => client,
-            Err(e) => {
-                // M-05: Log the error instead of silently swallowing it
-                eprintln!(
-                    "Warning: Failed to build HTTP client with custom settings: {}",
-                    e
-                );
-                eprintln!("Warning: Using default client (timeouts may not be applied)");
-
-                // Try a simpler configuration
-                Client::builder().build().unwrap_or_else(|_| Client::new())
+        if !http.client_cert.is_empty() {
+            builder = builder.identity(Self::load_identity(http)?);
+        }
+
+        if !http.headers.is_empty() {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &http.headers {
+                let header_name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("ai.http.headers: invalid header name '{}'", name))?;
+                let header_value = HeaderValue::from_str(value).with_context(|| {
+                    format!("ai.http.headers: invalid value for header '{}'", name)
+                })?;
+                headers.insert(header_name, header_value);
             }
+            builder = builder.default_headers(headers);
+        }
+
+        builder
+            .build()
+            .with_context(|| "ai.http: failed to build HTTP client")
+    }
+
+    /// Load the client certificate, PKCS#12 or PEM with a separate key
+    fn load_identity(http: &AiHttpConfig) -> Result<Identity> {
+        let cert_path = expand_path(&http.client_cert);
+        let cert = std::fs::read(&cert_path)
+            .with_context(|| format!("ai.http.client_cert: failed to read {:?}", cert_path))?;
+
+        let is_pkcs12 = cert_path
+            .extension()
+            .map(|ext| ext == "p12" || ext == "pfx")
+            .unwrap_or(false);
+        if is_pkcs12 {
+            return Identity::from_pkcs12_der(&cert, &http.client_cert_password).with_context(
+                || {
+                    format!(
+                        "ai.http.client_cert: {:?} is not a valid PKCS#12 file (check client_cert_password)",
+                        cert_path
+                    )
+                },
+            );
+        }
+
+        if http.client_key.is_empty() {
+            return Err(anyhow!(
+                "ai.http.client_key: required when ai.http.client_cert is a PEM file"
+            ));
+        }
+        let key_path = expand_path(&http.client_key);
+        let key = std::fs::read(&key_path)
+            .with_context(|| format!("ai.http.client_key: failed to read {:?}", key_path))?;
+        Identity::from_pkcs8_pem(&cert, &key).with_context(|| {
+            format!(
+                "ai.http.client_cert/client_key: {:?} and {:?} are not a valid PEM certificate and PKCS#8 key",
+                cert_path, key_path
+            )
+        })
+    }
+
+    /// Value for the auth header, e.g. "Bearer <key>" or the bare key
+    fn auth_value(&self, api_key: &str) -> String {
+        if self.config.http.auth_scheme.is_empty() {
+            api_key.to_string()
+        } else {
+            format!("{} {}", self.config.http.auth_scheme, api_key)
+        }
+    }
+
+    /// Await a response step, failing if the server goes quiet for too long
+    async fn with_read_timeout<T>(&self, step: impl std::future::Future<Output = T>) -> Result<T> {
+        let limit = Duration::from_secs(self.config.http.read_timeout_secs);
+        tokio::time::timeout(limit, step).await.map_err(|_| {
+            anyhow!(
+                "AI API did not respond within {}s (ai.http.read_timeout_secs)",
+                limit.as_secs()
+            )
+        })
+    }
+
+    /// An agent for `config` sharing this one's HTTP client, so `[ai.http]`
+    /// isn't rebuilt (or warned about) again; `config.http` is ignored
+    pub fn with_config(&self, config: AiConfig) -> Self {
+        Self {
+            client: self.client.clone(),
+            config,
         }
     }
 
     /// Check if AI is properly configured
     pub fn is_configured(&self) -> bool {
-        self.config.get_api_key().is_some()
+        self.client.is_ok() && self.config.get_api_key().is_some()
+    }
+
+    /// Why `is_configured` is false, for error messages
+    pub fn setup_error(&self) -> String {
+        match &self.client {
+            Err(e) => format!("AI disabled: {}", e),
+            Ok(_) => "AI not configured. Set FOOL_AI_KEY or OPENAI_API_KEY environment variable."
+                .to_string(),
+        }
+    }
+
+    fn client(&self) -> Result<&Client> {
+        self.client.as_ref().map_err(|e| anyhow!("{}", e))
     }
 
     /// Build messages for the API request
-    fn build_messages(&self, query: &str, history: &History) -> Vec<ChatMessage> {
+    pub fn build_messages(&self, query: &str, history: &History) -> Vec<ChatMessage> {
         let mut messages = Vec::new();
 
-        // Add system prompt
+        // Add system prompt, followed by the environment snapshot
+        let mut system_prompt = self.config.system_prompt.clone();
+        if let Some(context) = ContextBuilder::new(self.config.context.clone()).render() {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(&context);
+        }
         messages.push(ChatMessage {
             role: "system".to_string(),
-            content: self.config.system_prompt.clone(),
+            content: system_prompt,
         });
 
         // Add history context
@@ -125,11 +311,15 @@ impl AiAgent {
 
     /// Send a query and stream the response
     pub async fn query_stream(&self, query: &str, history: &History) -> Result<String> {
+        let messages = self.build_messages(query, history);
+        self.stream_messages(messages).await
+    }
+
+    /// Send pre-built messages and stream the response
+    pub async fn stream_messages(&self, messages: Vec<ChatMessage>) -> Result<String> {
         let api_key = self.config.get_api_key()
             .ok_or_else(|| anyhow!("API key not configured. Set FOOL_AI_KEY or OPENAI_API_KEY environment variable, or configure api_key in config.toml"))?;
 
-        let messages = self.build_messages(query, history);
-
         let request = ChatRequest {
             model: self.config.model.clone(),
             messages,
@@ -143,20 +333,23 @@ impl AiAgent {
         print!("\r");
         execute!(
             stdout(),
-            SetForegroundColor(Color::Cyan),
-            Print("⠋ Thinking..."),
-            ResetColor
+            PrintStyledContent(paint(Role::Info, "⠋ Thinking..."))
         )?;
         stdout().flush()?;
 
-        let response = self
-            .client
+        let request = self
+            .client()?
             .post(&url)
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header(
+                self.config.http.auth_header.as_str(),
+                self.auth_value(&api_key),
+            )
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
+            .send();
+        let response = self
+            .with_read_timeout(request)
+            .await?
             .with_context(|| "Failed to send request to AI API")?;
 
         if !response.status().is_success() {
@@ -180,14 +373,9 @@ impl AiAgent {
         let mut buffer = String::new(); // Buffer for partial SSE frames
 
         // Print AI response header
-        execute!(
-            stdout(),
-            SetForegroundColor(Color::Green),
-            Print("AI: "),
-            ResetColor
-        )?;
+        execute!(stdout(), PrintStyledContent(paint(Role::Success, "AI: ")))?;
 
-        while let Some(chunk) = stream.next().await {
+        while let Some(chunk) = self.with_read_timeout(stream.next()).await? {
             let chunk = chunk.with_context(|| "Failed to read response chunk")?;
             let text = String::from_utf8_lossy(&chunk);
 
@@ -247,13 +435,17 @@ impl AiAgent {
     /// Send a query without streaming (for testing or simple use)
     #[allow(dead_code)] // Reserved for future non-streaming API usage
     pub async fn query(&self, query: &str, history: &History) -> Result<String> {
+        let messages = self.build_messages(query, history);
+        self.complete(messages).await
+    }
+
+    /// Send pre-built messages without streaming and return the reply text
+    pub async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String> {
         let api_key = self
             .config
             .get_api_key()
             .ok_or_else(|| anyhow!("API key not configured"))?;
 
-        let messages = self.build_messages(query, history);
-
         let request = ChatRequest {
             model: self.config.model.clone(),
             messages,
@@ -263,14 +455,17 @@ impl AiAgent {
 
         let url = format!("{}/chat/completions", self.config.api_base);
 
-        let response = self
-            .client
+        let request = self
+            .client()?
             .post(&url)
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header(
+                self.config.http.auth_header.as_str(),
+                self.auth_value(&api_key),
+            )
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await?;
+            .send();
+        let response = self.with_read_timeout(request).await??;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -278,7 +473,7 @@ impl AiAgent {
             return Err(anyhow!("API request failed: {} - {}", status, body));
         }
 
-        let body: serde_json::Value = response.json().await?;
+        let body: serde_json::Value = self.with_read_timeout(response.json()).await??;
         let content = body["choices"][0]["message"]["content"]
             .as_str()
             .unwrap_or("")
@@ -289,12 +484,67 @@ impl AiAgent {
 }
 
 /// Render markdown in terminal using termimad
-#[allow(dead_code)] // Reserved for future markdown rendering feature
 pub fn render_markdown(text: &str) {
+    markdown_skin().print_text(text);
+}
+
+/// termimad's skin with the current theme's colours
+fn markdown_skin() -> termimad::MadSkin {
     use termimad::MadSkin;
 
-    let skin = MadSkin::default();
-    skin.print_text(text);
+    let theme = theme::current();
+    if theme.support() == ColorSupport::None {
+        return MadSkin::no_style();
+    }
+    let mut skin = MadSkin::default();
+    let fg = |role: Role| theme.get(role).foreground_color.map(mad_color);
+    if let Some(color) = fg(Role::Heading) {
+        skin.set_headers_fg(color);
+    }
+    let code = theme.get(Role::Code);
+    if let Some(color) = code.foreground_color.map(mad_color) {
+        skin.inline_code.set_fg(color);
+        skin.code_block.set_fg(color);
+    }
+    if theme.support() == ColorSupport::Basic {
+        // termimad's grey code background needs 256 colours
+        skin.inline_code.object_style.background_color = None;
+        skin.code_block.compound_style.object_style.background_color = None;
+    }
+    if let Some(color) = fg(Role::Accent) {
+        skin.bullet.set_fg(color);
+    }
+    if let Some(color) = fg(Role::Muted) {
+        skin.quote_mark.set_fg(color);
+        skin.horizontal_rule.set_fg(color);
+    }
+    skin
+}
+
+/// termimad uses its own crossterm version
+fn mad_color(color: Color) -> termimad::crossterm::style::Color {
+    use termimad::crossterm::style::Color as Mad;
+    match color {
+        Color::Reset => Mad::Reset,
+        Color::Black => Mad::Black,
+        Color::DarkGrey => Mad::DarkGrey,
+        Color::Red => Mad::Red,
+        Color::DarkRed => Mad::DarkRed,
+        Color::Green => Mad::Green,
+        Color::DarkGreen => Mad::DarkGreen,
+        Color::Yellow => Mad::Yellow,
+        Color::DarkYellow => Mad::DarkYellow,
+        Color::Blue => Mad::Blue,
+        Color::DarkBlue => Mad::DarkBlue,
+        Color::Magenta => Mad::Magenta,
+        Color::DarkMagenta => Mad::DarkMagenta,
+        Color::Cyan => Mad::Cyan,
+        Color::DarkCyan => Mad::DarkCyan,
+        Color::White => Mad::White,
+        Color::Grey => Mad::Grey,
+        Color::Rgb { r, g, b } => Mad::Rgb { r, g, b },
+        Color::AnsiValue(n) => Mad::AnsiValue(n),
+    }
 }
 
 #[cfg(test)]
@@ -313,4 +563,98 @@ mod tests {
         assert_eq!(messages.last().unwrap().role, "user");
         assert_eq!(messages.last().unwrap().content, "test query");
     }
+
+    #[test]
+    fn test_ai_builtin_parse() {
+        assert_eq!(AiBuiltin::parse("context"), Some(AiBuiltin::Context));
+        assert_eq!(AiBuiltin::parse(" context "), Some(AiBuiltin::Context));
+        assert_eq!(AiBuiltin::parse(":context"), Some(AiBuiltin::Context));
+        assert_eq!(AiBuiltin::parse("what is my context"), None);
+
+        assert_eq!(AiBuiltin::parse(":last"), Some(AiBuiltin::Last(1)));
+        assert_eq!(AiBuiltin::parse(":last 3"), Some(AiBuiltin::Last(3)));
+        assert_eq!(AiBuiltin::parse(":last time it failed"), None);
+
+        assert_eq!(
+            AiBuiltin::parse(":save 2 script.sh"),
+            Some(AiBuiltin::Save {
+                block: Some(2),
+                path: "script.sh".to_string(),
+                force: false
+            })
+        );
+        assert_eq!(
+            AiBuiltin::parse(":save -f notes.md"),
+            Some(AiBuiltin::Save {
+                block: None,
+                path: "notes.md".to_string(),
+                force: true
+            })
+        );
+        assert_eq!(AiBuiltin::parse(":save me some time please"), None);
+
+        assert_eq!(
+            AiBuiltin::parse(":answers tar"),
+            Some(AiBuiltin::Answers(Some("tar".to_string())))
+        );
+
+        // Without the prefix these are questions, however they're worded
+        for question in [
+            "last",
+            "last 3 commits?",
+            "save X",
+            "save -f notes.md",
+            "answers to life",
+            "answers",
+        ] {
+            assert_eq!(AiBuiltin::parse(question), None, "{}", question);
+        }
+    }
+
+    #[test]
+    fn test_build_client_errors_name_setting() {
+        let mut http = AiHttpConfig {
+            proxy: "not a url".to_string(),
+            ..AiHttpConfig::default()
+        };
+        let err = AiAgent::build_client(&http).unwrap_err();
+        assert!(format!("{:#}", err).contains("ai.http.proxy"));
+
+        http.proxy.clear();
+        http.ca_bundle = "/nonexistent/fool-ca.pem".to_string();
+        let err = AiAgent::build_client(&http).unwrap_err();
+        assert!(format!("{:#}", err).contains("ai.http.ca_bundle"));
+
+        http.ca_bundle.clear();
+        http.headers
+            .insert("bad header".to_string(), "x".to_string());
+        let err = AiAgent::build_client(&http).unwrap_err();
+        assert!(format!("{:#}", err).contains("ai.http.headers"));
+    }
+
+    #[test]
+    fn test_bad_http_settings_disable_ai() {
+        let mut config = AiConfig {
+            api_key: "sk-1".to_string(),
+            ..AiConfig::default()
+        };
+        assert!(AiAgent::new(config.clone()).is_configured());
+
+        config.http.proxy = "not a url".to_string();
+        let agent = AiAgent::new(config);
+        assert!(!agent.is_configured());
+        assert!(agent.setup_error().contains("ai.http.proxy"));
+    }
+
+    #[test]
+    fn test_auth_value_scheme() {
+        let mut config = AiConfig::default();
+        let agent = AiAgent::new(config.clone());
+        assert_eq!(agent.auth_value("sk-1"), "Bearer sk-1");
+
+        config.http.auth_header = "api-key".to_string();
+        config.http.auth_scheme = String::new();
+        let agent = AiAgent::new(config);
+        assert_eq!(agent.auth_value("sk-1"), "sk-1");
+    }
 }
@@ -0,0 +1,297 @@
+//! Answers module for Fool Shell
+//! Keeps recent AI answers in memory and on disk so they can be re-displayed,
+//! searched and saved to files
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// A question and the AI's answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerEntry {
+    pub question: String,
+    pub answer: String,
+    pub timestamp: DateTime<Utc>,
+    /// Timestamp of the `HistoryEntry` that recorded the question
+    #[serde(default)]
+    pub history_timestamp: Option<DateTime<Utc>>,
+    /// Kept for this session only, never written to the answers file
+    #[serde(skip)]
+    session_only: bool,
+}
+
+impl AnswerEntry {
+    pub fn new(question: String, answer: String, history_timestamp: Option<DateTime<Utc>>) -> Self {
+        Self {
+            question,
+            answer,
+            timestamp: Utc::now(),
+            history_timestamp,
+            session_only: false,
+        }
+    }
+
+    /// Fenced code blocks in the answer, in order
+    pub fn code_blocks(&self) -> Vec<String> {
+        code_blocks(&self.answer)
+    }
+}
+
+/// Store of recent answers
+pub struct AnswerStore {
+    entries: VecDeque<AnswerEntry>,
+    file_path: Option<PathBuf>, // None = memory-only mode
+    max_entries: usize,
+    entries_since_compact: usize,
+}
+
+impl AnswerStore {
+    pub fn new(file_path: String, max_entries: usize) -> Result<Self> {
+        let file_path = crate::config::expand_path(&file_path);
+
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create answers directory: {:?}", parent))?;
+        }
+
+        let mut store = Self {
+            entries: VecDeque::with_capacity(max_entries),
+            file_path: Some(file_path),
+            max_entries,
+            entries_since_compact: 0,
+        };
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Create a memory-only store (no file persistence)
+    pub fn new_memory_only(max_entries: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(max_entries),
+            file_path: None,
+            max_entries,
+            entries_since_compact: 0,
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        let file_path = match &self.file_path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+
+        let file = File::open(file_path)
+            .with_context(|| format!("Failed to open answers file: {:?}", file_path))?;
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            if let Ok(entry) = serde_json::from_str::<AnswerEntry>(&line) {
+                self.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, entry: AnswerEntry) {
+        self.entries.push_back(entry);
+        if self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Add an answer and append it to the answers file
+    pub fn add(&mut self, entry: AnswerEntry) -> Result<()> {
+        if let Some(file_path) = &self.file_path {
+            let mut options = OpenOptions::new();
+            options.create(true).append(true);
+
+            // Answers quote commands and files, so keep them private like history
+            #[cfg(unix)]
+            options.mode(0o600);
+
+            let mut file = options.open(file_path).with_context(|| {
+                format!("Failed to open answers file for writing: {:?}", file_path)
+            })?;
+            let json =
+                serde_json::to_string(&entry).with_context(|| "Failed to serialize answer")?;
+            writeln!(file, "{}", json).with_context(|| "Failed to write answer")?;
+        }
+
+        self.push(entry);
+        self.entries_since_compact += 1;
+        if self.entries_since_compact >= self.max_entries {
+            self.compact()?;
+            self.entries_since_compact = 0;
+        }
+        Ok(())
+    }
+
+    /// Add an answer that `!:last` and `!:save` can reach this session but
+    /// that is never written to disk (incognito or unrecorded questions)
+    pub fn add_in_memory(&mut self, mut entry: AnswerEntry) {
+        entry.session_only = true;
+        self.push(entry);
+    }
+
+    /// Rewrite the file with only the entries kept in memory
+    fn compact(&self) -> Result<()> {
+        let file_path = match &self.file_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let temp_path = file_path.with_extension("tmp");
+        {
+            let mut options = OpenOptions::new();
+            options.create(true).write(true).truncate(true);
+
+            #[cfg(unix)]
+            options.mode(0o600);
+
+            let mut file = options
+                .open(&temp_path)
+                .with_context(|| format!("Failed to create temp answers file: {:?}", temp_path))?;
+            for entry in self.entries.iter().filter(|e| !e.session_only) {
+                writeln!(file, "{}", serde_json::to_string(entry)?)?;
+            }
+            file.flush()?;
+        }
+        fs::rename(&temp_path, file_path).with_context(|| "Failed to rename temp answers file")?;
+        Ok(())
+    }
+
+    /// Get the `n`th most recent answer (1 = last)
+    pub fn get(&self, n: usize) -> Option<&AnswerEntry> {
+        if n == 0 {
+            return None;
+        }
+        self.entries.iter().rev().nth(n - 1)
+    }
+
+    /// Answers newest first, numbered as accepted by `get`
+    pub fn list(&self) -> Vec<(usize, &AnswerEntry)> {
+        self.entries
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, e)| (i + 1, e))
+            .collect()
+    }
+
+    /// Answers whose question or answer contains `query` (case-insensitive)
+    pub fn search(&self, query: &str) -> Vec<(usize, &AnswerEntry)> {
+        let query = query.to_lowercase();
+        self.list()
+            .into_iter()
+            .filter(|(_, e)| {
+                e.question.to_lowercase().contains(&query)
+                    || e.answer.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    #[allow(dead_code)] // Public API
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Extract the contents of fenced (```) code blocks
+pub fn code_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(lines) => blocks.push(lines.join("\n")),
+                None => current = Some(Vec::new()),
+            }
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_code_blocks() {
+        let answer = "Run this:\n```bash\necho one\n```\nthen\n```\nls\npwd\n```";
+        assert_eq!(code_blocks(answer), vec!["echo one", "ls\npwd"]);
+        assert!(code_blocks("no code here").is_empty());
+    }
+
+    #[test]
+    fn test_get_and_search() {
+        let mut store = AnswerStore::new_memory_only(10);
+        store
+            .add(AnswerEntry::new("q1".into(), "use tar".into(), None))
+            .unwrap();
+        store
+            .add(AnswerEntry::new("q2".into(), "use grep".into(), None))
+            .unwrap();
+
+        assert_eq!(store.get(1).unwrap().question, "q2");
+        assert_eq!(store.get(2).unwrap().question, "q1");
+        assert!(store.get(3).is_none());
+
+        let results = store.search("TAR");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, 2);
+    }
+
+    #[test]
+    fn test_persistence_and_limit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("answers").to_string_lossy().to_string();
+
+        {
+            let mut store = AnswerStore::new(path.clone(), 2).unwrap();
+            for i in 0..3 {
+                store
+                    .add(AnswerEntry::new(format!("q{}", i), "a".into(), None))
+                    .unwrap();
+            }
+        }
+
+        let store = AnswerStore::new(path, 2).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(1).unwrap().question, "q2");
+    }
+
+    #[test]
+    fn test_in_memory_answers_stay_off_disk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("answers").to_string_lossy().to_string();
+
+        {
+            let mut store = AnswerStore::new(path.clone(), 2).unwrap();
+            store.add_in_memory(AnswerEntry::new("secret".into(), "a".into(), None));
+            assert_eq!(store.get(1).unwrap().question, "secret");
+            // Compaction (every max_entries adds) rewrites the file from memory
+            for i in 0..2 {
+                store
+                    .add(AnswerEntry::new(format!("q{}", i), "a".into(), None))
+                    .unwrap();
+            }
+            store.add_in_memory(AnswerEntry::new("secret".into(), "b".into(), None));
+            assert_eq!(store.get(1).unwrap().answer, "b");
+        }
+
+        let content = std::fs::read_to_string(dir.path().join("answers")).unwrap();
+        assert!(!content.contains("secret"));
+        let store = AnswerStore::new(path, 2).unwrap();
+        assert_eq!(store.len(), 2);
+    }
+}
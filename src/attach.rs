@@ -0,0 +1,431 @@
+//! Attachment module for Fool Shell
+//! Inlines files referenced as `@path` in AI queries, with size caps,
+//! binary detection and secret masking
+
+use crate::config::{expand_path, AiAttachmentConfig};
+use regex::Regex;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Marker substituted for anything that looks like a secret
+const MASK: &str = "[MASKED]";
+
+/// Bytes inspected when deciding whether a file is binary
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Outcome of reading one `@path` reference
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: String,
+    pub status: AttachmentStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachmentStatus {
+    /// Inlined; `truncated` if cut at the size cap, `masked` secrets replaced
+    Included {
+        bytes: usize,
+        truncated: bool,
+        masked: usize,
+    },
+    Binary,
+    /// Skipped because the total size cap was already reached
+    OverBudget,
+    /// Refused because it resolves outside the current directory
+    OutsideDirectory,
+    Unreadable(String),
+}
+
+impl Attachment {
+    pub fn is_included(&self) -> bool {
+        matches!(self.status, AttachmentStatus::Included { .. })
+    }
+
+    /// One-line description for the user, e.g. "Cargo.toml (812 bytes, 1 secret masked)"
+    pub fn summary(&self) -> String {
+        match &self.status {
+            AttachmentStatus::Included {
+                bytes,
+                truncated,
+                masked,
+            } => {
+                let mut notes = vec![format!("{} bytes", bytes)];
+                if *truncated {
+                    notes.push("truncated".to_string());
+                }
+                if *masked > 0 {
+                    let noun = if *masked == 1 { "secret" } else { "secrets" };
+                    notes.push(format!("{} {} masked", masked, noun));
+                }
+                format!("{} ({})", self.path, notes.join(", "))
+            }
+            AttachmentStatus::Binary => format!("{} skipped: binary file", self.path),
+            AttachmentStatus::OverBudget => {
+                format!("{} skipped: attachment size limit reached", self.path)
+            }
+            AttachmentStatus::OutsideDirectory => {
+                format!("{} refused: outside the current directory", self.path)
+            }
+            AttachmentStatus::Unreadable(e) => format!("{} skipped: {}", self.path, e),
+        }
+    }
+}
+
+/// A query with its referenced files inlined
+pub struct ExpandedQuery {
+    pub prompt: String,
+    pub attachments: Vec<Attachment>,
+}
+
+/// Find `@path` references: an `@` at the start of a word, with `\ ` for spaces
+pub fn find_references(query: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    let chars: Vec<char> = query.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let at_word_start = i == 0 || chars[i - 1].is_whitespace();
+        if chars[i] == '@' && at_word_start {
+            let mut path = String::new();
+            i += 1;
+            while i < chars.len() && !chars[i].is_whitespace() {
+                if chars[i] == '\\' && i + 1 < chars.len() && chars[i + 1] == ' ' {
+                    path.push(' ');
+                    i += 2;
+                    continue;
+                }
+                path.push(chars[i]);
+                i += 1;
+            }
+            if !path.is_empty() && !refs.contains(&path) {
+                refs.push(path);
+            }
+        }
+        i += 1;
+    }
+
+    refs
+}
+
+/// Inline every `@path` in `query` below the question
+///
+/// Only files inside the current directory are read; the query leaves the
+/// machine, so `@~/.ssh/id_rsa` or `@../secret.env` must not.
+pub fn expand_query(query: &str, config: &AiAttachmentConfig) -> ExpandedQuery {
+    match std::env::current_dir() {
+        Ok(cwd) => expand_query_in(query, config, &cwd),
+        Err(e) => ExpandedQuery {
+            prompt: query.to_string(),
+            attachments: find_references(query)
+                .into_iter()
+                .map(|path| Attachment {
+                    path,
+                    status: AttachmentStatus::Unreadable(e.to_string()),
+                })
+                .collect(),
+        },
+    }
+}
+
+/// `expand_query` with references resolved against `base`
+fn expand_query_in(query: &str, config: &AiAttachmentConfig, base: &Path) -> ExpandedQuery {
+    let mut prompt = query.to_string();
+    let mut attachments = Vec::new();
+    let mut budget = config.max_total_bytes;
+
+    for reference in find_references(query) {
+        // Allow sentence punctuation right after a reference: "see @a.log."
+        let path = resolve_reference(&reference, base);
+        let (status, body) = read_attachment(&path, base, config, budget);
+
+        if let Some(body) = body {
+            budget = budget.saturating_sub(body.len());
+            prompt.push_str(&format!(
+                "\n\n--- @{} ---\n{}\n--- end @{} ---",
+                path,
+                body.trim_end(),
+                path
+            ));
+        }
+        attachments.push(Attachment { path, status });
+    }
+
+    ExpandedQuery {
+        prompt,
+        attachments,
+    }
+}
+
+/// Strip trailing punctuation unless the file really has it in its name
+fn resolve_reference(reference: &str, base: &Path) -> String {
+    if base.join(expand_path(reference)).exists() {
+        return reference.to_string();
+    }
+    reference
+        .trim_end_matches([',', '.', ';', ':', '?', '!', ')'])
+        .to_string()
+}
+
+/// The real location of `path`, if it is inside `base` once symlinks and
+/// `..` are resolved
+fn confine(path: &str, base: &Path) -> Result<PathBuf, AttachmentStatus> {
+    let unreadable = |e: std::io::Error| AttachmentStatus::Unreadable(e.to_string());
+    let full_path = base
+        .join(expand_path(path))
+        .canonicalize()
+        .map_err(unreadable)?;
+    let base = base.canonicalize().map_err(unreadable)?;
+    if full_path.starts_with(&base) {
+        Ok(full_path)
+    } else {
+        Err(AttachmentStatus::OutsideDirectory)
+    }
+}
+
+fn read_attachment(
+    path: &str,
+    base: &Path,
+    config: &AiAttachmentConfig,
+    budget: usize,
+) -> (AttachmentStatus, Option<String>) {
+    let full_path = match confine(path, base) {
+        Ok(full_path) => full_path,
+        Err(status) => return (status, None),
+    };
+    // Opening a FIFO or a device like /dev/tty could block, so look first
+    let metadata = match std::fs::metadata(&full_path) {
+        Ok(metadata) => metadata,
+        Err(e) => return (AttachmentStatus::Unreadable(e.to_string()), None),
+    };
+    if metadata.is_dir() {
+        return (
+            AttachmentStatus::Unreadable("is a directory".to_string()),
+            None,
+        );
+    }
+    if !metadata.is_file() {
+        return (
+            AttachmentStatus::Unreadable("not a regular file".to_string()),
+            None,
+        );
+    }
+    if budget == 0 {
+        return (AttachmentStatus::OverBudget, None);
+    }
+
+    let file = match std::fs::File::open(&full_path) {
+        Ok(f) => f,
+        Err(e) => return (AttachmentStatus::Unreadable(e.to_string()), None),
+    };
+    let total_size = metadata.len() as usize;
+    let limit = config.max_file_bytes.min(budget);
+
+    // Read one byte past the limit to know whether we truncated
+    let mut bytes = Vec::new();
+    if let Err(e) = file.take(limit as u64 + 1).read_to_end(&mut bytes) {
+        return (AttachmentStatus::Unreadable(e.to_string()), None);
+    }
+
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return (AttachmentStatus::Binary, None);
+    }
+
+    let truncated = bytes.len() > limit;
+    bytes.truncate(limit);
+    let mut text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            // A multi-byte character cut by the limit is fine; anything else is binary
+            let valid = e.utf8_error().valid_up_to();
+            if truncated && e.utf8_error().error_len().is_none() {
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).unwrap_or_default()
+            } else {
+                return (AttachmentStatus::Binary, None);
+            }
+        }
+    };
+
+    let masked = if config.mask_secrets {
+        let (clean, count) = mask_secrets(&text);
+        text = clean;
+        count
+    } else {
+        0
+    };
+
+    let bytes = text.len();
+    if truncated {
+        text.push_str(&format!(
+            "\n[... truncated: showing first {} of {} bytes]",
+            bytes, total_size
+        ));
+    }
+
+    (
+        AttachmentStatus::Included {
+            bytes,
+            truncated,
+            masked,
+        },
+        Some(text),
+    )
+}
+
+fn secret_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // PEM private key blocks
+            r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+            // Well-known token formats
+            r"\bsk-[A-Za-z0-9_-]{16,}",
+            r"\bAKIA[0-9A-Z]{16}\b",
+            r"\bgh[pousr]_[A-Za-z0-9]{30,}",
+            r"\bxox[baprs]-[A-Za-z0-9-]{10,}",
+            r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]{16,}=*",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid secret pattern"))
+        .collect()
+    })
+}
+
+fn assignment_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        // key = value / key: "value" where the key names a credential
+        Regex::new(
+            r#"(?i)\b([A-Za-z0-9_.-]*(?:api[_-]?key|secret|token|password|passwd|credential)[A-Za-z0-9_.-]*)(["']?\s*[:=]\s*["']?)([^\s"',;]{4,})"#,
+        )
+        .expect("valid assignment pattern")
+    })
+}
+
+/// Replace likely secrets with a mask, returning the text and mask count
+pub fn mask_secrets(text: &str) -> (String, usize) {
+    let mut count = 0;
+    let mut result = text.to_string();
+
+    for pattern in secret_patterns() {
+        let replaced = pattern.replace_all(&result, |_: &regex::Captures| {
+            count += 1;
+            MASK
+        });
+        result = replaced.into_owned();
+    }
+
+    let replaced = assignment_pattern().replace_all(&result, |caps: &regex::Captures| {
+        if &caps[3] == MASK {
+            return caps[0].to_string();
+        }
+        count += 1;
+        format!("{}{}{}", &caps[1], &caps[2], MASK)
+    });
+
+    (replaced.into_owned(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_references() {
+        let refs = find_references("why does this fail @Cargo.toml @logs/build\\ 1.log user@host");
+        assert_eq!(refs, vec!["Cargo.toml", "logs/build 1.log"]);
+    }
+
+    #[test]
+    fn test_mask_secrets() {
+        let text = "api_key = \"sk-abcdefghijklmnopqrstuv\"\nPASSWORD: hunter22\nname = fool";
+        let (masked, count) = mask_secrets(text);
+        assert_eq!(count, 2);
+        assert!(!masked.contains("sk-abcdefghijklmnopqrstuv"));
+        assert!(!masked.contains("hunter22"));
+        assert!(masked.contains("name = fool"));
+    }
+
+    #[test]
+    fn test_expand_query_truncates_and_detects_binary() {
+        let dir = tempdir().unwrap();
+        let text_path = dir.path().join("big.txt");
+        let bin_path = dir.path().join("data.bin");
+        std::fs::write(&text_path, "a".repeat(100)).unwrap();
+        std::fs::write(&bin_path, [0u8, 1, 2, 3]).unwrap();
+
+        let config = AiAttachmentConfig {
+            max_file_bytes: 10,
+            ..AiAttachmentConfig::default()
+        };
+        let expanded = expand_query_in("explain @big.txt @data.bin", &config, dir.path());
+
+        assert_eq!(
+            expanded.attachments[0].status,
+            AttachmentStatus::Included {
+                bytes: 10,
+                truncated: true,
+                masked: 0
+            }
+        );
+        assert_eq!(expanded.attachments[1].status, AttachmentStatus::Binary);
+        assert!(expanded
+            .prompt
+            .contains("[... truncated: showing first 10 of 100 bytes]"));
+    }
+
+    #[test]
+    fn test_trailing_punctuation_and_missing_file() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("build.log"), "ok").unwrap();
+        let config = AiAttachmentConfig::default();
+        let expanded = expand_query_in("look at @build.log. @fool.log", &config, dir.path());
+        assert_eq!(expanded.attachments[0].path, "build.log");
+        assert!(expanded.attachments[0].is_included());
+        assert!(matches!(
+            expanded.attachments[1].status,
+            AttachmentStatus::Unreadable(_)
+        ));
+    }
+
+    #[test]
+    fn test_refuses_files_outside_current_directory() {
+        let dir = tempdir().unwrap();
+        let cwd = dir.path().join("project");
+        std::fs::create_dir(&cwd).unwrap();
+        std::fs::write(dir.path().join("x"), "secret").unwrap();
+        let config = AiAttachmentConfig::default();
+
+        let expanded = expand_query_in("read @/etc/passwd @../x", &config, &cwd);
+        for attachment in &expanded.attachments {
+            assert_eq!(attachment.status, AttachmentStatus::OutsideDirectory);
+        }
+        assert_eq!(expanded.attachments.len(), 2);
+        assert!(!expanded.prompt.contains("secret"));
+
+        // Nor through a symlink that points out of it
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path().join("x"), cwd.join("link")).unwrap();
+            let expanded = expand_query_in("read @link", &config, &cwd);
+            assert_eq!(
+                expanded.attachments[0].status,
+                AttachmentStatus::OutsideDirectory
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skips_special_files() {
+        let config = AiAttachmentConfig::default();
+        let expanded = expand_query_in("read @null", &config, Path::new("/dev"));
+        assert_eq!(
+            expanded.attachments[0].status,
+            AttachmentStatus::Unreadable("not a regular file".to_string())
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Key bindings module for Fool Shell
+//! Parses the `[keybindings]` table and the handlers behind its actions
+//!
+//! Keys are written like `ctrl-x`, `alt-e`, `f5` or `shift-tab`; several
+//! keys separated by spaces form a sequence (`ctrl-x ctrl-e`). Actions are
+//! editor commands (`beginning-of-line`, `kill-word`, ...), `history-picker`,
+//! `insert:TEXT`, `run:COMMAND`, `ai-explain`, `ai-fix` and
+//! `edit-command-line`.
+
+use crate::prompt::ViMode;
+use anyhow::{anyhow, bail, Result};
+use rustyline::{
+    Anchor, At, Cmd, ConditionalEventHandler, Event, EventContext, InputMode, KeyCode, KeyEvent,
+    Modifiers, Movement, RepeatCount, Word,
+};
+use std::sync::{Arc, Mutex};
+
+/// What a bound key does
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// A line editor command
+    Editor(Cmd),
+    /// The fuzzy history picker
+    HistoryPicker,
+    /// Insert text at the cursor
+    Insert(String),
+    /// Run a command, then return to the line being edited
+    Run(String),
+    /// Ask the AI to explain the current line
+    AiExplain,
+    /// Ask the AI to correct the current line and edit the answer
+    AiFix,
+    /// Edit the current line in `ui.editor`, then run what was saved
+    EditCommand,
+}
+
+impl Action {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if let Some(text) = spec.strip_prefix("insert:") {
+            return Ok(Self::Insert(text.to_string()));
+        }
+        if let Some(command) = spec.strip_prefix("run:") {
+            if command.trim().is_empty() {
+                bail!("'run:' needs a command");
+            }
+            return Ok(Self::Run(command.trim().to_string()));
+        }
+        let cmd = match spec {
+            "history-picker" => return Ok(Self::HistoryPicker),
+            "ai-explain" => return Ok(Self::AiExplain),
+            "ai-fix" => return Ok(Self::AiFix),
+            "edit-command-line" => return Ok(Self::EditCommand),
+            "accept-line" => Cmd::AcceptLine,
+            "accept-hint" => Cmd::CompleteHint,
+            "abort" => Cmd::Abort,
+            "interrupt" => Cmd::Interrupt,
+            "noop" => Cmd::Noop,
+            "newline" => Cmd::Newline,
+            "clear-screen" => Cmd::ClearScreen,
+            "complete" => Cmd::Complete,
+            "beginning-of-line" => Cmd::Move(Movement::BeginningOfLine),
+            "end-of-line" => Cmd::Move(Movement::EndOfLine),
+            "backward-char" => Cmd::Move(Movement::BackwardChar(1)),
+            "forward-char" => Cmd::Move(Movement::ForwardChar(1)),
+            "backward-word" => Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+            "forward-word" => Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+            "backward-delete-char" => Cmd::Kill(Movement::BackwardChar(1)),
+            "delete-char" => Cmd::Kill(Movement::ForwardChar(1)),
+            "kill-line" => Cmd::Kill(Movement::EndOfLine),
+            "backward-kill-line" => Cmd::Kill(Movement::BeginningOfLine),
+            "kill-whole-line" => Cmd::Kill(Movement::WholeLine),
+            "kill-word" => Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+            "backward-kill-word" => Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+            "unix-word-rubout" => Cmd::Kill(Movement::BackwardWord(1, Word::Big)),
+            "yank" => Cmd::Yank(1, Anchor::Before),
+            "yank-pop" => Cmd::YankPop,
+            "undo" => Cmd::Undo(1),
+            "transpose-chars" => Cmd::TransposeChars,
+            "transpose-words" => Cmd::TransposeWords(1),
+            "upcase-word" => Cmd::UpcaseWord,
+            "downcase-word" => Cmd::DowncaseWord,
+            "capitalize-word" => Cmd::CapitalizeWord,
+            "previous-history" => Cmd::PreviousHistory,
+            "next-history" => Cmd::NextHistory,
+            "beginning-of-history" => Cmd::BeginningOfHistory,
+            "end-of-history" => Cmd::EndOfHistory,
+            "history-search-backward" => Cmd::HistorySearchBackward,
+            "history-search-forward" => Cmd::HistorySearchForward,
+            "reverse-search-history" => Cmd::ReverseSearchHistory,
+            "forward-search-history" => Cmd::ForwardSearchHistory,
+            _ => bail!("unknown action '{}'", spec),
+        };
+        Ok(Self::Editor(cmd))
+    }
+
+    /// Whether the action runs after the editor returns instead of inside it
+    pub fn is_deferred(&self) -> bool {
+        matches!(
+            self,
+            Self::Run(_) | Self::AiExplain | Self::AiFix | Self::EditCommand
+        )
+    }
+}
+
+/// A key sequence such as `ctrl-x ctrl-e`
+pub fn parse_keys(spec: &str) -> Result<Vec<KeyEvent>> {
+    let keys = spec
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        bail!("empty key");
+    }
+    Ok(keys)
+}
+
+fn parse_key(spec: &str) -> Result<KeyEvent> {
+    let mut mods = Modifiers::NONE;
+    let mut rest = spec;
+    // A lone "-" is the key itself, not a separator
+    while let Some((prefix, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+        mods |= match prefix.to_ascii_lowercase().as_str() {
+            "ctrl" | "c" => Modifiers::CTRL,
+            "alt" | "meta" | "m" => Modifiers::ALT,
+            "shift" | "s" => Modifiers::SHIFT,
+            _ => break,
+        };
+        rest = key;
+    }
+
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyEvent::normalize(KeyEvent::new(c, mods)));
+    }
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "tab" => KeyCode::Tab,
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n @ 1..=24) => KeyCode::F(n),
+            _ => return Err(anyhow!("unknown key '{}'", spec)),
+        },
+    };
+    Ok(KeyEvent::normalize(KeyEvent(code, mods)))
+}
+
+/// One entry of the `[keybindings]` table
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub keys: Vec<KeyEvent>,
+    pub action: Action,
+}
+
+/// One `keys = "action"` entry
+pub fn parse_binding(keys: &str, action: &str) -> Result<Binding> {
+    Ok(Binding {
+        keys: parse_keys(keys)?,
+        action: Action::parse(action)?,
+    })
+}
+
+/// A deferred action together with the line it was triggered on
+#[derive(Debug, Clone)]
+pub struct Deferred {
+    pub action: Action,
+    pub line: String,
+}
+
+/// Filled by `DeferredHandler`, emptied by the REPL once readline returns
+pub type DeferredSlot = Arc<Mutex<Option<Deferred>>>;
+
+/// Ends the edit so the REPL can run the action with the terminal back in
+/// normal mode; the REPL restores the line afterwards
+pub struct DeferredHandler {
+    pub action: Action,
+    pub slot: DeferredSlot,
+}
+
+impl ConditionalEventHandler for DeferredHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+        *slot = Some(Deferred {
+            action: self.action.clone(),
+            line: ctx.line().to_string(),
+        });
+        Some(Cmd::AcceptLine)
+    }
+}
+
+/// Follows vi insert/normal mode for the prompt's `{mode}` segment
+///
+/// The editor has no hook for mode changes, so this watches every unbound
+/// key and predicts the mode it leads to, then lets the key through.
+pub struct ViModeTracker {
+    pub mode: Arc<Mutex<ViMode>>,
+}
+
+impl ConditionalEventHandler for ViModeTracker {
+    fn handle(
+        &self,
+        evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        if let Some(key) = evt.get(0) {
+            let next = next_vi_mode(ctx.input_mode(), key);
+            *self.mode.lock().unwrap_or_else(|e| e.into_inner()) = next;
+        }
+        None
+    }
+}
+
+fn next_vi_mode(current: InputMode, key: &KeyEvent) -> ViMode {
+    match (current, key) {
+        (InputMode::Command, KeyEvent(KeyCode::Char(c), Modifiers::NONE))
+            if "iaIAsScCoOR".contains(*c) =>
+        {
+            ViMode::Insert
+        }
+        (InputMode::Command, _) => ViMode::Normal,
+        (_, KeyEvent(KeyCode::Esc, _)) => ViMode::Normal,
+        _ => ViMode::Insert,
+    }
+}
+
+/// What `ai-explain` asks
+pub fn explain_query(line: &str) -> String {
+    format!("Explain what this shell command does:\n{}", line)
+}
+
+/// What `ai-fix` asks
+pub fn fix_query(line: &str) -> String {
+    format!(
+        "This shell command is wrong or fails. Reply with only the corrected \
+         command on one line, no explanation, no markdown:\n{}",
+        line
+    )
+}
+
+/// The corrected command in an `ai-fix` reply
+pub fn extract_fixed_command(reply: &str) -> Option<String> {
+    let line = reply
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("```"))?;
+    let line = line.trim_matches('`');
+    let line = line.strip_prefix("$ ").unwrap_or(line).trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("ctrl-x").unwrap(), vec![KeyEvent::ctrl('X')]);
+        assert_eq!(parse_keys("Alt-e").unwrap(), vec![KeyEvent::alt('e')]);
+        assert_eq!(
+            parse_keys("ctrl-x ctrl-e").unwrap(),
+            vec![KeyEvent::ctrl('X'), KeyEvent::ctrl('E')]
+        );
+        assert_eq!(
+            parse_keys("shift-tab").unwrap(),
+            vec![KeyEvent(KeyCode::BackTab, Modifiers::NONE)]
+        );
+        assert_eq!(
+            parse_keys("f5").unwrap(),
+            vec![KeyEvent(KeyCode::F(5), Modifiers::NONE)]
+        );
+        assert_eq!(
+            parse_keys("alt--").unwrap(),
+            vec![KeyEvent(KeyCode::Char('-'), Modifiers::ALT)]
+        );
+        for bad in ["", "ctrl-", "hyper-x", "f99", "ctrl-nope"] {
+            assert!(parse_keys(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        assert_eq!(
+            Action::parse("kill-word").unwrap(),
+            Action::Editor(Cmd::Kill(Movement::ForwardWord(
+                1,
+                At::AfterEnd,
+                Word::Emacs
+            )))
+        );
+        assert_eq!(
+            Action::parse("insert: | less").unwrap(),
+            Action::Insert(" | less".to_string())
+        );
+        assert_eq!(
+            Action::parse("run: git status").unwrap(),
+            Action::Run("git status".to_string())
+        );
+        assert!(Action::parse("ai-fix").unwrap().is_deferred());
+        assert!(Action::parse("edit-command-line").unwrap().is_deferred());
+        assert!(!Action::parse("history-picker").unwrap().is_deferred());
+        assert!(Action::parse("run:").is_err());
+        assert!(Action::parse("launch-rockets").is_err());
+    }
+
+    #[test]
+    fn test_vi_mode_prediction() {
+        let key = |c| KeyEvent(KeyCode::Char(c), Modifiers::NONE);
+        let esc = KeyEvent(KeyCode::Esc, Modifiers::NONE);
+        assert_eq!(next_vi_mode(InputMode::Insert, &esc), ViMode::Normal);
+        assert_eq!(next_vi_mode(InputMode::Insert, &key('x')), ViMode::Insert);
+        assert_eq!(next_vi_mode(InputMode::Command, &key('A')), ViMode::Insert);
+        assert_eq!(next_vi_mode(InputMode::Command, &key('w')), ViMode::Normal);
+    }
+
+    #[test]
+    fn test_extract_fixed_command() {
+        assert_eq!(
+            extract_fixed_command("```bash\n$ git push -u origin main\n```"),
+            Some("git push -u origin main".to_string())
+        );
+        assert_eq!(
+            extract_fixed_command("`ls -la`"),
+            Some("ls -la".to_string())
+        );
+        assert_eq!(extract_fixed_command("\n\n"), None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Completion module for Fool Shell
+//! Works out what the word under the cursor is and offers matching commands,
+//! variable names, spec-driven arguments, directories or files
+
+use crate::compspec::SpecSet;
+use crate::config::{expand_path, CompletionConfig};
+use crate::executor::BuiltinCommand;
+use crate::extcomp::ExternalProviders;
+use rustyline::completion::{FilenameCompleter, Pair};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// What the word under the cursor is
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordKind {
+    /// The program of a pipeline stage
+    Command,
+    /// A `$NAME` or `${NAME` reference
+    Variable { braced: bool },
+    /// The argument of `cd`
+    Directory,
+    /// Any other argument
+    Argument,
+}
+
+/// Find where the word under the cursor starts and what it is
+pub fn classify(line: &str, pos: usize) -> (usize, WordKind) {
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || matches!(c, '|' | '<' | '>'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &before[start..];
+
+    if let Some(dollar) = word.rfind('$') {
+        let name = &word[dollar + 1..];
+        let (braced, name) = match name.strip_prefix('{') {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            let name_start = start + dollar + 1 + usize::from(braced);
+            return (name_start, WordKind::Variable { braced });
+        }
+    }
+
+    let kind = match stage_words(&before[..start])[..] {
+        [] => WordKind::Command,
+        ["cd"] => WordKind::Directory,
+        _ => WordKind::Argument,
+    };
+    (start, kind)
+}
+
+/// Words of the last command in `before`, after any `|` or `&&`
+fn stage_words(before: &str) -> Vec<&str> {
+    let start = [
+        before.rfind('|').map(|i| i + 1),
+        before.rfind("&&").map(|i| i + 2),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(0);
+    before[start..].split_whitespace().collect()
+}
+
+/// Position-aware completion for shell lines
+pub struct ShellCompleter {
+    files: FilenameCompleter,
+    specs: SpecSet,
+    external: ExternalProviders,
+    aliases: Vec<String>,
+    // Executables on PATH, rescanned when PATH changes or gains a program
+    path_cache: Mutex<Option<(OsString, Arc<BTreeSet<String>>)>>,
+}
+
+impl ShellCompleter {
+    pub fn new(config: &CompletionConfig) -> Self {
+        Self {
+            files: FilenameCompleter::new(),
+            specs: SpecSet::load(config),
+            external: ExternalProviders::new(config),
+            aliases: Vec::new(),
+            path_cache: Mutex::new(None),
+        }
+    }
+
+    /// Alias names change with the `alias` builtin, so they're refreshed per prompt
+    pub fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.aliases = aliases;
+    }
+
+    /// Complete the word ending at `pos`
+    pub fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, kind) = classify(line, pos);
+        let word = &line[start..pos];
+        match kind {
+            // Paths such as ./build.sh run as they are
+            WordKind::Command if !word.contains('/') => Ok((start, self.commands(word))),
+            WordKind::Variable { braced } => Ok((start, variables(word, braced))),
+            WordKind::Directory => self.directories(line, pos),
+            WordKind::Argument => self.arguments(line, start, pos),
+            WordKind::Command => self.files.complete_path(line, pos),
+        }
+    }
+
+    /// Arguments from a command's own provider or completion spec; file
+    /// names otherwise
+    fn arguments(
+        &self,
+        line: &str,
+        start: usize,
+        pos: usize,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let word = &line[start..pos];
+        let words = stage_words(&line[..start]);
+        let Some((command, args)) = words.split_first() else {
+            return self.files.complete_path(line, pos);
+        };
+        if let Some(words) = self.external.complete(command, args, word) {
+            return Ok((start, words.iter().map(|word| pair(word)).collect()));
+        }
+
+        let candidates = self.specs.complete(command, args, word);
+        let Some(candidates) = candidates else {
+            return self.files.complete_path(line, pos);
+        };
+
+        let start = start + candidates.offset;
+        let mut pairs: Vec<Pair> = candidates.words.iter().map(|word| pair(word)).collect();
+        if candidates.files || candidates.directories {
+            let (path_start, paths) = if candidates.files {
+                self.files.complete_path(line, pos)?
+            } else {
+                self.directories(line, pos)?
+            };
+            if pairs.is_empty() {
+                return Ok((path_start, paths));
+            }
+            // Both replace the same text unless the path needed quoting
+            if path_start == start {
+                pairs.extend(paths);
+            }
+        }
+        Ok((start, pairs))
+    }
+
+    fn directories(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, mut candidates) = self.files.complete_path(line, pos)?;
+        candidates.retain(|pair| pair.replacement.ends_with(std::path::MAIN_SEPARATOR));
+        Ok((start, candidates))
+    }
+
+    /// Complete a file name the way arguments are completed
+    pub fn complete_path(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<Pair>)> {
+        self.files.complete_path(line, pos)
+    }
+
+    /// Whether `word` names a builtin, alias or executable
+    pub fn is_command(&self, word: &str) -> bool {
+        let name = word.replace('\\', "");
+        if name.contains('/') {
+            return is_executable(&expand_path(&name));
+        }
+        BuiltinCommand::from_str(&name).is_some()
+            || self.aliases.contains(&name)
+            || self.path_commands().contains(&name)
+            || self.installed_since_scan(&name)
+    }
+
+    /// Whether `name` appeared on PATH after it was scanned, as it does when
+    /// a program is installed mid-session; the scan is then redone for
+    /// completion too
+    fn installed_since_scan(&self, name: &str) -> bool {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        if !on_path(&path, name) {
+            return false;
+        }
+        *self
+            .path_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        true
+    }
+
+    /// Builtins, aliases and PATH executables starting with `prefix`
+    fn commands(&self, prefix: &str) -> Vec<Pair> {
+        let path_commands = self.path_commands();
+        let mut names: BTreeSet<&str> = BuiltinCommand::NAMES.iter().copied().collect();
+        names.extend(self.aliases.iter().map(String::as_str));
+        names.extend(path_commands.iter().map(String::as_str));
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(pair)
+            .collect()
+    }
+
+    fn path_commands(&self) -> Arc<BTreeSet<String>> {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut cache = self
+            .path_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match cache.as_ref() {
+            Some((cached, commands)) if *cached == path => Arc::clone(commands),
+            _ => {
+                let commands = Arc::new(scan_path(&path));
+                *cache = Some((path, Arc::clone(&commands)));
+                commands
+            }
+        }
+    }
+}
+
+/// Names of the executables in the directories of `path`
+pub fn scan_path(path: &OsString) -> BTreeSet<String> {
+    std::env::split_paths(path)
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Whether a directory of `path` holds an executable called `name`
+pub fn on_path(path: &OsString, name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains('/')
+        && std::env::split_paths(path).any(|dir| is_executable(&dir.join(name)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    // Follows symlinks, as most of /usr/bin is
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Environment variable names starting with `prefix`
+fn variables(prefix: &str, braced: bool) -> Vec<Pair> {
+    let names: BTreeSet<String> = std::env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names
+        .into_iter()
+        .map(|name| Pair {
+            replacement: if braced {
+                format!("{}}}", name)
+            } else {
+                name.clone()
+            },
+            display: name,
+        })
+        .collect()
+}
+
+fn pair(name: &str) -> Pair {
+    Pair {
+        display: name.to_string(),
+        replacement: name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("car", 3), (0, WordKind::Command));
+        assert_eq!(classify("  ls -la", 4), (2, WordKind::Command));
+        assert_eq!(classify("ls -la | gr", 11), (9, WordKind::Command));
+        assert_eq!(classify("ls|gr", 5), (3, WordKind::Command));
+        assert_eq!(classify("make && ma", 10), (8, WordKind::Command));
+        assert_eq!(classify("cd x &&\n  cd sr", 15), (13, WordKind::Directory));
+        assert_eq!(classify("ls ", 3), (3, WordKind::Argument));
+        assert_eq!(classify("cat src/ma", 10), (4, WordKind::Argument));
+        assert_eq!(classify("cd sr", 5), (3, WordKind::Directory));
+        assert_eq!(classify("cd src ta", 9), (7, WordKind::Argument));
+        assert_eq!(
+            classify("echo $HO", 8),
+            (6, WordKind::Variable { braced: false })
+        );
+        assert_eq!(
+            classify("echo ${HO", 9),
+            (7, WordKind::Variable { braced: true })
+        );
+        assert_eq!(classify("cat $HOME/fi", 12), (4, WordKind::Argument));
+    }
+
+    #[test]
+    fn test_spec_arguments() {
+        let completer = ShellCompleter::new(&CompletionConfig::default());
+        let (start, pairs) = completer.complete("ls | cargo bu", 13).unwrap();
+        assert_eq!(start, 11);
+        assert_eq!(pairs[0].replacement, "build");
+        let (start, pairs) = completer.complete("cargo run --profile=rel", 23).unwrap();
+        assert_eq!(start, 20);
+        assert_eq!(pairs[0].replacement, "release");
+    }
+
+    #[test]
+    fn test_commands_include_builtins_and_aliases() {
+        let mut completer = ShellCompleter::new(&CompletionConfig::default());
+        completer.set_aliases(vec!["ll".to_string(), "histogram".to_string()]);
+        let names: Vec<String> = completer
+            .commands("hist")
+            .into_iter()
+            .map(|pair| pair.replacement)
+            .collect();
+        assert!(names.contains(&"history".to_string()));
+        assert!(names.contains(&"histogram".to_string()));
+        assert!(names.iter().all(|name| name.starts_with("hist")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("mytool");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        std::fs::create_dir(dir.path().join("subdir")).unwrap();
+
+        let path = dir.path().as_os_str().to_owned();
+        let commands = scan_path(&path);
+        assert_eq!(commands.into_iter().collect::<Vec<_>>(), ["mytool"]);
+
+        // Installed after the scan: found by looking it up directly
+        let newer = dir.path().join("newtool");
+        std::fs::write(&newer, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&newer, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(on_path(&path, "newtool"));
+        assert!(!on_path(&path, "notes.txt"));
+        assert!(!on_path(&path, "subdir"));
+        assert!(!on_path(&path, ""));
+    }
+}
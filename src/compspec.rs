@@ -0,0 +1,792 @@
+//! Completion spec module for Fool Shell
+//! Declarative descriptions of a command's subcommands, flags and argument
+//! values, built in for common tools and loadable from TOML files
+//!
+//! A spec lists `flags` (no value), `options` (followed by a value),
+//! `subcommands` (nested specs) and `args` (positional values). Values come
+//! from a fixed list, the output lines of a shell command, or a provider
+//! such as `ssh-hosts` or `files`; `{}` is a value with nothing to offer.
+
+use crate::config::{CompletionConfig, Config};
+use crate::extcomp;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Specs shipped with the shell; a file in `completions_dir()` replaces one
+pub const BUILTIN_SPECS: &[(&str, &str)] = &[
+    ("git", GIT),
+    ("cargo", CARGO),
+    ("make", MAKE),
+    ("ssh", SSH),
+    ("scp", SCP),
+    ("docker", DOCKER),
+];
+
+const GIT: &str = r#"
+flags = ["--version", "--help", "--no-pager", "-p", "--paginate"]
+options = { "-C" = { providers = ["directories"] }, "-c" = {} }
+
+[subcommands.add]
+flags = ["-A", "--all", "-p", "--patch", "-u", "--update", "-n", "--dry-run", "-f", "--force", "-i", "--interactive"]
+
+[subcommands.commit]
+flags = ["-a", "--all", "--amend", "--no-edit", "-p", "--patch", "-s", "--signoff", "-v", "--verbose", "--no-verify", "--fixup"]
+options = { "-m" = {}, "--message" = {}, "-F" = { providers = ["files"] }, "--file" = { providers = ["files"] } }
+
+[subcommands.checkout]
+flags = ["-b", "-B", "-f", "--force", "-p", "--patch", "--detach", "-t", "--track"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/tags", providers = ["files"] }
+
+[subcommands.switch]
+flags = ["-c", "--create", "-C", "--force-create", "-d", "--detach", "-t", "--track", "--discard-changes"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads" }
+
+[subcommands.branch]
+flags = ["-a", "--all", "-r", "--remotes", "-d", "--delete", "-D", "-m", "--move", "-M", "-v", "-vv", "--merged", "--no-merged", "--show-current"]
+options = { "-u" = { command = "git for-each-ref --format='%(refname:short)' refs/remotes" }, "--set-upstream-to" = { command = "git for-each-ref --format='%(refname:short)' refs/remotes" } }
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads" }
+
+[subcommands.merge]
+flags = ["--no-ff", "--ff-only", "--squash", "--abort", "--continue", "--no-edit"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags" }
+
+[subcommands.rebase]
+flags = ["-i", "--interactive", "--continue", "--abort", "--skip", "--autosquash", "--onto"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags" }
+
+[subcommands.cherry-pick]
+flags = ["--continue", "--abort", "--skip", "-n", "--no-commit", "-x"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes" }
+
+[subcommands.push]
+flags = ["-f", "--force", "--force-with-lease", "-u", "--set-upstream", "--tags", "--all", "-n", "--dry-run", "--delete"]
+args = { command = "git remote; git for-each-ref --format='%(refname:short)' refs/heads" }
+
+[subcommands.pull]
+flags = ["--rebase", "--no-rebase", "--ff-only", "--all", "--tags"]
+args = { command = "git remote; git for-each-ref --format='%(refname:short)' refs/heads" }
+
+[subcommands.fetch]
+flags = ["--all", "-p", "--prune", "--tags", "--unshallow"]
+args = { command = "git remote" }
+
+[subcommands.remote]
+flags = ["-v", "--verbose"]
+args = { command = "git remote" }
+subcommands = { add = {}, remove = { args = { command = "git remote" } }, rename = { args = { command = "git remote" } }, set-url = { args = { command = "git remote" } }, get-url = { args = { command = "git remote" } }, show = { args = { command = "git remote" } }, prune = { args = { command = "git remote" } } }
+
+[subcommands.status]
+flags = ["-s", "--short", "-b", "--branch", "--ignored", "-u", "--untracked-files"]
+
+[subcommands.log]
+flags = ["--oneline", "--graph", "--all", "--decorate", "--stat", "-p", "--patch", "--follow", "--reverse"]
+options = { "-n" = {}, "--author" = {}, "--since" = {}, "--until" = {} }
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags", providers = ["files"] }
+
+[subcommands.diff]
+flags = ["--staged", "--cached", "--stat", "--name-only", "--name-status", "-w", "--word-diff"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags", providers = ["files"] }
+
+[subcommands.show]
+flags = ["--stat", "--name-only", "--oneline"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags" }
+
+[subcommands.reset]
+flags = ["--soft", "--mixed", "--hard", "--keep", "-p", "--patch"]
+args = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/remotes refs/tags", providers = ["files"] }
+
+[subcommands.restore]
+flags = ["-S", "--staged", "-W", "--worktree", "-p", "--patch"]
+options = { "-s" = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/tags" }, "--source" = { command = "git for-each-ref --format='%(refname:short)' refs/heads refs/tags" } }
+
+[subcommands.stash]
+flags = ["-u", "--include-untracked", "-k", "--keep-index"]
+subcommands = { push = {}, pop = {}, apply = {}, list = {}, show = {}, drop = {}, clear = {}, branch = {} }
+
+[subcommands.tag]
+flags = ["-a", "--annotate", "-d", "--delete", "-l", "--list", "-f", "--force", "-s", "--sign"]
+options = { "-m" = {} }
+args = { command = "git tag" }
+
+[subcommands.blame]
+[subcommands.clean]
+flags = ["-n", "--dry-run", "-f", "--force", "-d", "-x", "-X", "-i"]
+[subcommands.clone]
+flags = ["--depth", "--recursive", "--recurse-submodules", "--bare", "--mirror"]
+options = { "-b" = {}, "--branch" = {} }
+[subcommands.grep]
+flags = ["-i", "-n", "-w", "-l", "-e"]
+[subcommands.init]
+flags = ["--bare", "-q", "--quiet"]
+options = { "-b" = {}, "--initial-branch" = {} }
+[subcommands.mv]
+[subcommands.rm]
+flags = ["--cached", "-r", "-f", "--force", "-n", "--dry-run"]
+[subcommands.revert]
+flags = ["--no-edit", "-n", "--no-commit", "--continue", "--abort"]
+[subcommands.bisect]
+subcommands = { start = {}, good = {}, bad = {}, reset = {}, skip = {}, log = {}, run = {} }
+[subcommands.submodule]
+subcommands = { add = {}, init = {}, update = {}, status = {}, sync = {}, foreach = {} }
+[subcommands.worktree]
+subcommands = { add = {}, list = {}, remove = {}, prune = {}, move = {} }
+"#;
+
+const CARGO_BUILD_OPTIONS: &str = r#"
+flags = ["--release", "--all-targets", "--workspace", "--all-features", "--no-default-features", "--lib", "--bins", "--examples", "--tests", "--benches", "-q", "--quiet", "-v", "--verbose", "--locked", "--offline"]
+options = { "--bin" = { providers = ["cargo-bins"] }, "-p" = {}, "--package" = {}, "-F" = {}, "--features" = {}, "--example" = {}, "--test" = {}, "--target" = {}, "--profile" = { values = ["dev", "release", "test", "bench"] }, "-j" = {}, "--jobs" = {}, "--manifest-path" = { providers = ["files"] }, "--target-dir" = { providers = ["directories"] } }
+"#;
+
+const CARGO: &str = r#"
+flags = ["--version", "-V", "--list", "--help", "-h"]
+options = { "-C" = { providers = ["directories"] }, "--config" = {} }
+
+[subcommands]
+build = "@build"
+b = "@build"
+check = "@build"
+c = "@build"
+run = "@build"
+r = "@build"
+test = "@build"
+t = "@build"
+bench = "@build"
+clippy = "@build"
+doc = "@build"
+install = "@build"
+fix = "@build"
+
+[subcommands.fmt]
+flags = ["--all", "--check", "-v"]
+[subcommands.clean]
+flags = ["--release", "--doc"]
+[subcommands.new]
+flags = ["--bin", "--lib", "--vcs"]
+options = { "--name" = {}, "--edition" = { values = ["2015", "2018", "2021", "2024"] } }
+[subcommands.init]
+flags = ["--bin", "--lib"]
+options = { "--name" = {}, "--edition" = { values = ["2015", "2018", "2021", "2024"] } }
+[subcommands.add]
+flags = ["--dev", "--build", "--optional", "--no-default-features"]
+options = { "-F" = {}, "--features" = {} }
+args = {}
+[subcommands.remove]
+flags = ["--dev", "--build"]
+args = {}
+[subcommands.update]
+flags = ["--workspace", "--dry-run"]
+options = { "-p" = {}, "--package" = {} }
+[subcommands.tree]
+flags = ["-i", "--invert", "-d", "--duplicates", "-e"]
+[subcommands.uninstall]
+args = {}
+[subcommands.publish]
+flags = ["--dry-run", "--allow-dirty", "--no-verify"]
+[subcommands.search]
+args = {}
+[subcommands.metadata]
+flags = ["--no-deps", "--format-version"]
+[subcommands.vendor]
+[subcommands.login]
+[subcommands.package]
+flags = ["--list", "--allow-dirty", "--no-verify"]
+"#;
+
+const MAKE: &str = r#"
+flags = ["-B", "--always-make", "-n", "--dry-run", "-k", "--keep-going", "-s", "--silent", "-i", "-q", "--question", "-p"]
+options = { "-C" = { providers = ["directories"] }, "--directory" = { providers = ["directories"] }, "-f" = { providers = ["files"] }, "--file" = { providers = ["files"] }, "-j" = {}, "--jobs" = {} }
+args = { providers = ["make-targets"] }
+"#;
+
+const SSH: &str = r#"
+flags = ["-4", "-6", "-A", "-a", "-C", "-f", "-G", "-g", "-K", "-k", "-M", "-N", "-n", "-q", "-s", "-T", "-t", "-V", "-v", "-X", "-x", "-Y", "-y"]
+options = { "-i" = { providers = ["files"] }, "-F" = { providers = ["files"] }, "-J" = { providers = ["ssh-hosts"] }, "-p" = {}, "-l" = {}, "-L" = {}, "-R" = {}, "-D" = {}, "-o" = {}, "-E" = { providers = ["files"] }, "-W" = {} }
+args = { providers = ["ssh-hosts"] }
+"#;
+
+const SCP: &str = r#"
+flags = ["-3", "-4", "-6", "-A", "-B", "-C", "-O", "-p", "-q", "-R", "-r", "-T", "-v"]
+options = { "-i" = { providers = ["files"] }, "-F" = { providers = ["files"] }, "-J" = { providers = ["ssh-hosts"] }, "-P" = {}, "-l" = {}, "-o" = {}, "-c" = {} }
+args = { providers = ["ssh-hosts", "files"] }
+"#;
+
+const DOCKER: &str = r#"
+flags = ["--version", "--help", "-D", "--debug"]
+options = { "-H" = {}, "--context" = { command = "docker context ls --format '{{.Name}}'" } }
+
+[subcommands]
+ps = { flags = ["-a", "--all", "-q", "--quiet", "-s", "--size", "--no-trunc", "-l", "--latest"], options = { "-f" = {}, "--filter" = {}, "--format" = {}, "-n" = {} } }
+images = { flags = ["-a", "--all", "-q", "--quiet", "--digests", "--no-trunc"], options = { "-f" = {}, "--format" = {} } }
+build = { flags = ["--no-cache", "--pull", "-q", "--quiet", "--rm"], options = { "-t" = {}, "--tag" = {}, "-f" = { providers = ["files"] }, "--file" = { providers = ["files"] }, "--build-arg" = {}, "--target" = {}, "--platform" = {} }, args = { providers = ["directories"] } }
+pull = { flags = ["-a", "--all-tags", "-q"], args = { command = "docker images --format '{{.Repository}}:{{.Tag}}'" } }
+push = { args = { command = "docker images --format '{{.Repository}}:{{.Tag}}'" } }
+rmi = { flags = ["-f", "--force"], args = { command = "docker images --format '{{.Repository}}:{{.Tag}}'" } }
+tag = { args = { command = "docker images --format '{{.Repository}}:{{.Tag}}'" } }
+start = { flags = ["-a", "--attach", "-i", "--interactive"], args = { command = "docker ps -a --format '{{.Names}}'" } }
+rm = { flags = ["-f", "--force", "-v", "--volumes"], args = { command = "docker ps -a --format '{{.Names}}'" } }
+stop = { options = { "-t" = {} }, args = { command = "docker ps --format '{{.Names}}'" } }
+restart = { options = { "-t" = {} }, args = { command = "docker ps --format '{{.Names}}'" } }
+kill = { options = { "-s" = {} }, args = { command = "docker ps --format '{{.Names}}'" } }
+logs = { flags = ["-f", "--follow", "-t", "--timestamps"], options = { "--tail" = {}, "--since" = {} }, args = { command = "docker ps -a --format '{{.Names}}'" } }
+inspect = { options = { "-f" = {}, "--format" = {} }, args = { command = "docker ps -a --format '{{.Names}}'; docker images --format '{{.Repository}}:{{.Tag}}'" } }
+exec = { flags = ["-i", "-t", "-it", "-d", "--detach", "--privileged"], options = { "-e" = {}, "--env" = {}, "-u" = {}, "--user" = {}, "-w" = {}, "--workdir" = {} }, args = { command = "docker ps --format '{{.Names}}'" } }
+run = { flags = ["-i", "-t", "-it", "-d", "--detach", "--rm", "--privileged", "--init"], options = { "-e" = {}, "--env" = {}, "--env-file" = { providers = ["files"] }, "-p" = {}, "--publish" = {}, "-v" = {}, "--volume" = {}, "--name" = {}, "--network" = { command = "docker network ls --format '{{.Name}}'" }, "-w" = {}, "--workdir" = {}, "-u" = {}, "--user" = {}, "--entrypoint" = {}, "--platform" = {} }, args = { command = "docker images --format '{{.Repository}}:{{.Tag}}'" } }
+
+[subcommands.network]
+subcommands = { ls = {}, create = {}, inspect = { args = { command = "docker network ls --format '{{.Name}}'" } }, rm = { args = { command = "docker network ls --format '{{.Name}}'" } }, connect = { args = { command = "docker network ls --format '{{.Name}}'" } }, disconnect = { args = { command = "docker network ls --format '{{.Name}}'" } }, prune = {} }
+
+[subcommands.volume]
+subcommands = { ls = {}, create = {}, inspect = { args = { command = "docker volume ls --format '{{.Name}}'" } }, rm = { args = { command = "docker volume ls --format '{{.Name}}'" } }, prune = {} }
+
+[subcommands.compose]
+options = { "-f" = { providers = ["files"] }, "--file" = { providers = ["files"] }, "-p" = {}, "--project-name" = {}, "--profile" = {} }
+subcommands = { up = { flags = ["-d", "--detach", "--build", "--force-recreate", "--remove-orphans"], args = { command = "docker compose config --services" } }, down = { flags = ["-v", "--volumes", "--remove-orphans"] }, build = { flags = ["--no-cache", "--pull"], args = { command = "docker compose config --services" } }, logs = { flags = ["-f", "--follow"], args = { command = "docker compose config --services" } }, ps = { flags = ["-a", "--all", "-q"] }, exec = { args = { command = "docker compose config --services" } }, restart = { args = { command = "docker compose config --services" } }, pull = { args = { command = "docker compose config --services" } }, config = { flags = ["--services", "-q"] } }
+"#;
+
+/// How a command line is completed past its program name
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Spec {
+    /// Flags that take no value
+    pub flags: Vec<String>,
+    /// Flags followed by a value, such as `--bin NAME`
+    pub options: BTreeMap<String, Source>,
+    pub subcommands: BTreeMap<String, SubSpec>,
+    /// Positional arguments; file names when unset
+    pub args: Option<Source>,
+}
+
+/// A subcommand's spec, or `"@name"` to reuse the shared spec `name`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SubSpec {
+    Shared(String),
+    Spec(Spec),
+}
+
+/// Where the values for an option or argument come from
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Source {
+    pub values: Vec<String>,
+    /// Shell command whose output lines are candidates
+    pub command: Option<String>,
+    pub providers: Vec<Provider>,
+}
+
+/// Values that need more than a shell command to find
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    Files,
+    Directories,
+    /// `Host` entries of `~/.ssh/config`
+    SshHosts,
+    /// Binary targets of the Cargo package around the working directory
+    CargoBins,
+    /// Targets of the Makefile in the working directory
+    MakeTargets,
+}
+
+/// What to offer for the word under the cursor
+#[derive(Debug, Default, PartialEq)]
+pub struct Candidates {
+    /// Where the completed text starts within the word (after `--opt=`)
+    pub offset: usize,
+    pub words: BTreeSet<String>,
+    pub files: bool,
+    pub directories: bool,
+}
+
+impl Candidates {
+    fn add(&mut self, source: &Source, prefix: &str, timeout: Duration) {
+        let matching = |word: &String| word.starts_with(prefix);
+        self.words
+            .extend(source.values.iter().filter(|w| matching(w)).cloned());
+        if let Some(command) = &source.command {
+            self.words
+                .extend(command_lines(command, timeout).into_iter().filter(matching));
+        }
+        for provider in &source.providers {
+            let words = match provider {
+                Provider::Files => {
+                    self.files = true;
+                    continue;
+                }
+                Provider::Directories => {
+                    self.directories = true;
+                    continue;
+                }
+                Provider::SshHosts => ssh_hosts(),
+                Provider::CargoBins => std::env::current_dir()
+                    .map(|dir| cargo_bins(&dir))
+                    .unwrap_or_default(),
+                Provider::MakeTargets => make_targets(),
+            };
+            self.words.extend(words.into_iter().filter(matching));
+        }
+    }
+}
+
+/// Built-in specs plus the user's spec files, falling back to fish's
+pub struct SpecSet {
+    specs: HashMap<String, Spec>,
+    shared: HashMap<String, Spec>,
+    fish_dirs: Vec<PathBuf>,
+    // Parsed on first use; None when a command has no fish file
+    fish_specs: Mutex<HashMap<String, Option<Arc<Spec>>>>,
+    timeout: Duration,
+}
+
+impl SpecSet {
+    /// Built-in specs overridden by `~/.config/fool/completions/COMMAND.toml`
+    pub fn load(config: &CompletionConfig) -> Self {
+        let mut set = Self::builtin();
+        set.fish_dirs = extcomp::fish_dirs(config);
+        set.timeout = Duration::from_millis(config.timeout_ms);
+        let Ok(entries) = std::fs::read_dir(completions_dir()) else {
+            return set;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(command) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match Self::read_spec(&path) {
+                Ok(spec) => {
+                    set.specs.insert(command.to_string(), spec);
+                }
+                Err(e) => eprintln!("Warning: {:#}, ignoring it", e),
+            }
+        }
+        set
+    }
+
+    pub fn builtin() -> Self {
+        let mut set = Self {
+            specs: HashMap::new(),
+            shared: HashMap::new(),
+            fish_dirs: Vec::new(),
+            fish_specs: Mutex::new(HashMap::new()),
+            timeout: Duration::from_millis(CompletionConfig::default().timeout_ms),
+        };
+        for (command, source) in BUILTIN_SPECS {
+            let spec = toml::from_str(source).expect("built-in completion specs are valid");
+            set.specs.insert(command.to_string(), spec);
+        }
+        let build =
+            toml::from_str(CARGO_BUILD_OPTIONS).expect("built-in completion specs are valid");
+        set.shared.insert("build".to_string(), build);
+        set
+    }
+
+    fn read_spec(path: &Path) -> Result<Spec> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read completion spec: {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Invalid completion spec {:?}", path))
+    }
+
+    /// Candidates for `word`, which follows `args` on a `command` line
+    pub fn complete(&self, command: &str, args: &[&str], word: &str) -> Option<Candidates> {
+        match self.specs.get(command) {
+            Some(spec) => self.walk(spec, args, word),
+            None => self.walk(&*self.fish_spec(command)?, args, word),
+        }
+    }
+
+    fn fish_spec(&self, command: &str) -> Option<Arc<Spec>> {
+        let cached = {
+            let fish_specs = self
+                .fish_specs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            fish_specs.get(command).cloned()
+        };
+        if let Some(spec) = cached {
+            return spec;
+        }
+        let spec = extcomp::find_fish_file(&self.fish_dirs, command)
+            .and_then(|path| extcomp::load_fish_spec(&path, command));
+        match spec {
+            Some(spec) => Some(self.add_fish(command, spec)),
+            None => {
+                let mut fish_specs = self
+                    .fish_specs
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                fish_specs.insert(command.to_string(), None);
+                None
+            }
+        }
+    }
+
+    /// Remember the spec read from `command`'s fish file
+    pub fn add_fish(&self, command: &str, spec: Spec) -> Arc<Spec> {
+        let spec = Arc::new(spec);
+        let mut fish_specs = self
+            .fish_specs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        fish_specs.insert(command.to_string(), Some(Arc::clone(&spec)));
+        spec
+    }
+
+    fn walk<'a>(&'a self, mut spec: &'a Spec, args: &[&str], word: &str) -> Option<Candidates> {
+        let mut positional = false;
+        let mut value_of: Option<&Source> = None;
+        for arg in args {
+            if value_of.take().is_some() {
+                continue;
+            }
+            if let Some(source) = spec.options.get(*arg) {
+                value_of = Some(source);
+            } else if arg.starts_with('-') {
+                // Unknown flags and `--opt=value` are skipped
+            } else if let Some(sub) = spec.subcommands.get(*arg).filter(|_| !positional) {
+                spec = self.resolve(sub)?;
+            } else {
+                positional = true;
+            }
+        }
+
+        let mut candidates = Candidates::default();
+        if let Some(source) = value_of {
+            candidates.add(source, word, self.timeout);
+        } else if word.starts_with('-') {
+            let option_value = word
+                .split_once('=')
+                .and_then(|(name, value)| Some((spec.options.get(name)?, value)));
+            if let Some((source, value)) = option_value {
+                candidates.offset = word.len() - value.len();
+                candidates.add(source, value, self.timeout);
+            } else {
+                candidates.words = spec
+                    .flags
+                    .iter()
+                    .chain(spec.options.keys())
+                    .filter(|flag| flag.starts_with(word))
+                    .cloned()
+                    .collect();
+            }
+        } else {
+            if !positional {
+                let names = spec.subcommands.keys();
+                candidates
+                    .words
+                    .extend(names.filter(|name| name.starts_with(word)).cloned());
+            }
+            match &spec.args {
+                Some(source) => candidates.add(source, word, self.timeout),
+                // Commands without subcommands take files by default
+                None => candidates.files = spec.subcommands.is_empty() || positional,
+            }
+        }
+        Some(candidates)
+    }
+
+    fn resolve<'a>(&'a self, sub: &'a SubSpec) -> Option<&'a Spec> {
+        match sub {
+            SubSpec::Spec(spec) => Some(spec),
+            SubSpec::Shared(name) => self.shared.get(name.strip_prefix('@')?),
+        }
+    }
+}
+
+/// Where user completion specs live
+pub fn completions_dir() -> PathBuf {
+    Config::default_path()
+        .parent()
+        .map(|dir| dir.join("completions"))
+        .unwrap_or_else(|| PathBuf::from("completions"))
+}
+
+/// Output lines of `sh -c command`, or nothing if it fails or takes too long
+pub fn command_lines(command: &str, timeout: Duration) -> Vec<String> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    output_lines(cmd, timeout)
+}
+
+/// Non-empty output lines of `cmd`, or nothing if it fails or takes too long
+pub fn output_lines(mut cmd: Command, timeout: Duration) -> Vec<String> {
+    let child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return Vec::new();
+    };
+
+    // Read on another thread so a hung command can't block the editor
+    let deadline = Instant::now() + timeout;
+    let stdout = child.stdout.take();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut stdout) = stdout {
+            let _ = stdout.read_to_string(&mut output);
+        }
+        let _ = tx.send(output);
+    });
+
+    let output = rx.recv_timeout(timeout).ok();
+    // Closing stdout doesn't mean it exits; the wait shares the timeout
+    let exited = output.is_some() && wait_until(&mut child, deadline);
+    if !exited {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Vec::new();
+    }
+    output
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Wait for `child` until `deadline`; false if it is still running
+fn wait_until(child: &mut Child, deadline: Instant) -> bool {
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => return true,
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn ssh_hosts() -> Vec<String> {
+    dirs::home_dir()
+        .and_then(|home| std::fs::read_to_string(home.join(".ssh").join("config")).ok())
+        .map(|content| parse_ssh_hosts(&content))
+        .unwrap_or_default()
+}
+
+/// Concrete names from `Host` lines; patterns and negations are skipped
+pub fn parse_ssh_hosts(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (keyword, rest) = line.split_once(|c: char| c.is_whitespace() || c == '=')?;
+            keyword.eq_ignore_ascii_case("host").then_some(rest)
+        })
+        .flat_map(|rest| rest.split_whitespace())
+        .filter(|host| !host.contains(['*', '?', '!']))
+        .map(String::from)
+        .collect()
+}
+
+/// Binary targets of the package whose manifest is at or above `dir`
+pub fn cargo_bins(dir: &Path) -> Vec<String> {
+    let Some(root) = dir.ancestors().find(|d| d.join("Cargo.toml").is_file()) else {
+        return Vec::new();
+    };
+    let Some(manifest) = std::fs::read_to_string(root.join("Cargo.toml"))
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut bins = BTreeSet::new();
+    if let Some(targets) = manifest.get("bin").and_then(|b| b.as_array()) {
+        bins.extend(
+            targets
+                .iter()
+                .filter_map(|t| t.get("name")?.as_str())
+                .map(String::from),
+        );
+    }
+    let package = manifest
+        .get("package")
+        .and_then(|p| p.get("name"))
+        .and_then(|n| n.as_str());
+    if let Some(package) = package.filter(|_| root.join("src/main.rs").is_file()) {
+        bins.insert(package.to_string());
+    }
+    if let Ok(entries) = std::fs::read_dir(root.join("src/bin")) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let is_bin =
+                path.extension().is_some_and(|e| e == "rs") || path.join("main.rs").is_file();
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()).filter(|_| is_bin) {
+                bins.insert(name.to_string());
+            }
+        }
+    }
+    bins.into_iter().collect()
+}
+
+fn make_targets() -> Vec<String> {
+    ["GNUmakefile", "makefile", "Makefile"]
+        .iter()
+        .find_map(|name| std::fs::read_to_string(name).ok())
+        .map(|content| parse_make_targets(&content))
+        .unwrap_or_default()
+}
+
+/// Explicit targets of a Makefile; variables, patterns and special targets are skipped
+pub fn parse_make_targets(content: &str) -> Vec<String> {
+    let mut targets = BTreeSet::new();
+    for line in content.lines() {
+        // Recipe lines start with a tab
+        if line.starts_with(['\t', ' ', '#']) {
+            continue;
+        }
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // `:=` and `::=` are assignments
+        if rest.starts_with('=') || rest.starts_with(":=") || names.contains(['=', '$']) {
+            continue;
+        }
+        targets.extend(
+            names
+                .split_whitespace()
+                .filter(|name| !name.starts_with('.') && !name.contains('%'))
+                .map(String::from),
+        );
+    }
+    targets.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(candidates: Option<Candidates>) -> Vec<String> {
+        candidates.unwrap().words.into_iter().collect()
+    }
+
+    #[test]
+    fn test_builtin_specs_parse() {
+        let set = SpecSet::builtin();
+        for (command, _) in BUILTIN_SPECS {
+            assert!(set.specs.contains_key(*command));
+        }
+        // Every shared reference resolves
+        for spec in set.specs.values() {
+            for sub in spec.subcommands.values() {
+                assert!(set.resolve(sub).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_spec_walk() {
+        let set = SpecSet::builtin();
+        assert_eq!(words(set.complete("cargo", &[], "bu")), ["build"]);
+        assert_eq!(
+            words(set.complete("cargo", &["build"], "--rel")),
+            ["--release"]
+        );
+        assert_eq!(
+            words(set.complete("cargo", &["test", "--profile"], "re")),
+            ["release"]
+        );
+        let candidates = set.complete("cargo", &["b"], "--profile=de").unwrap();
+        assert_eq!(candidates.offset, "--profile=".len());
+        assert_eq!(candidates.words.into_iter().collect::<Vec<_>>(), ["dev"]);
+
+        // Subcommands only count before the first positional argument
+        assert_eq!(words(set.complete("git", &["stash"], "p")), ["pop", "push"]);
+        let files = set.complete("git", &["add"], "src").unwrap();
+        assert!(files.files && files.words.is_empty());
+        assert!(set.complete("ls", &[], "").is_none());
+    }
+
+    #[test]
+    fn test_user_spec() {
+        let mut set = SpecSet::builtin();
+        let spec: Spec = toml::from_str(
+            r#"
+flags = ["--verbose"]
+[subcommands.deploy]
+options = { "--env" = { values = ["staging", "production"] } }
+args = { values = ["web", "worker"] }
+"#,
+        )
+        .unwrap();
+        set.specs.insert("tool".to_string(), spec);
+        assert_eq!(words(set.complete("tool", &[], "")), ["deploy"]);
+        assert_eq!(
+            words(set.complete("tool", &["deploy", "--env"], "p")),
+            ["production"]
+        );
+        assert_eq!(
+            words(set.complete("tool", &["deploy", "web"], "w")),
+            ["web", "worker"]
+        );
+        assert!(toml::from_str::<Spec>("flag = []").is_err());
+    }
+
+    #[test]
+    fn test_command_source() {
+        let mut candidates = Candidates::default();
+        let source = Source {
+            command: Some("printf 'alpha\\nbeta\\n'".to_string()),
+            ..Source::default()
+        };
+        candidates.add(&source, "a", Duration::from_secs(5));
+        assert_eq!(candidates.words.into_iter().collect::<Vec<_>>(), ["alpha"]);
+        assert!(command_lines("sleep 5", Duration::from_millis(50)).is_empty());
+    }
+
+    #[test]
+    fn test_parse_ssh_hosts() {
+        let config = "Host *\n  User me\nHost web db.internal\n  Port 22\nhost=bastion\nHost !old *.corp\nMatch host foo\n";
+        assert_eq!(parse_ssh_hosts(config), ["web", "db.internal", "bastion"]);
+    }
+
+    #[test]
+    fn test_parse_make_targets() {
+        let makefile = ".PHONY: all test\nCC := gcc\nall: build test\n\t$(CC) -o x\nbuild test:\n%.o: %.c\nVERSION ::= 1\n# note: x\n";
+        assert_eq!(parse_make_targets(makefile), ["all", "build", "test"]);
+    }
+
+    #[test]
+    fn test_cargo_bins() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\n\n[[bin]]\nname = \"tool\"\npath = \"tools/tool.rs\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("src/bin/server")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src/bin/migrate.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src/bin/server/main.rs"), "").unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+
+        assert_eq!(
+            cargo_bins(&dir.path().join("nested")),
+            ["app", "migrate", "server", "tool"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_lines_timeout() {
+        let timeout = Duration::from_millis(200);
+        assert_eq!(command_lines("echo a; echo; echo b", timeout), ["a", "b"]);
+
+        // Output finished but the command lingers: still bounded
+        let started = Instant::now();
+        assert!(command_lines("echo a; exec >&-; sleep 5", timeout).is_empty());
+        assert!(command_lines("sleep 5", timeout).is_empty());
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+}
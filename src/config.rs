@@ -3,9 +3,10 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::fs::OpenOptionsExt;
@@ -13,10 +14,46 @@ use std::os::unix::fs::OpenOptionsExt;
 /// UI configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiConfig {
+    /// Built-in theme, a theme in the themes directory, or a `.toml` path
     #[serde(default = "default_theme")]
     pub theme: String,
+    #[serde(default)]
+    pub color: ColorMode,
+    #[serde(default)]
+    pub edit_mode: EditMode,
     #[serde(default = "default_editor")]
     pub editor: String,
+    /// Prompt template, see `prompt.rs` for the syntax
+    #[serde(default = "default_prompt_template")]
+    pub prompt: String,
+    /// Template shown at the right edge of the prompt line; empty for none
+    #[serde(default)]
+    pub right_prompt: String,
+}
+
+/// Which colours to use; "auto" detects them from the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// `NO_COLOR`, `COLORTERM` and `TERM` decide
+    #[default]
+    Auto,
+    Never,
+    /// The 16 named ANSI colours
+    #[serde(rename = "16")]
+    Basic,
+    #[serde(rename = "256")]
+    Ansi256,
+    Truecolor,
+}
+
+/// Key layout of the line editor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
 }
 
 fn default_theme() -> String {
@@ -27,11 +64,191 @@ fn default_editor() -> String {
     std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string())
 }
 
+fn default_prompt_template() -> String {
+    crate::prompt::DEFAULT_TEMPLATE.to_string()
+}
+
 impl Default for UiConfig {
     fn default() -> Self {
         Self {
             theme: default_theme(),
+            color: ColorMode::default(),
+            edit_mode: EditMode::default(),
             editor: default_editor(),
+            prompt: default_prompt_template(),
+            right_prompt: String::new(),
+        }
+    }
+}
+
+/// Prompt configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptConfig {
+    /// Show how long the previous command took...
+    #[serde(default = "default_true")]
+    pub show_duration: bool,
+    /// ...when it ran for at least this long
+    #[serde(default = "default_duration_threshold_ms")]
+    pub duration_threshold_ms: u64,
+    /// `{mode}` in vi insert mode
+    #[serde(default = "default_vi_insert_indicator")]
+    pub vi_insert_indicator: String,
+    /// `{mode}` in vi normal mode
+    #[serde(default = "default_vi_normal_indicator")]
+    pub vi_normal_indicator: String,
+    /// Redraw the prompt of an accepted line as `transient_prompt`
+    #[serde(default)]
+    pub transient: bool,
+    /// Template for prompts left in the scrollback, see `prompt.rs`
+    #[serde(default = "default_transient_prompt")]
+    pub transient_prompt: String,
+    /// Draw a line with the exit code and duration after each command
+    #[serde(default)]
+    pub separator: bool,
+    /// Character the separator line is drawn with
+    #[serde(default = "default_separator_char")]
+    pub separator_char: String,
+}
+
+fn default_duration_threshold_ms() -> u64 {
+    2000
+}
+
+fn default_vi_insert_indicator() -> String {
+    "I".to_string()
+}
+
+fn default_vi_normal_indicator() -> String {
+    "N".to_string()
+}
+
+fn default_transient_prompt() -> String {
+    crate::prompt::DEFAULT_TRANSIENT_TEMPLATE.to_string()
+}
+
+fn default_separator_char() -> String {
+    "─".to_string()
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            show_duration: true,
+            duration_threshold_ms: default_duration_threshold_ms(),
+            vi_insert_indicator: default_vi_insert_indicator(),
+            vi_normal_indicator: default_vi_normal_indicator(),
+            transient: false,
+            transient_prompt: default_transient_prompt(),
+            separator: false,
+            separator_char: default_separator_char(),
+        }
+    }
+}
+
+/// Notifications when long-running commands finish
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only commands that ran at least this long
+    #[serde(default = "default_notify_min_duration_secs")]
+    pub min_duration_secs: u64,
+    /// Only notify when the terminal reported losing focus
+    #[serde(default = "default_true")]
+    pub unfocused_only: bool,
+    #[serde(default)]
+    pub method: NotifyMethod,
+    /// Program and arguments for `method = "command"`; `{title}`, `{body}`,
+    /// `{command}`, `{status}` and `{duration}` are replaced in each argument
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Interactive programs that never trigger a notification
+    #[serde(default = "default_notify_ignore")]
+    pub ignore: Vec<String>,
+}
+
+/// How a notification is delivered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyMethod {
+    /// `OSC 9` escape sequence (iTerm2, WezTerm, Windows Terminal, kitty, ...)
+    #[default]
+    Osc9,
+    /// `OSC 777` escape sequence (rxvt-unicode, foot, VTE terminals, ...)
+    Osc777,
+    /// Run `command`
+    Command,
+}
+
+fn default_notify_min_duration_secs() -> u64 {
+    10
+}
+
+fn default_notify_ignore() -> Vec<String> {
+    [
+        "vim", "nvim", "vi", "nano", "emacs", "less", "more", "man", "ssh", "top", "htop",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_duration_secs: default_notify_min_duration_secs(),
+            unfocused_only: true,
+            method: NotifyMethod::default(),
+            command: Vec::new(),
+            ignore: default_notify_ignore(),
+        }
+    }
+}
+
+/// Completion providers beyond the built-in specs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionConfig {
+    /// Read fish `complete -c` declarations for commands without a spec
+    #[serde(default = "default_true")]
+    pub fish: bool,
+    /// Directories searched for `COMMAND.fish`, in order
+    #[serde(default = "default_fish_dirs")]
+    pub fish_dirs: Vec<String>,
+    /// Command to program that prints candidates for it, one per line,
+    /// e.g. `gh = "gh __complete"`; the words on the line are appended
+    #[serde(default)]
+    pub external: BTreeMap<String, String>,
+    /// How long a provider or spec command may run
+    #[serde(default = "default_completion_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_fish_dirs() -> Vec<String> {
+    [
+        "~/.config/fish/completions",
+        "/etc/fish/completions",
+        "/usr/local/share/fish/vendor_completions.d",
+        "/usr/share/fish/vendor_completions.d",
+        "/usr/local/share/fish/completions",
+        "/usr/share/fish/completions",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_completion_timeout_ms() -> u64 {
+    1000
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            fish: true,
+            fish_dirs: default_fish_dirs(),
+            external: BTreeMap::new(),
+            timeout_ms: default_completion_timeout_ms(),
         }
     }
 }
@@ -43,6 +260,52 @@ pub struct HistoryConfig {
     pub file_path: String,
     #[serde(default = "default_max_entries")]
     pub max_entries: usize,
+    #[serde(default)]
+    pub backend: HistoryBackend,
+    /// Database used by the sqlite backend
+    #[serde(default = "default_history_db_path")]
+    pub db_path: String,
+    /// Pick up commands from other running sessions before each prompt
+    #[serde(default)]
+    pub share: bool,
+    /// Commands matching any of these regexes are never recorded
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+    /// Don't record commands typed with a leading space
+    #[serde(default = "default_true")]
+    pub ignore_space: bool,
+    /// Don't record a command identical to the previous one
+    #[serde(default)]
+    pub ignore_dups: bool,
+    /// Remove older copies of a command when it is run again
+    #[serde(default)]
+    pub erase_dups: bool,
+    #[serde(default)]
+    pub hints: HistoryHintConfig,
+    #[serde(default)]
+    pub encryption: HistoryEncryptionConfig,
+}
+
+/// Where history is persisted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBackend {
+    /// One JSON object per line in `file_path`
+    #[default]
+    Jsonl,
+    /// Indexed SQLite database at `db_path`, imported from `file_path` on first use
+    Sqlite,
+}
+
+fn default_history_db_path() -> String {
+    dirs::data_local_dir()
+        .map(|p| {
+            p.join("fool")
+                .join("history.db")
+                .to_string_lossy()
+                .to_string()
+        })
+        .unwrap_or_else(|| "~/.local/share/fool/history.db".to_string())
 }
 
 fn default_history_path() -> String {
@@ -60,6 +323,87 @@ impl Default for HistoryConfig {
         Self {
             file_path: default_history_path(),
             max_entries: default_max_entries(),
+            backend: HistoryBackend::default(),
+            db_path: default_history_db_path(),
+            share: false,
+            ignore_patterns: Vec::new(),
+            ignore_space: true,
+            ignore_dups: false,
+            erase_dups: false,
+            hints: HistoryHintConfig::default(),
+            encryption: HistoryEncryptionConfig::default(),
+        }
+    }
+}
+
+/// Weights for ranking history hints; the highest total score is suggested
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryHintConfig {
+    /// Rank by context; when false the most recent prefix match is used
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_same_dir_weight")]
+    pub same_dir_weight: f64,
+    #[serde(default = "default_same_repo_weight")]
+    pub same_repo_weight: f64,
+    #[serde(default = "default_success_weight")]
+    pub success_weight: f64,
+    /// Scaled from 0 (oldest match) to this value (newest match)
+    #[serde(default = "default_recency_weight")]
+    pub recency_weight: f64,
+}
+
+fn default_same_dir_weight() -> f64 {
+    4.0
+}
+
+fn default_same_repo_weight() -> f64 {
+    2.0
+}
+
+fn default_success_weight() -> f64 {
+    1.0
+}
+
+fn default_recency_weight() -> f64 {
+    1.0
+}
+
+impl Default for HistoryHintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            same_dir_weight: default_same_dir_weight(),
+            same_repo_weight: default_same_repo_weight(),
+            success_weight: default_success_weight(),
+            recency_weight: default_recency_weight(),
+        }
+    }
+}
+
+/// Encryption of the JSONL history file at rest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// File whose contents are the key material; empty uses a passphrase
+    #[serde(default)]
+    pub key_file: String,
+    /// Variable holding the passphrase; it is asked for when unset
+    #[serde(default = "default_passphrase_env")]
+    pub passphrase_env: String,
+}
+
+fn default_passphrase_env() -> String {
+    "FOOL_HISTORY_PASSPHRASE".to_string()
+}
+
+impl Default for HistoryEncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: String::new(),
+            passphrase_env: default_passphrase_env(),
         }
     }
 }
@@ -81,6 +425,16 @@ pub struct AiConfig {
     pub context_lines: usize,
     #[serde(default = "default_system_prompt")]
     pub system_prompt: String,
+    #[serde(default)]
+    pub suggest: AiSuggestConfig,
+    #[serde(default)]
+    pub http: AiHttpConfig,
+    #[serde(default)]
+    pub context: AiContextConfig,
+    #[serde(default)]
+    pub attachments: AiAttachmentConfig,
+    #[serde(default)]
+    pub answers: AiAnswersConfig,
 }
 
 fn default_trigger_prefix() -> String {
@@ -120,6 +474,269 @@ impl Default for AiConfig {
             temperature: default_temperature(),
             context_lines: default_context_lines(),
             system_prompt: default_system_prompt(),
+            suggest: AiSuggestConfig::default(),
+            http: AiHttpConfig::default(),
+            context: AiContextConfig::default(),
+            attachments: AiAttachmentConfig::default(),
+            answers: AiAnswersConfig::default(),
+        }
+    }
+}
+
+/// Inline AI autosuggestion configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiSuggestConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model used for suggestions; empty means `ai.model`
+    #[serde(default)]
+    pub model: String,
+    /// API base used for suggestions; empty means `ai.api_base`
+    #[serde(default)]
+    pub api_base: String,
+    #[serde(default = "default_suggest_debounce_ms")]
+    pub debounce_ms: u64,
+    #[serde(default = "default_suggest_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_suggest_min_chars")]
+    pub min_chars: usize,
+    #[serde(default = "default_suggest_history_lines")]
+    pub history_lines: usize,
+    #[serde(default = "default_suggest_max_dir_entries")]
+    pub max_dir_entries: usize,
+    #[serde(default = "default_suggest_cache_size")]
+    pub cache_size: usize,
+}
+
+fn default_suggest_debounce_ms() -> u64 {
+    400
+}
+
+fn default_suggest_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_suggest_min_chars() -> usize {
+    3
+}
+
+fn default_suggest_history_lines() -> usize {
+    10
+}
+
+fn default_suggest_max_dir_entries() -> usize {
+    40
+}
+
+fn default_suggest_cache_size() -> usize {
+    256
+}
+
+impl Default for AiSuggestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: String::new(),
+            api_base: String::new(),
+            debounce_ms: default_suggest_debounce_ms(),
+            timeout_ms: default_suggest_timeout_ms(),
+            min_chars: default_suggest_min_chars(),
+            history_lines: default_suggest_history_lines(),
+            max_dir_entries: default_suggest_max_dir_entries(),
+            cache_size: default_suggest_cache_size(),
+        }
+    }
+}
+
+impl AiSuggestConfig {
+    /// Whether suggestions are enabled; `FOOL_AI_SUGGEST=0` always wins
+    pub fn is_enabled(&self) -> bool {
+        let forced_off = std::env::var("FOOL_AI_SUGGEST")
+            .map(|v| matches!(v.as_str(), "0" | "off" | "false"))
+            .unwrap_or(false);
+        self.enabled && !forced_off
+    }
+}
+
+/// HTTP client configuration for AI requests (proxy, TLS, headers, timeouts)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiHttpConfig {
+    /// Proxy URL for all AI traffic, e.g. "http://proxy.corp:8080"
+    #[serde(default)]
+    pub proxy: String,
+    /// Hosts that bypass the proxy
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    /// Extra CA bundle (PEM) trusted in addition to the system roots
+    #[serde(default)]
+    pub ca_bundle: String,
+    /// Client certificate: PEM (with `client_key`) or PKCS#12 (.p12/.pfx)
+    #[serde(default)]
+    pub client_cert: String,
+    /// PKCS#8 PEM private key for a PEM `client_cert`
+    #[serde(default)]
+    pub client_key: String,
+    /// Password for a PKCS#12 `client_cert`
+    #[serde(default)]
+    pub client_cert_password: String,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Header carrying the API key, e.g. "api-key" for Azure-style gateways
+    #[serde(default = "default_auth_header")]
+    pub auth_header: String,
+    /// Scheme placed before the key; empty sends the bare key
+    #[serde(default = "default_auth_scheme")]
+    pub auth_scheme: String,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Maximum wait for the response or the next streamed chunk
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+}
+
+fn default_auth_header() -> String {
+    "Authorization".to_string()
+}
+
+fn default_auth_scheme() -> String {
+    "Bearer".to_string()
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_read_timeout_secs() -> u64 {
+    60
+}
+
+impl Default for AiHttpConfig {
+    fn default() -> Self {
+        Self {
+            proxy: String::new(),
+            no_proxy: Vec::new(),
+            ca_bundle: String::new(),
+            client_cert: String::new(),
+            client_key: String::new(),
+            client_cert_password: String::new(),
+            headers: BTreeMap::new(),
+            auth_header: default_auth_header(),
+            auth_scheme: default_auth_scheme(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+        }
+    }
+}
+
+/// Environment snapshot sent with AI queries; each provider can be toggled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiContextConfig {
+    #[serde(default = "default_true")]
+    pub os: bool,
+    #[serde(default = "default_true")]
+    pub shell: bool,
+    #[serde(default = "default_true")]
+    pub cwd: bool,
+    #[serde(default = "default_true")]
+    pub dir_listing: bool,
+    #[serde(default = "default_true")]
+    pub git: bool,
+    #[serde(default = "default_true")]
+    pub project: bool,
+    #[serde(default = "default_true")]
+    pub env: bool,
+    #[serde(default = "default_context_max_dir_entries")]
+    pub max_dir_entries: usize,
+    /// Only these environment variables are ever included
+    #[serde(default = "default_context_env_allowlist")]
+    pub env_allowlist: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_context_max_dir_entries() -> usize {
+    50
+}
+
+fn default_context_env_allowlist() -> Vec<String> {
+    ["LANG", "TERM", "VIRTUAL_ENV", "CONDA_DEFAULT_ENV"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+impl Default for AiContextConfig {
+    fn default() -> Self {
+        Self {
+            os: true,
+            shell: true,
+            cwd: true,
+            dir_listing: true,
+            git: true,
+            project: true,
+            env: true,
+            max_dir_entries: default_context_max_dir_entries(),
+            env_allowlist: default_context_env_allowlist(),
+        }
+    }
+}
+
+/// Limits for files attached to AI queries with `@path`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiAttachmentConfig {
+    #[serde(default = "default_attachment_max_file_bytes")]
+    pub max_file_bytes: usize,
+    #[serde(default = "default_attachment_max_total_bytes")]
+    pub max_total_bytes: usize,
+    #[serde(default = "default_true")]
+    pub mask_secrets: bool,
+}
+
+fn default_attachment_max_file_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_attachment_max_total_bytes() -> usize {
+    256 * 1024
+}
+
+impl Default for AiAttachmentConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: default_attachment_max_file_bytes(),
+            max_total_bytes: default_attachment_max_total_bytes(),
+            mask_secrets: true,
+        }
+    }
+}
+
+/// Storage for past AI answers (`!:last`, `!:save`, `!:answers`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiAnswersConfig {
+    #[serde(default = "default_answers_path")]
+    pub file_path: String,
+    #[serde(default = "default_answers_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_answers_path() -> String {
+    dirs::data_local_dir()
+        .map(|p| p.join("fool").join("answers").to_string_lossy().to_string())
+        .unwrap_or_else(|| "~/.local/share/fool/answers".to_string())
+}
+
+fn default_answers_max_entries() -> usize {
+    50
+}
+
+impl Default for AiAnswersConfig {
+    fn default() -> Self {
+        Self {
+            file_path: default_answers_path(),
+            max_entries: default_answers_max_entries(),
         }
     }
 }
@@ -137,15 +754,37 @@ impl AiConfig {
     }
 }
 
+/// Expand a leading `~/` to the home directory
+pub fn expand_path(path: &str) -> PathBuf {
+    if let Some(stripped) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(stripped);
+        }
+    }
+    PathBuf::from(path)
+}
+
 /// Root configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     #[serde(default)]
     pub ui: UiConfig,
     #[serde(default)]
+    pub prompt: PromptConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub completion: CompletionConfig,
+    #[serde(default)]
     pub history: HistoryConfig,
     #[serde(default)]
     pub ai: AiConfig,
+    /// Key (or space-separated key sequence) to action, see `bindings.rs`
+    #[serde(default)]
+    pub keybindings: BTreeMap<String, String>,
+    /// Name to expansion, managed with the `abbr` builtin, see `abbr.rs`
+    #[serde(default)]
+    pub abbreviations: BTreeMap<String, String>,
 }
 
 impl Config {
@@ -202,6 +841,117 @@ impl Config {
             self.history.max_entries = 10000;
         }
 
+        self.history.ignore_patterns.retain(|pattern| {
+            let valid = regex::Regex::new(pattern).is_ok();
+            if !valid {
+                eprintln!(
+                    "Warning: history.ignore_patterns entry '{}' is not a valid regex, ignoring it",
+                    pattern
+                );
+            }
+            valid
+        });
+
+        if self.history.encryption.enabled && self.history.backend != HistoryBackend::Jsonl {
+            eprintln!(
+                "Warning: history.encryption is only supported by the jsonl backend, using jsonl"
+            );
+            self.history.backend = HistoryBackend::Jsonl;
+        }
+
+        let hints = &mut self.history.hints;
+        for (name, weight) in [
+            ("same_dir_weight", &mut hints.same_dir_weight),
+            ("same_repo_weight", &mut hints.same_repo_weight),
+            ("success_weight", &mut hints.success_weight),
+            ("recency_weight", &mut hints.recency_weight),
+        ] {
+            if !weight.is_finite() || *weight < 0.0 {
+                eprintln!(
+                    "Warning: history.hints.{} {} must be a non-negative number, using 0",
+                    name, weight
+                );
+                *weight = 0.0;
+            }
+        }
+
+        let theme = match crate::theme::Theme::load(&self.ui) {
+            Ok(theme) => theme,
+            Err(e) => {
+                eprintln!(
+                    "Warning: ui.theme is invalid ({:#}), using the default theme",
+                    e
+                );
+                self.ui.theme = default_theme();
+                crate::theme::Theme::from_config(&self.ui)
+            }
+        };
+        if let Err(e) = crate::prompt::Template::parse(&self.ui.prompt, &theme) {
+            eprintln!(
+                "Warning: ui.prompt is invalid ({}), using the default prompt",
+                e
+            );
+            self.ui.prompt = default_prompt_template();
+        }
+        if let Err(e) = crate::prompt::Template::parse(&self.ui.right_prompt, &theme) {
+            eprintln!("Warning: ui.right_prompt is invalid ({}), ignoring it", e);
+            self.ui.right_prompt.clear();
+        }
+        if let Err(e) = crate::prompt::Template::parse(&self.prompt.transient_prompt, &theme) {
+            eprintln!(
+                "Warning: prompt.transient_prompt is invalid ({}), using the default",
+                e
+            );
+            self.prompt.transient_prompt = default_transient_prompt();
+        }
+        if self.prompt.separator_char.chars().count() != 1
+            || crate::prompt::display_width(&self.prompt.separator_char) != 1
+        {
+            eprintln!(
+                "Warning: prompt.separator_char must be a single narrow character, using '─'"
+            );
+            self.prompt.separator_char = default_separator_char();
+        }
+
+        self.keybindings.retain(|keys, action| {
+            match crate::bindings::parse_binding(keys, action) {
+                Ok(_) => true,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: keybindings.\"{}\" is invalid ({}), ignoring it",
+                        keys, e
+                    );
+                    false
+                }
+            }
+        });
+
+        self.abbreviations.retain(|name, expansion| {
+            let valid = crate::abbr::check_name(name).is_ok() && !expansion.trim().is_empty();
+            if !valid {
+                eprintln!(
+                    "Warning: abbreviations.\"{}\" is invalid, ignoring it",
+                    name
+                );
+            }
+            valid
+        });
+
+        if self.completion.timeout_ms == 0 {
+            eprintln!(
+                "Warning: completion.timeout_ms cannot be 0, using default {}",
+                default_completion_timeout_ms()
+            );
+            self.completion.timeout_ms = default_completion_timeout_ms();
+        }
+
+        if self.notify.method == NotifyMethod::Command && self.notify.command.is_empty() {
+            eprintln!(
+                "Warning: notify.method is \"command\" but notify.command is empty, using osc9"
+            );
+            self.notify.method = NotifyMethod::Osc9;
+        }
+
         // M-09: Validate temperature (OpenAI API accepts 0.0 to 2.0)
         if self.ai.temperature < 0.0 || self.ai.temperature > 2.0 {
             eprintln!(
@@ -220,6 +970,45 @@ impl Config {
             );
             self.ai.context_lines = MAX_CONTEXT_LINES;
         }
+
+        // Suggestions fire while typing, so keep the debounce from hammering the API
+        const MIN_SUGGEST_DEBOUNCE_MS: u64 = 100;
+        if self.ai.suggest.debounce_ms < MIN_SUGGEST_DEBOUNCE_MS {
+            eprintln!(
+                "Warning: ai.suggest.debounce_ms {} is below minimum {}, clamping",
+                self.ai.suggest.debounce_ms, MIN_SUGGEST_DEBOUNCE_MS
+            );
+            self.ai.suggest.debounce_ms = MIN_SUGGEST_DEBOUNCE_MS;
+        }
+        if self.ai.http.connect_timeout_secs == 0 {
+            eprintln!("Warning: ai.http.connect_timeout_secs cannot be 0, using default 10");
+            self.ai.http.connect_timeout_secs = default_connect_timeout_secs();
+        }
+        if self.ai.http.read_timeout_secs == 0 {
+            eprintln!("Warning: ai.http.read_timeout_secs cannot be 0, using default 60");
+            self.ai.http.read_timeout_secs = default_read_timeout_secs();
+        }
+        if self.ai.http.auth_header.is_empty() {
+            eprintln!(
+                "Warning: ai.http.auth_header cannot be empty, using default 'Authorization'"
+            );
+            self.ai.http.auth_header = default_auth_header();
+        }
+
+        if self.ai.attachments.max_file_bytes == 0 {
+            eprintln!("Warning: ai.attachments.max_file_bytes cannot be 0, using default 65536");
+            self.ai.attachments.max_file_bytes = default_attachment_max_file_bytes();
+        }
+
+        if self.ai.answers.max_entries == 0 {
+            eprintln!("Warning: ai.answers.max_entries cannot be 0, using default 50");
+            self.ai.answers.max_entries = default_answers_max_entries();
+        }
+
+        if self.ai.suggest.cache_size == 0 {
+            eprintln!("Warning: ai.suggest.cache_size cannot be 0, using default 256");
+            self.ai.suggest.cache_size = default_suggest_cache_size();
+        }
     }
 
     /// Save configuration to the default path
@@ -232,38 +1021,41 @@ impl Config {
     /// Save configuration to a specific path
     /// Uses restricted permissions (0o600 on Unix) to protect sensitive data like API keys
     #[allow(dead_code)] // Public API for config management
-    pub fn save_to(&self, path: &PathBuf) -> Result<()> {
-        // Ensure parent directory exists with restricted permissions
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create config directory: {:?}", parent))?;
-
-            // Set directory permissions to 0o700 on Unix
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let perms = std::fs::Permissions::from_mode(0o700);
-                let _ = std::fs::set_permissions(parent, perms);
-            }
-        }
-
+    pub fn save_to(&self, path: &Path) -> Result<()> {
         let content = toml::to_string_pretty(self).with_context(|| "Failed to serialize config")?;
+        write_config_file(path, &content)
+    }
 
-        // Create file with restricted permissions (0o600 on Unix)
-        let mut options = OpenOptions::new();
-        options.write(true).create(true).truncate(true);
-
-        #[cfg(unix)]
-        options.mode(0o600);
-
-        let mut file = options
-            .open(path)
-            .with_context(|| format!("Failed to open config file for writing: {:?}", path))?;
-
-        file.write_all(content.as_bytes())
-            .with_context(|| format!("Failed to write config file: {:?}", path))?;
+    /// Replace the `[abbreviations]` table of the config file at `path`,
+    /// keeping the rest of the file (comments included) as it is
+    pub fn save_abbreviations_to(
+        path: &Path,
+        abbreviations: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config file: {:?}", path))
+            }
+        };
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .with_context(|| format!("Failed to parse config file: {:?}", path))?;
 
-        Ok(())
+        let item = document
+            .entry("abbreviations")
+            .or_insert_with(toml_edit::table);
+        let Some(table) = item.as_table_mut() else {
+            anyhow::bail!("abbreviations in {:?} is not a table", path);
+        };
+        table.retain(|name, _| abbreviations.contains_key(name));
+        for (name, expansion) in abbreviations {
+            if table.get(name).and_then(|item| item.as_str()) != Some(expansion.as_str()) {
+                table.insert(name, toml_edit::value(expansion.as_str()));
+            }
+        }
+        write_config_file(path, &document.to_string())
     }
 
     /// Generate a default config file with comments
@@ -271,12 +1063,106 @@ impl Config {
         r#"# Fool Shell Configuration
 
 [ui]
-theme = "dracula"          # Interface theme
-editor = "vim"             # Default editor
+# dracula, monokai, solarized, nord, no-color, or NAME for
+# ~/.config/fool/themes/NAME.toml (inherits = "nord" plus a [styles] table)
+theme = "dracula"
+# "auto" (honours NO_COLOR, COLORTERM and TERM), "never", "16", "256" or "truecolor"
+color = "auto"
+edit_mode = "emacs"        # "emacs" or "vi"
+editor = "vim"             # For ctrl-x ctrl-e and the edit builtin
+# Prompt template: {segment} or {segment:arg}, <style>...</> and (...) groups
+# that only show when a segment inside has a value. Segments: user, host,
+# cwd (cwd:N keeps the last N directories), git, git_dirty, status (exit code
+# of a failed command), duration, time (time:%H:%M), venv, ai
+# Styles are colours and attributes, or theme styles such as <cwd> or <error>.
+# {mode} is the vi insert/normal indicator and is empty in emacs mode
+prompt = '(<muted>[{mode}]</> )<user>{user}</> <cwd>{cwd}</> (<duration>took {duration} </>)<symbol>❯</> '
+# right_prompt = '(<error>✗ {status}</> )(<info>{git}{git_dirty}</> )<muted>{time}</>'
+right_prompt = ""
+
+[prompt]
+show_duration = true       # Show how long the previous command took...
+duration_threshold_ms = 2000   # ...if it ran at least this long
+vi_insert_indicator = "I"  # {mode} in vi insert mode
+vi_normal_indicator = "N"  # {mode} in vi normal mode
+transient = false          # Shrink prompts in the scrollback to transient_prompt
+transient_prompt = "<symbol>❯</> "
+separator = false          # Line with the exit code and duration after commands
+separator_char = "─"
+
+# Key bindings: "ctrl-x", "alt-e", "f5", "shift-tab" or a sequence such as
+# "ctrl-x ctrl-e". Actions: editor commands (beginning-of-line, kill-word,
+# history-search-backward, accept-hint, ...), history-picker, "insert:TEXT",
+# "run:COMMAND", ai-explain (explain the current line), ai-fix (replace
+# the current line with the AI's correction) and edit-command-line (edit the
+# line in ui.editor and run it, bound to ctrl-x ctrl-e by default)
+[keybindings]
+# "alt-e" = "ai-explain"
+# "alt-f" = "ai-fix"
+# "ctrl-g" = "run:git status"
+# "alt-l" = "insert: | less"
+
+# Abbreviations expand in place after space or enter, so the full command is
+# what runs and gets recorded; `abbr NAME EXPANSION` adds one from the shell
+[abbreviations]
+# gco = "git checkout"
+# gst = "git status"
+
+# Tab completion beyond the built-in git/cargo/make/ssh/docker specs and
+# ~/.config/fool/completions/COMMAND.toml
+[completion]
+fish = true                # Use fish's completion files for other commands
+fish_dirs = ["~/.config/fish/completions", "/etc/fish/completions", "/usr/local/share/fish/vendor_completions.d", "/usr/share/fish/vendor_completions.d", "/usr/local/share/fish/completions", "/usr/share/fish/completions"]
+timeout_ms = 1000          # Slower providers are ignored
+# Programs that complete themselves: the words on the line are appended and
+# each output line is a candidate (cobra's __complete protocol works as is)
+[completion.external]
+# gh = "gh __complete"
+# kubectl = "kubectl __complete"
+
+# Notify when a long-running command finishes while the terminal is unfocused
+[notify]
+enabled = false
+min_duration_secs = 10
+unfocused_only = true      # Terminals that can't report focus are never notified
+# "osc9" or "osc777" (escape sequences shown by the terminal), or "command"
+method = "osc9"
+# For method = "command"; {title}, {body}, {command}, {status} and {duration}
+# are substituted, e.g. ["notify-send", "{title}", "{body}"]
+command = []
+ignore = ["vim", "nvim", "vi", "nano", "emacs", "less", "more", "man", "ssh", "top", "htop"]
 
 [history]
 file_path = "~/.local/share/fool/history"
 max_entries = 10000        # Maximum history entries
+# Storage backend: "jsonl" (plain file) or "sqlite" (indexed, safe with many
+# terminals open; the jsonl file is imported the first time it is used)
+backend = "jsonl"
+db_path = "~/.local/share/fool/history.db"
+share = false              # Show commands from other open sessions right away
+ignore_space = true        # Commands starting with a space are not recorded
+ignore_dups = false        # Skip a command identical to the previous one
+erase_dups = false         # Keep only the newest copy of each command
+# Commands matching any of these regexes are never recorded, e.g.
+# ignore_patterns = ["^export .*(KEY|TOKEN|SECRET|PASSWORD)"]
+ignore_patterns = []
+
+# Hints prefer commands run in the same directory or git repo that succeeded;
+# each matching property adds its weight, ties go to the most recent command
+[history.hints]
+enabled = true
+same_dir_weight = 4.0
+same_repo_weight = 2.0
+success_weight = 1.0
+recency_weight = 1.0       # Newest match gets the full weight, oldest gets 0
+
+# Encrypt every history record (jsonl backend only); an existing plain file
+# is converted the first time. The key comes from key_file if set, otherwise
+# from the passphrase in $FOOL_HISTORY_PASSPHRASE, otherwise you are asked.
+[history.encryption]
+enabled = false
+key_file = ""
+passphrase_env = "FOOL_HISTORY_PASSPHRASE"
 
 [ai]
 # AI trigger prefix, default is "!"
@@ -294,10 +1180,94 @@ context_lines = 10
 
 # System prompt for AI
 system_prompt = "You are Fool, a helpful assistant running inside a command-line shell. Be concise and provide direct answers. When suggesting commands, provide them in a way that can be easily copied and executed."
+
+# Environment snapshot included with every AI query (see it with "!context")
+[ai.context]
+os = true
+shell = true
+cwd = true
+dir_listing = true
+git = true                 # Branch and status summary
+project = true             # Detected from Cargo.toml, package.json, ...
+env = true
+max_dir_entries = 50
+env_allowlist = ["LANG", "TERM", "VIRTUAL_ENV", "CONDA_DEFAULT_ENV"]
+
+# Files referenced as @path in AI queries
+[ai.attachments]
+max_file_bytes = 65536     # Larger files are truncated
+max_total_bytes = 262144   # Across all files in one query
+mask_secrets = true        # Replace API keys, tokens and passwords with [MASKED]
+
+# Past AI answers, shown with "!:last" and "!:answers", saved with "!:save"
+# Kept in memory only while history.encryption is enabled
+[ai.answers]
+file_path = "~/.local/share/fool/answers"
+max_entries = 50
+
+# Inline AI autosuggestions (ghost text, accept with Right or Ctrl-F)
+# Set FOOL_AI_SUGGEST=0 to force them off regardless of this file
+[ai.suggest]
+enabled = false
+model = ""                 # Fast/local model; empty uses ai.model
+api_base = ""              # Empty uses ai.api_base
+debounce_ms = 400          # Wait this long after the last keystroke
+timeout_ms = 3000
+min_chars = 3
+
+# HTTP client settings for AI requests
+[ai.http]
+proxy = ""                 # e.g. "http://proxy.corp:8080"
+no_proxy = []              # e.g. ["localhost", ".corp.internal"]
+ca_bundle = ""             # Extra CA certificates (PEM)
+client_cert = ""           # PEM (with client_key) or .p12/.pfx
+client_key = ""
+client_cert_password = ""
+auth_header = "Authorization"  # Some gateways expect "api-key"
+auth_scheme = "Bearer"         # Empty sends the bare key
+connect_timeout_secs = 10
+read_timeout_secs = 60
+
+# Extra headers sent with every AI request
+[ai.http.headers]
 "#.to_string()
     }
 }
 
+/// Write a config file, creating its directory, with restricted permissions
+/// (0o600 on Unix) to protect sensitive data like API keys
+fn write_config_file(path: &Path, content: &str) -> Result<()> {
+    // Ensure parent directory exists with restricted permissions
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {:?}", parent))?;
+
+        // Set directory permissions to 0o700 on Unix
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let perms = std::fs::Permissions::from_mode(0o700);
+            let _ = std::fs::set_permissions(parent, perms);
+        }
+    }
+
+    // Create file with restricted permissions (0o600 on Unix)
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to open config file for writing: {:?}", path))?;
+
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write config file: {:?}", path))?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -315,6 +1285,7 @@ mod tests {
         let toml_str = r#"
 [ui]
 theme = "monokai"
+color = "256"
 
 [ai]
 model = "gpt-3.5-turbo"
@@ -322,7 +1293,64 @@ context_lines = 20
 "#;
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.ui.theme, "monokai");
+        assert_eq!(config.ui.color, ColorMode::Ansi256);
         assert_eq!(config.ai.model, "gpt-3.5-turbo");
         assert_eq!(config.ai.context_lines, 20);
     }
+
+    #[test]
+    fn test_completion_config() {
+        let config: Config = toml::from_str(&Config::generate_default_config()).unwrap();
+        assert!(config.completion.fish);
+        assert_eq!(config.completion.fish_dirs, default_fish_dirs());
+        assert!(config.completion.external.is_empty());
+
+        let config: Config = toml::from_str(
+            r#"
+[completion]
+fish = false
+timeout_ms = 300
+
+[completion.external]
+gh = "gh __complete"
+"#,
+        )
+        .unwrap();
+        assert!(!config.completion.fish);
+        assert_eq!(config.completion.timeout_ms, 300);
+        assert_eq!(config.completion.external["gh"], "gh __complete");
+    }
+
+    #[test]
+    fn test_keybindings() {
+        let toml_str = r#"
+[ui]
+edit_mode = "vi"
+
+[keybindings]
+"alt-e" = "ai-explain"
+"ctrl-x ctrl-g" = "run: git status"
+"hyper-q" = "accept-line"
+"ctrl-b" = "no-such-action"
+"#;
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.ui.edit_mode, EditMode::Vi);
+        config.validate_and_fix();
+        let keys: Vec<_> = config.keybindings.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["alt-e", "ctrl-x ctrl-g"]);
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let toml_str = r#"
+[abbreviations]
+gco = "git checkout"
+"bad name" = "echo"
+empty = ""
+"#;
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        config.validate_and_fix();
+        let names: Vec<_> = config.abbreviations.keys().map(String::as_str).collect();
+        assert_eq!(names, ["gco"]);
+    }
 }
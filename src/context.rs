@@ -0,0 +1,303 @@
+//! Context module for Fool Shell
+//! Builds the environment snapshot (OS, cwd, git, project type...) sent to the AI
+
+use crate::config::AiContextConfig;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Project markers and the project type they indicate
+const PROJECT_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "Rust (cargo)"),
+    ("package.json", "Node.js (npm)"),
+    ("pyproject.toml", "Python"),
+    ("requirements.txt", "Python"),
+    ("setup.py", "Python"),
+    ("go.mod", "Go"),
+    ("pom.xml", "Java (maven)"),
+    ("build.gradle", "Java/Kotlin (gradle)"),
+    ("build.gradle.kts", "Kotlin (gradle)"),
+    ("Gemfile", "Ruby (bundler)"),
+    ("composer.json", "PHP (composer)"),
+    ("CMakeLists.txt", "C/C++ (cmake)"),
+    ("Makefile", "make"),
+    ("Dockerfile", "Docker"),
+    ("docker-compose.yml", "Docker Compose"),
+];
+
+/// One titled block of context
+#[derive(Debug, Clone)]
+pub struct ContextSection {
+    pub title: &'static str,
+    pub content: String,
+}
+
+/// Collects the enabled context providers
+pub struct ContextBuilder {
+    config: AiContextConfig,
+}
+
+impl ContextBuilder {
+    pub fn new(config: AiContextConfig) -> Self {
+        Self { config }
+    }
+
+    /// Collect sections for the current working directory
+    pub fn collect(&self) -> Vec<ContextSection> {
+        match std::env::current_dir() {
+            Ok(dir) => self.collect_in(&dir),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Collect sections as if the shell were in `dir`
+    pub fn collect_in(&self, dir: &Path) -> Vec<ContextSection> {
+        let mut sections = Vec::new();
+        let mut push = |title: &'static str, content: Option<String>| {
+            if let Some(content) = content.filter(|c| !c.is_empty()) {
+                sections.push(ContextSection { title, content });
+            }
+        };
+
+        if self.config.os {
+            push("OS", Some(os_description()));
+        }
+        if self.config.shell {
+            push("Shell", Some(shell_description()));
+        }
+        if self.config.cwd {
+            push("Working directory", Some(dir.display().to_string()));
+        }
+        if self.config.dir_listing {
+            push(
+                "Directory listing",
+                dir_listing(dir, self.config.max_dir_entries),
+            );
+        }
+        if self.config.git {
+            push("Git", git_summary(dir));
+        }
+        if self.config.project {
+            push("Project type", project_types(dir));
+        }
+        if self.config.env {
+            push(
+                "Environment variables",
+                env_vars(&self.config.env_allowlist),
+            );
+        }
+
+        sections
+    }
+
+    /// Render the snapshot as a block for the system prompt
+    pub fn render(&self) -> Option<String> {
+        format_sections(&self.collect())
+    }
+}
+
+/// Format sections as "Title: content" blocks, or None if there are none
+pub fn format_sections(sections: &[ContextSection]) -> Option<String> {
+    if sections.is_empty() {
+        return None;
+    }
+    let body = sections
+        .iter()
+        .map(|s| format!("{}: {}", s.title, s.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(format!("Current environment:\n{}", body))
+}
+
+/// Find the root of the git repository containing `dir`
+pub fn find_git_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|p| p.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Current git branch of the repository containing `dir`
+pub fn git_branch(dir: &Path) -> Option<String> {
+    find_git_root(dir)?;
+    let branch = run_git(dir, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let branch = branch.trim();
+    (!branch.is_empty()).then(|| branch.to_string())
+}
+
+/// Whether the work tree containing `dir` has uncommitted changes
+pub fn git_dirty(dir: &Path) -> Option<bool> {
+    find_git_root(dir)?;
+    let status = run_git(dir, &["status", "--porcelain"])?;
+    Some(!status.trim().is_empty())
+}
+
+fn os_description() -> String {
+    let pretty = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|content| {
+            content
+                .lines()
+                .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+                .map(|v| v.trim_matches('"').to_string())
+        });
+    match pretty {
+        Some(name) => format!("{} ({})", name, std::env::consts::ARCH),
+        None => format!("{} ({})", std::env::consts::OS, std::env::consts::ARCH),
+    }
+}
+
+fn shell_description() -> String {
+    let mut desc = format!("Fool Shell v{}", env!("CARGO_PKG_VERSION"));
+    if let Ok(login_shell) = std::env::var("SHELL") {
+        desc.push_str(&format!(" (login shell: {})", login_shell));
+    }
+    desc
+}
+
+fn dir_listing(dir: &Path, max_entries: usize) -> Option<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if e.path().is_dir() {
+                format!("{}/", name)
+            } else {
+                name
+            }
+        })
+        .collect();
+    names.sort();
+
+    let total = names.len();
+    names.truncate(max_entries);
+    let mut listing = names.join(" ");
+    if total > max_entries {
+        listing.push_str(&format!(" ... ({} more)", total - max_entries));
+    }
+    Some(listing)
+}
+
+fn git_summary(dir: &Path) -> Option<String> {
+    let branch = git_branch(dir)?;
+    let status = run_git(dir, &["status", "--porcelain"]).unwrap_or_default();
+
+    let (mut staged, mut modified, mut untracked) = (0, 0, 0);
+    for line in status.lines() {
+        let bytes = line.as_bytes();
+        if line.starts_with("??") {
+            untracked += 1;
+            continue;
+        }
+        if bytes.first().is_some_and(|c| *c != b' ') {
+            staged += 1;
+        }
+        if bytes.get(1).is_some_and(|c| *c != b' ') {
+            modified += 1;
+        }
+    }
+
+    if staged + modified + untracked == 0 {
+        Some(format!("branch {}, clean", branch))
+    } else {
+        Some(format!(
+            "branch {}, {} staged, {} modified, {} untracked",
+            branch, staged, modified, untracked
+        ))
+    }
+}
+
+fn project_types(dir: &Path) -> Option<String> {
+    let mut types: Vec<&str> = Vec::new();
+    for (marker, kind) in PROJECT_MARKERS {
+        if dir.join(marker).exists() && !types.contains(kind) {
+            types.push(kind);
+        }
+    }
+    (!types.is_empty()).then(|| types.join(", "))
+}
+
+fn env_vars(allowlist: &[String]) -> Option<String> {
+    let vars: Vec<String> = allowlist
+        .iter()
+        .filter_map(|name| std::env::var(name).ok().map(|v| format!("{}={}", name, v)))
+        .collect();
+    (!vars.is_empty()).then(|| vars.join(" "))
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn all_off() -> AiContextConfig {
+        AiContextConfig {
+            os: false,
+            shell: false,
+            cwd: false,
+            dir_listing: false,
+            git: false,
+            project: false,
+            env: false,
+            ..AiContextConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_all_providers_disabled() {
+        let dir = tempdir().unwrap();
+        let sections = ContextBuilder::new(all_off()).collect_in(dir.path());
+        assert!(sections.is_empty());
+        assert!(format_sections(&sections).is_none());
+    }
+
+    #[test]
+    fn test_project_detection_and_listing_truncation() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.path().join("Makefile"), "").unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+
+        let config = AiContextConfig {
+            project: true,
+            dir_listing: true,
+            max_dir_entries: 2,
+            ..all_off()
+        };
+        let sections = ContextBuilder::new(config).collect_in(dir.path());
+
+        assert_eq!(sections[0].title, "Directory listing");
+        assert_eq!(sections[0].content, "Cargo.toml Makefile ... (1 more)");
+        assert_eq!(sections[1].content, "Rust (cargo), make");
+    }
+
+    #[test]
+    fn test_env_allowlist() {
+        std::env::set_var("FOOL_CONTEXT_TEST_VAR", "visible");
+        std::env::set_var("FOOL_CONTEXT_TEST_SECRET", "hidden");
+
+        let config = AiContextConfig {
+            env: true,
+            env_allowlist: vec!["FOOL_CONTEXT_TEST_VAR".to_string()],
+            ..all_off()
+        };
+        let dir = tempdir().unwrap();
+        let sections = ContextBuilder::new(config).collect_in(dir.path());
+
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].content, "FOOL_CONTEXT_TEST_VAR=visible");
+    }
+}
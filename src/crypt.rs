@@ -0,0 +1,286 @@
+//! Crypt module for Fool Shell
+//! Encryption of history records at rest, keyed by a passphrase or key file
+
+use crate::config::HistoryEncryptionConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::Path;
+
+const FORMAT: &str = "fool-history-encrypted";
+const VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Known plaintext in the header, to tell a wrong passphrase from a damaged file
+const CHECK_TEXT: &[u8] = b"fool history";
+
+/// First line of an encrypted history file
+///
+/// Plain JSONL readers skip it, since it isn't a history entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    format: String,
+    version: u32,
+    kdf: String,
+    salt: String,
+    check: String,
+}
+
+impl Header {
+    /// Parse `line` if it is an encrypted history header
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str::<Header>(line)
+            .ok()
+            .filter(|h| h.format == FORMAT)
+    }
+
+    /// The header of the file at `path`, if it exists and is encrypted
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to open history file: {:?}", path))
+            }
+        };
+        let mut first = String::new();
+        BufReader::new(file).read_line(&mut first)?;
+        Ok(Self::parse(first.trim_end()))
+    }
+}
+
+/// Encrypts and decrypts single history records (one line each)
+pub struct RecordCipher {
+    cipher: XChaCha20Poly1305,
+    header: Header,
+}
+
+impl RecordCipher {
+    /// A cipher for a new file, with a fresh random salt
+    pub fn create(secret: &[u8]) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = derive_cipher(secret, &salt)?;
+
+        let mut header = Header {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            kdf: "argon2id".to_string(),
+            salt: BASE64.encode(salt),
+            check: String::new(),
+        };
+        header.check = seal(&cipher, CHECK_TEXT)?;
+        Ok(Self { cipher, header })
+    }
+
+    /// The cipher for an existing file; fails if `secret` is wrong
+    pub fn unlock(header: &Header, secret: &[u8]) -> Result<Self> {
+        if header.version != VERSION || header.kdf != "argon2id" {
+            bail!(
+                "unsupported encrypted history format (version {}, {})",
+                header.version,
+                header.kdf
+            );
+        }
+        let salt = BASE64
+            .decode(&header.salt)
+            .context("corrupt encrypted history header")?;
+        let cipher = derive_cipher(secret, &salt)?;
+        if open(&cipher, &header.check).as_deref() != Some(CHECK_TEXT) {
+            bail!("wrong history passphrase or key file");
+        }
+        Ok(Self {
+            cipher,
+            header: header.clone(),
+        })
+    }
+
+    /// The header line to write at the top of the file
+    pub fn header_line(&self) -> String {
+        serde_json::to_string(&self.header).unwrap_or_default()
+    }
+
+    /// Encrypt one record into a single base64 line
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        seal(&self.cipher, plaintext.as_bytes())
+    }
+
+    /// Decrypt a line written by `encrypt`; `None` for anything else
+    pub fn decrypt(&self, line: &str) -> Option<String> {
+        String::from_utf8(open(&self.cipher, line)?).ok()
+    }
+}
+
+fn derive_cipher(secret: &[u8], salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive history key: {}", e))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// base64(nonce || ciphertext)
+fn seal(cipher: &XChaCha20Poly1305, plaintext: &[u8]) -> Result<String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt history entry"))?;
+    let mut bytes = nonce.to_vec();
+    bytes.extend_from_slice(&ciphertext);
+    Ok(BASE64.encode(bytes))
+}
+
+fn open(cipher: &XChaCha20Poly1305, line: &str) -> Option<Vec<u8>> {
+    let bytes = BASE64.decode(line.trim()).ok()?;
+    if bytes.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    cipher.decrypt(XNonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// The key material: the key file's contents, the passphrase from the
+/// environment, or one typed at the terminal (twice when `new_file`)
+pub fn load_secret(config: &HistoryEncryptionConfig, new_file: bool) -> Result<Vec<u8>> {
+    if !config.key_file.is_empty() {
+        let path = crate::config::expand_path(&config.key_file);
+        let mut secret = std::fs::read(&path)
+            .with_context(|| format!("Failed to read history key file: {:?}", path))?;
+        // Editors add a trailing newline
+        while secret.last().is_some_and(|b| b.is_ascii_whitespace()) {
+            secret.pop();
+        }
+        if secret.is_empty() {
+            bail!("history key file {:?} is empty", path);
+        }
+        return Ok(secret);
+    }
+
+    if !config.passphrase_env.is_empty() {
+        if let Ok(passphrase) = std::env::var(&config.passphrase_env) {
+            if !passphrase.is_empty() {
+                return Ok(passphrase.into_bytes());
+            }
+        }
+    }
+
+    if !io::stdin().is_terminal() {
+        bail!("{}", missing_secret_message(config));
+    }
+    let passphrase = read_hidden("History passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("empty history passphrase");
+    }
+    if new_file && read_hidden("Confirm passphrase: ")? != passphrase {
+        bail!("passphrases do not match");
+    }
+    Ok(passphrase.into_bytes())
+}
+
+/// What to do when there is no key file, passphrase variable or terminal
+fn missing_secret_message(config: &HistoryEncryptionConfig) -> String {
+    let mut ways = Vec::new();
+    if !config.passphrase_env.is_empty() {
+        ways.push(format!("set ${}", config.passphrase_env));
+    }
+    ways.push("set history.encryption.key_file".to_string());
+    ways.push("start the shell in a terminal to type the passphrase".to_string());
+    format!(
+        "history is encrypted and no passphrase is available: {}",
+        ways.join(", or ")
+    )
+}
+
+/// Read a line from the terminal without echoing it
+fn read_hidden(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    terminal::enable_raw_mode()?;
+
+    let mut input = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(anyhow!("cancelled"));
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    let _ = terminal::disable_raw_mode();
+    eprintln!();
+    result.map(|_| input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_unlock() {
+        let cipher = RecordCipher::create(b"hunter2").unwrap();
+        let line = cipher.encrypt(r#"{"command":"ssh prod"}"#).unwrap();
+        assert!(!line.contains("ssh"));
+        // A fresh nonce per record
+        assert_ne!(line, cipher.encrypt(r#"{"command":"ssh prod"}"#).unwrap());
+
+        let header = Header::parse(&cipher.header_line()).unwrap();
+        let reopened = RecordCipher::unlock(&header, b"hunter2").unwrap();
+        assert_eq!(
+            reopened.decrypt(&line).as_deref(),
+            Some(r#"{"command":"ssh prod"}"#)
+        );
+    }
+
+    #[test]
+    fn test_wrong_secret_and_tampering() {
+        let cipher = RecordCipher::create(b"right").unwrap();
+        let header = Header::parse(&cipher.header_line()).unwrap();
+        assert!(RecordCipher::unlock(&header, b"wrong").is_err());
+
+        let line = cipher.encrypt("secret").unwrap();
+        let mut bytes = BASE64.decode(&line).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert_eq!(cipher.decrypt(&BASE64.encode(bytes)), None);
+        assert_eq!(cipher.decrypt(r#"{"command":"ls"}"#), None);
+        assert!(Header::parse(r#"{"command":"ls"}"#).is_none());
+    }
+
+    #[test]
+    fn test_missing_secret_message() {
+        let mut config = HistoryEncryptionConfig {
+            passphrase_env: "FOOL_HISTORY_PASSPHRASE".to_string(),
+            ..HistoryEncryptionConfig::default()
+        };
+        let message = missing_secret_message(&config);
+        assert!(
+            message.contains("set $FOOL_HISTORY_PASSPHRASE, or set history.encryption.key_file")
+        );
+
+        config.passphrase_env.clear();
+        let message = missing_secret_message(&config);
+        assert!(!message.contains("$"));
+        assert!(
+            message.contains(": set history.encryption.key_file, or start the shell in a terminal")
+        );
+    }
+}
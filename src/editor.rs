@@ -0,0 +1,120 @@
+//! External editor module for Fool Shell
+//! Opens command text in `ui.editor` through a temporary file and splits what
+//! was saved into the commands to run
+
+use crate::parser::Parser;
+use anyhow::{bail, Context, Result};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// Let the user edit `text` in `editor`, returning what they saved
+///
+/// `editor` may carry arguments (`code --wait`); it runs through `sh` with
+/// the file name appended.
+pub fn edit(editor: &str, text: &str) -> Result<String> {
+    if editor.trim().is_empty() {
+        bail!("no editor configured, set ui.editor or $EDITOR");
+    }
+    let path = temp_path();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(text.as_bytes())?;
+    if !text.is_empty() && !text.ends_with('\n') {
+        file.write_all(b"\n")?;
+    }
+    drop(file);
+
+    let result = run_editor(editor, &path).and_then(|()| {
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn run_editor(editor: &str, path: &Path) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+    if !status.success() {
+        bail!("editor '{}' exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+/// A file name in the temp directory no other shell will pick; the `.sh`
+/// suffix gets editors to highlight it as shell
+fn temp_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    std::env::temp_dir().join(format!("fool-edit-{}-{}.sh", std::process::id(), nanos))
+}
+
+/// The commands in saved editor text: one per line, except that a line
+/// continued with `|`, `&&`, `\` or an open quote runs on into the next.
+/// Blank lines and `#` comments are skipped
+pub fn split_commands(parser: &Parser, text: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if current.is_empty() && (line.trim().is_empty() || line.trim_start().starts_with('#')) {
+            continue;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+        if !parser.is_continued(&current) {
+            commands.push(std::mem::take(&mut current).trim().to_string());
+        }
+    }
+    // Whatever is left fails to parse and is reported when it runs
+    if !current.trim().is_empty() {
+        commands.push(current.trim().to_string());
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_commands() {
+        let parser = Parser::new("!".to_string());
+        let text = "# setup\ncd /tmp\n\nls -la |\n  grep x &&\necho 'a\nb'\nmake \\\n  install\n";
+        assert_eq!(
+            split_commands(&parser, text),
+            [
+                "cd /tmp",
+                "ls -la |\n  grep x &&\necho 'a\nb'",
+                "make \\\n  install"
+            ]
+        );
+        assert_eq!(split_commands(&parser, "ls |\n"), ["ls |"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit() {
+        // A "editor" that appends a line to the file it's given
+        let edited = edit("printf 'pwd\\n' >>", "ls").unwrap();
+        assert_eq!(edited, "ls\npwd\n");
+        assert!(edit("false", "ls").is_err());
+    }
+}
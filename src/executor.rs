@@ -1,7 +1,10 @@
 //! Command Executor module for Fool Shell
 //! Handles process spawning, pipes, and redirections
 
+use crate::abbr::{self, SharedAbbreviations};
+use crate::history::{self, History, HistoryEntry, HistoryFormat, HistoryQuery, SharedHistory};
 use crate::parser::Command;
+use crate::stats::HistoryStats;
 use anyhow::{anyhow, Context, Result};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
@@ -34,6 +37,10 @@ impl ExecutionResult {
             stderr: None,
         }
     }
+
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == 0
+    }
 }
 
 /// Built-in shell commands
@@ -47,10 +54,35 @@ pub enum BuiltinCommand {
     Clear,
     Pwd,
     Alias,
+    Abbr,
     Source,
+    Incognito,
+    Stats,
+    Edit,
 }
 
 impl BuiltinCommand {
+    /// Every name `from_str` accepts, for completion
+    pub const NAMES: &'static [&'static str] = &[
+        "cd",
+        "exit",
+        "quit",
+        "export",
+        "unset",
+        "history",
+        "help",
+        "clear",
+        "pwd",
+        "alias",
+        "abbr",
+        "source",
+        ".",
+        "incognito",
+        "stats",
+        "edit",
+        "fc",
+    ];
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "cd" => Some(Self::Cd),
@@ -62,7 +94,11 @@ impl BuiltinCommand {
             "clear" => Some(Self::Clear),
             "pwd" => Some(Self::Pwd),
             "alias" => Some(Self::Alias),
+            "abbr" => Some(Self::Abbr),
             "source" | "." => Some(Self::Source),
+            "incognito" => Some(Self::Incognito),
+            "stats" => Some(Self::Stats),
+            "edit" | "fc" => Some(Self::Edit),
             _ => None,
         }
     }
@@ -72,9 +108,11 @@ impl BuiltinCommand {
 pub struct Executor {
     env_vars: HashMap<String, String>,
     aliases: HashMap<String, Vec<String>>,
+    abbreviations: SharedAbbreviations, // In memory only until the REPL shares its own
     last_exit_code: i32,
-    history_entries: Vec<String>, // Store history commands for display
-    ai_trigger_prefix: String,    // M-03: Store AI trigger prefix for source command
+    history: Option<SharedHistory>, // None when running without history (-c mode)
+    saved_history: Option<History>, // Persisted history set aside while incognito
+    ai_trigger_prefix: String,      // M-03: Store AI trigger prefix for source command
 }
 
 impl Executor {
@@ -91,15 +129,27 @@ impl Executor {
         Self {
             env_vars,
             aliases: HashMap::new(),
+            abbreviations: SharedAbbreviations::default(),
             last_exit_code: 0,
-            history_entries: Vec::new(),
+            history: None,
+            saved_history: None,
             ai_trigger_prefix,
         }
     }
 
-    /// Set history entries for the history command
-    pub fn set_history(&mut self, entries: Vec<String>) {
-        self.history_entries = entries;
+    /// Share the REPL history with the history command
+    pub fn set_history(&mut self, history: SharedHistory) {
+        self.history = Some(history);
+    }
+
+    /// Share the REPL abbreviations with the abbr command
+    pub fn set_abbreviations(&mut self, abbreviations: SharedAbbreviations) {
+        self.abbreviations = abbreviations;
+    }
+
+    /// Names of the defined aliases
+    pub fn alias_names(&self) -> Vec<String> {
+        self.aliases.keys().cloned().collect()
     }
 
     /// Get last exit code
@@ -108,8 +158,23 @@ impl Executor {
         self.last_exit_code
     }
 
-    /// Execute a pipeline of commands
-    pub fn execute_pipeline(&mut self, commands: Vec<Command>) -> Result<ExecutionResult> {
+    /// Execute a pipeline of commands, or pipelines joined by `&&` until one fails
+    pub fn execute_pipeline(&mut self, mut commands: Vec<Command>) -> Result<ExecutionResult> {
+        let mut result = ExecutionResult::success();
+        while let Some(end) = commands.iter().position(|cmd| cmd.and_then) {
+            let rest = commands.split_off(end + 1);
+            result = self.run_pipeline(std::mem::replace(&mut commands, rest))?;
+            if result.exit_code != 0 {
+                return Ok(result);
+            }
+        }
+        if commands.is_empty() {
+            return Ok(result);
+        }
+        self.run_pipeline(commands)
+    }
+
+    fn run_pipeline(&mut self, commands: Vec<Command>) -> Result<ExecutionResult> {
         if commands.is_empty() {
             return Ok(ExecutionResult::success());
         }
@@ -150,7 +215,15 @@ impl Executor {
             BuiltinCommand::Clear => self.builtin_clear(),
             BuiltinCommand::Pwd => self.builtin_pwd(),
             BuiltinCommand::Alias => self.builtin_alias(&cmd.args),
+            BuiltinCommand::Abbr => self.builtin_abbr(&cmd.args),
             BuiltinCommand::Source => self.builtin_source(&cmd.args),
+            BuiltinCommand::Incognito => self.builtin_incognito(&cmd.args),
+            BuiltinCommand::Stats => self.builtin_stats(&cmd.args),
+            // The REPL opens the editor itself so it can run what was saved
+            BuiltinCommand::Edit => {
+                eprintln!("{}: only available in the interactive shell", cmd.program);
+                Ok(ExecutionResult::with_code(1))
+            }
         }
     }
 
@@ -232,7 +305,11 @@ impl Executor {
         println!("  export VAR=val  Set environment variable");
         println!("  unset VAR       Unset environment variable");
         println!("  alias           Manage aliases");
-        println!("  history         Show command history");
+        println!("  abbr [opts]     Manage abbreviations (abbr --help for usage)");
+        println!("  history [opts]  Show command history (history --help for filters)");
+        println!("  incognito       Toggle saving commands to the history file");
+        println!("  edit [N|TEXT]   Edit a past command in $EDITOR and run it (also fc)");
+        println!("  stats [opts]    Most used, slowest and failing commands");
         println!("  clear           Clear the screen");
         println!("  help            Show this help");
         println!("  exit [code]     Exit the shell");
@@ -240,6 +317,11 @@ impl Executor {
         println!("AI Mode:");
         println!("  !query          Send a query to AI assistant");
         println!("  Example: ! how to find large files in Linux");
+        println!("  @path           Attach a file to the query: ! why @build.log");
+        println!("  !context        Show the environment sent with queries");
+        println!("  !:last [N]      Show the last (or Nth last) answer");
+        println!("  !:save [N] FILE Save the last answer, or its Nth code block");
+        println!("  !:answers [Q]   List past answers, optionally searching for Q");
         Ok(ExecutionResult::success())
     }
 
@@ -255,14 +337,176 @@ impl Executor {
         Ok(ExecutionResult::success())
     }
 
-    fn builtin_history(&self, _args: &[String]) -> Result<ExecutionResult> {
-        if self.history_entries.is_empty() {
+    fn builtin_history(&self, args: &[String]) -> Result<ExecutionResult> {
+        if args.iter().any(|a| a == "-h" || a == "--help") {
+            println!(
+                "usage: history [-n N] [--cwd DIR] [--here] [--failed] [--session] [--since AGE]"
+            );
+            println!("               [--grep REGEX] [--prefix TEXT] [-t | --json] [TEXT]");
+            println!("       history -d N | --delete-matching REGEX");
+            return Ok(ExecutionResult::success());
+        }
+
+        if let Some(option @ ("-d" | "--delete-matching")) = args.first().map(String::as_str) {
+            return self.history_delete(option, args.get(1));
+        }
+
+        let query = match HistoryQuery::from_args(args) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("history: {:#}", e);
+                return Ok(ExecutionResult::with_code(2));
+            }
+        };
+
+        let Some(shared) = &self.history else {
+            println!("No history available");
+            return Ok(ExecutionResult::success());
+        };
+        let history = history::lock(shared);
+
+        let mut out = io::stdout().lock();
+        for (number, entry) in history.query(&query) {
+            match query.format {
+                HistoryFormat::Plain => writeln!(out, "{:5}  {}", number, entry.command)?,
+                HistoryFormat::Timestamps => writeln!(
+                    out,
+                    "{:5}  {}  {}",
+                    number,
+                    history::format_local_time(&entry.timestamp),
+                    entry.command
+                )?,
+                HistoryFormat::Json => {
+                    let mut value = serde_json::to_value(entry)?;
+                    value["number"] = serde_json::json!(number);
+                    writeln!(out, "{}", value)?
+                }
+            }
+        }
+        Ok(ExecutionResult::success())
+    }
+
+    /// `stats [--top N] [history filters]`
+    fn builtin_stats(&self, args: &[String]) -> Result<ExecutionResult> {
+        if args.iter().any(|a| a == "-h" || a == "--help") {
+            println!("usage: stats [--top N] [history filters, see history --help]");
+            return Ok(ExecutionResult::success());
+        }
+
+        let mut top = 10;
+        let mut filters = Vec::new();
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            if arg != "--top" {
+                filters.push(arg.clone());
+                continue;
+            }
+            match rest.next().and_then(|n| n.parse().ok()) {
+                Some(n) => top = n,
+                None => {
+                    eprintln!("stats: --top requires a number");
+                    return Ok(ExecutionResult::with_code(2));
+                }
+            }
+        }
+        let query = match HistoryQuery::from_args(&filters) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("stats: {:#}", e);
+                return Ok(ExecutionResult::with_code(2));
+            }
+        };
+
+        let Some(shared) = &self.history else {
+            println!("No history available");
+            return Ok(ExecutionResult::success());
+        };
+        let history = history::lock(shared);
+        let entries: Vec<&HistoryEntry> =
+            history.query(&query).into_iter().map(|(_, e)| e).collect();
+        print!("{}", HistoryStats::compute(&entries).render(top));
+        Ok(ExecutionResult::success())
+    }
+
+    /// `history -d N` and `history --delete-matching REGEX`
+    fn history_delete(&self, option: &str, value: Option<&String>) -> Result<ExecutionResult> {
+        let Some(shared) = &self.history else {
             println!("No history available");
+            return Ok(ExecutionResult::success());
+        };
+        let Some(value) = value else {
+            eprintln!("history: {} requires an argument", option);
+            return Ok(ExecutionResult::with_code(2));
+        };
+
+        let mut history = history::lock(shared);
+        let result = if option == "-d" {
+            match value.parse::<usize>() {
+                Ok(number) => history.delete(number).map(|_| ()),
+                Err(_) => Err(anyhow!("{}: invalid history position", value)),
+            }
         } else {
-            for (i, cmd) in self.history_entries.iter().enumerate() {
-                println!("{:5}  {}", i + 1, cmd);
+            match regex::Regex::new(value) {
+                Ok(pattern) => history.delete_matching(&pattern).map(|count| {
+                    println!("Deleted {} history entries", count);
+                }),
+                Err(e) => Err(anyhow!("invalid regex '{}': {}", value, e)),
+            }
+        };
+
+        match result {
+            Ok(()) => Ok(ExecutionResult::success()),
+            Err(e) => {
+                eprintln!("history: {:#}", e);
+                Ok(ExecutionResult::with_code(1))
             }
         }
+    }
+
+    /// Switch history persistence off (`incognito on`) and back on
+    fn builtin_incognito(&mut self, args: &[String]) -> Result<ExecutionResult> {
+        let Some(shared) = &self.history else {
+            println!("No history available");
+            return Ok(ExecutionResult::success());
+        };
+        let enable = match args.first().map(String::as_str) {
+            None => self.saved_history.is_none(),
+            Some("on") => true,
+            Some("off") => false,
+            Some(other) => {
+                eprintln!("incognito: usage: incognito [on|off] (got '{}')", other);
+                return Ok(ExecutionResult::with_code(2));
+            }
+        };
+
+        let mut history = history::lock(shared);
+        match (enable, self.saved_history.is_some()) {
+            (true, false) if history.is_memory_only() => {
+                println!("History is not being saved in this session");
+            }
+            (true, false) => {
+                // `incognito on` was typed with history on: save it now, as
+                // the REPL finishes the entry in whichever history is current
+                history.finish_pending(0)?;
+                let private = history.to_memory_only();
+                self.saved_history = Some(std::mem::replace(&mut *history, private));
+                println!("Incognito mode on: commands are no longer saved to history");
+            }
+            (false, true) => {
+                // Commands run while incognito are forgotten along with the
+                // copy; `incognito off` moves over and is saved when finished
+                let pending = history.take_pending();
+                if let Some(saved) = self.saved_history.take() {
+                    *history = saved;
+                }
+                if let Some(entry) = pending {
+                    history.restore_pending(entry);
+                }
+                println!("Incognito mode off: history is saved again");
+            }
+            (true, true) => println!("Incognito mode is already on"),
+            (false, false) => println!("Incognito mode is already off"),
+        }
         Ok(ExecutionResult::success())
     }
 
@@ -289,6 +533,52 @@ impl Executor {
         Ok(ExecutionResult::success())
     }
 
+    fn builtin_abbr(&mut self, args: &[String]) -> Result<ExecutionResult> {
+        let mut abbreviations = abbr::lock(&self.abbreviations);
+        match args.first().map(String::as_str) {
+            None => {
+                for (name, expansion) in abbreviations.entries() {
+                    println!("abbr {} '{}'", name, expansion);
+                }
+            }
+            Some("-h" | "--help") => {
+                println!("usage: abbr [NAME [EXPANSION...]]");
+                println!("       abbr -e | --erase NAME...");
+            }
+            Some("-e" | "--erase") => {
+                let mut code = 0;
+                for name in &args[1..] {
+                    match abbreviations.erase(name) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            eprintln!("abbr: no such abbreviation: {}", name);
+                            code = 1;
+                        }
+                        Err(e) => {
+                            eprintln!("abbr: {:#}", e);
+                            code = 1;
+                        }
+                    }
+                }
+                return Ok(ExecutionResult::with_code(code));
+            }
+            Some(name) if args.len() == 1 => match abbreviations.get(name) {
+                Some(expansion) => println!("abbr {} '{}'", name, expansion),
+                None => {
+                    eprintln!("abbr: no such abbreviation: {}", name);
+                    return Ok(ExecutionResult::with_code(1));
+                }
+            },
+            Some(name) => {
+                if let Err(e) = abbreviations.set(name, &args[1..].join(" ")) {
+                    eprintln!("abbr: {:#}", e);
+                    return Ok(ExecutionResult::with_code(1));
+                }
+            }
+        }
+        Ok(ExecutionResult::success())
+    }
+
     fn builtin_source(&mut self, args: &[String]) -> Result<ExecutionResult> {
         if args.is_empty() {
             eprintln!("source: usage: source <filename>");
@@ -608,6 +898,13 @@ mod tests {
         assert_eq!(executor.get_env("TEST_VAR"), Some(&"hello".to_string()));
     }
 
+    #[test]
+    fn test_builtin_names() {
+        for name in BuiltinCommand::NAMES {
+            assert!(Executor::is_builtin(name), "{}", name);
+        }
+    }
+
     #[test]
     fn test_alias_with_spaces() {
         let mut executor = Executor::new();
@@ -636,6 +933,28 @@ mod tests {
         assert_eq!(alias_tokens[1], "hello world");
     }
 
+    #[test]
+    fn test_abbr() {
+        let mut executor = Executor::new();
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let result = executor
+            .builtin_abbr(&args(&["gco", "git", "checkout"]))
+            .unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(
+            abbr::lock(&executor.abbreviations).get("gco"),
+            Some("git checkout")
+        );
+        let result = executor
+            .builtin_abbr(&args(&["-e", "gco", "nope"]))
+            .unwrap();
+        assert_eq!(result.exit_code, 1);
+        assert!(abbr::lock(&executor.abbreviations).entries().is_empty());
+        let result = executor.builtin_abbr(&args(&["gco"])).unwrap();
+        assert_eq!(result.exit_code, 1);
+    }
+
     #[test]
     fn test_alias_expansion() {
         let mut executor = Executor::new();
@@ -650,6 +969,7 @@ mod tests {
             stdin_redirect: None,
             stdout_redirect: None,
             stdout_append: false,
+            and_then: false,
         };
 
         // Get the alias
@@ -691,6 +1011,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: None,
                 stdout_append: false,
+                and_then: false,
             },
             Command {
                 program: "grep".to_string(),
@@ -698,6 +1019,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: Some(output_path.to_string_lossy().to_string()),
                 stdout_append: false,
+                and_then: false,
             },
         ];
 
@@ -709,6 +1031,71 @@ mod tests {
         assert_eq!(output.trim(), "bar");
     }
 
+    #[test]
+    fn test_and_list_stops_at_failure() {
+        let mut executor = Executor::new();
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ran");
+        let parser = crate::parser::Parser::new("!".to_string());
+        let run = |executor: &mut Executor, line: &str| match parser.parse(line) {
+            crate::parser::ParseResult::Commands(commands) => {
+                executor.execute_pipeline(commands).unwrap().exit_code
+            }
+            other => panic!("Expected Commands, got: {:?}", other),
+        };
+
+        let touch = format!("touch {}", marker.display());
+        assert_ne!(run(&mut executor, &format!("false && {}", touch)), 0);
+        assert!(!marker.exists());
+        assert_eq!(run(&mut executor, &format!("true && {}", touch)), 0);
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn test_incognito_round_trip_keeps_store_consistent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history").to_string_lossy().to_string();
+        let history: SharedHistory = std::sync::Arc::new(std::sync::Mutex::new(
+            History::new(path.clone(), 100).unwrap(),
+        ));
+        let mut executor = Executor::new();
+        executor.set_history(std::sync::Arc::clone(&history));
+        let parser = crate::parser::Parser::new("!".to_string());
+
+        // What the REPL does for each line
+        let mut run = |line: &str| {
+            history::lock(&history)
+                .add(HistoryEntry::new(line.to_string()))
+                .unwrap();
+            let crate::parser::ParseResult::Commands(commands) = parser.parse(line) else {
+                panic!("Expected Commands for {}", line);
+            };
+            let code = executor.execute_pipeline(commands).unwrap().exit_code;
+            history::lock(&history)
+                .finish_last(code, std::time::Duration::from_millis(5))
+                .unwrap();
+        };
+        run("incognito on");
+        run("echo secret > /dev/null");
+        run("incognito off");
+        run("false");
+
+        let stored = History::new(path, 100).unwrap();
+        let stored: Vec<(&str, Option<i32>)> = stored
+            .get_recent(100)
+            .into_iter()
+            .map(|e| (e.command.as_str(), e.exit_code))
+            .collect();
+        assert_eq!(
+            stored,
+            [
+                ("incognito on", Some(0)),
+                ("incognito off", Some(0)),
+                ("false", Some(1))
+            ]
+        );
+    }
+
     #[test]
     fn test_pipeline_exit_code_propagation() {
         let mut executor = Executor::new();
@@ -721,6 +1108,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: None,
                 stdout_append: false,
+                and_then: false,
             },
             Command {
                 program: "grep".to_string(),
@@ -728,6 +1116,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: None,
                 stdout_append: false,
+                and_then: false,
             },
         ];
 
@@ -749,6 +1138,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: None,
                 stdout_append: false,
+                and_then: false,
             },
             Command {
                 program: "nonexistent_command_12345".to_string(),
@@ -756,6 +1146,7 @@ mod tests {
                 stdin_redirect: None,
                 stdout_redirect: None,
                 stdout_append: false,
+                and_then: false,
             },
         ];
 
@@ -784,6 +1175,7 @@ mod tests {
             stdin_redirect: None,
             stdout_redirect: Some(output_path.to_string_lossy().to_string()),
             stdout_append: false,
+            and_then: false,
         }];
 
         let result = executor.execute_external_pipeline(commands).unwrap();
@@ -809,6 +1201,7 @@ mod tests {
             stdin_redirect: None,
             stdout_redirect: Some(output_path.to_string_lossy().to_string()),
             stdout_append: false,
+            and_then: false,
         }];
         executor.execute_external_pipeline(commands1).unwrap();
 
@@ -819,6 +1212,7 @@ mod tests {
             stdin_redirect: None,
             stdout_redirect: Some(output_path.to_string_lossy().to_string()),
             stdout_append: true,
+            and_then: false,
         }];
         executor.execute_external_pipeline(commands2).unwrap();
 
@@ -0,0 +1,555 @@
+//! External completion module for Fool Shell
+//! Reads fish `complete -c` declarations into completion specs and asks
+//! programs that can complete themselves (`gh __complete ...`)
+
+use crate::compspec::{output_lines, Provider, Source, Spec, SubSpec};
+use crate::config::{expand_path, CompletionConfig};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long a provider's answer is reused for the same words
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Candidates by the words before the one being completed
+type ProviderCache = HashMap<Vec<String>, (Instant, Arc<Vec<String>>)>;
+
+/// Programs configured in `[completion.external]`
+pub struct ExternalProviders {
+    commands: BTreeMap<String, String>,
+    timeout: Duration,
+    cache: Mutex<ProviderCache>,
+}
+
+impl ExternalProviders {
+    pub fn new(config: &CompletionConfig) -> Self {
+        Self {
+            commands: config.external.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Candidates for `word`, or None if `command` has no provider or it
+    /// offered nothing
+    pub fn complete(&self, command: &str, args: &[&str], word: &str) -> Option<Vec<String>> {
+        let program = self.commands.get(command)?;
+        let mut key = vec![command.to_string()];
+        key.extend(args.iter().map(|arg| arg.to_string()));
+
+        let cached = {
+            let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache
+                .get(&key)
+                .filter(|(at, _)| at.elapsed() < CACHE_TTL)
+                .map(|(_, words)| Arc::clone(words))
+        };
+        let words = match cached {
+            Some(words) => words,
+            None => {
+                // The provider sees an empty current word so one answer
+                // serves every prefix typed afterwards
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c")
+                    .arg(format!("{} \"$@\"", program))
+                    .arg("sh")
+                    .args(args)
+                    .arg("");
+                let words = Arc::new(parse_provider_output(&output_lines(cmd, self.timeout)));
+                let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+                cache.insert(key, (Instant::now(), Arc::clone(&words)));
+                words
+            }
+        };
+
+        let matching: Vec<String> = words
+            .iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .cloned()
+            .collect();
+        (!matching.is_empty()).then_some(matching)
+    }
+}
+
+/// `candidate` or `candidate<TAB>description` lines; cobra's `:directive`
+/// line is skipped
+fn parse_provider_output(lines: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    lines
+        .iter()
+        .filter(|line| !line.starts_with(':'))
+        .filter_map(|line| line.split('\t').next())
+        .map(str::trim)
+        .filter(|candidate| !candidate.is_empty() && seen.insert(*candidate))
+        .map(String::from)
+        .collect()
+}
+
+/// The first `COMMAND.fish` in `dirs`
+pub fn find_fish_file(dirs: &[PathBuf], command: &str) -> Option<PathBuf> {
+    // Keeps "../x" and the like from escaping the directories
+    if command.contains(['/', '\\']) || command.starts_with('.') {
+        return None;
+    }
+    dirs.iter()
+        .map(|dir| dir.join(format!("{}.fish", command)))
+        .find(|path| path.is_file())
+}
+
+/// Fish completion directories from the config, with `~` expanded
+pub fn fish_dirs(config: &CompletionConfig) -> Vec<PathBuf> {
+    if !config.fish {
+        return Vec::new();
+    }
+    config
+        .fish_dirs
+        .iter()
+        .map(|dir| expand_path(dir))
+        .collect()
+}
+
+/// Read a fish completion file
+pub fn load_fish_spec(path: &Path, command: &str) -> Option<Spec> {
+    let content = std::fs::read_to_string(path).ok()?;
+    Some(parse_fish(&content, command))
+}
+
+/// One `complete` declaration
+#[derive(Debug, Default)]
+struct Declaration {
+    commands: Vec<String>,
+    options: Vec<String>,
+    arguments: Vec<String>,
+    condition: Option<String>,
+    requires_value: bool,
+    no_files: bool,
+}
+
+/// Which part of the command line a declaration applies to
+#[derive(Debug, PartialEq)]
+enum Scope {
+    Root,
+    /// Offered where a subcommand is expected: the arguments are subcommands
+    SubcommandPosition,
+    /// After one of these subcommands
+    Subcommands(Vec<String>),
+}
+
+/// A spec being built, with fish's "no files" switch kept separately
+#[derive(Default)]
+struct FishSpec {
+    spec: Spec,
+    no_files: bool,
+    subcommands: BTreeMap<String, FishSpec>,
+}
+
+impl FishSpec {
+    fn apply(&mut self, declaration: &Declaration) {
+        let values = argument_values(&declaration.arguments);
+        let spec = &mut self.spec;
+        if declaration.options.is_empty() {
+            let args = spec.args.get_or_insert_with(Source::default);
+            args.values.extend(values);
+            self.no_files |= declaration.no_files;
+        } else if declaration.requires_value {
+            for name in &declaration.options {
+                let source = spec.options.entry(name.clone()).or_default();
+                source.values.extend(values.iter().cloned());
+                if !declaration.no_files && !source.providers.contains(&Provider::Files) {
+                    source.providers.push(Provider::Files);
+                }
+            }
+        } else {
+            for name in &declaration.options {
+                if !spec.flags.contains(name) {
+                    spec.flags.push(name.clone());
+                }
+            }
+        }
+    }
+
+    fn into_spec(mut self) -> Spec {
+        match &mut self.spec.args {
+            Some(args) if !self.no_files => args.providers.push(Provider::Files),
+            Some(_) => {}
+            // An explicit "no files" with nothing to offer
+            None if self.no_files => self.spec.args = Some(Source::default()),
+            None => {}
+        }
+        for (name, sub) in self.subcommands {
+            self.spec
+                .subcommands
+                .insert(name, SubSpec::Spec(sub.into_spec()));
+        }
+        self.spec
+    }
+}
+
+/// Turn the `complete -c COMMAND` lines of a fish file into a spec
+///
+/// Fish conditions are arbitrary code; only the common subcommand helpers
+/// are understood and other declarations apply to the whole command.
+pub fn parse_fish(content: &str, command: &str) -> Spec {
+    let mut root = FishSpec::default();
+    for line in logical_lines(content) {
+        let words = tokenize(&line);
+        if words.first().map(String::as_str) != Some("complete") {
+            continue;
+        }
+        let declaration = parse_declaration(&words[1..]);
+        if !declaration.commands.iter().any(|c| c == command) {
+            continue;
+        }
+        match scope(declaration.condition.as_deref()) {
+            Scope::SubcommandPosition if declaration.options.is_empty() => {
+                for name in argument_values(&declaration.arguments) {
+                    root.subcommands.entry(name).or_default();
+                }
+            }
+            Scope::Subcommands(names) => {
+                for name in names {
+                    root.subcommands
+                        .entry(name)
+                        .or_default()
+                        .apply(&declaration);
+                }
+            }
+            Scope::Root | Scope::SubcommandPosition => root.apply(&declaration),
+        }
+    }
+    root.into_spec()
+}
+
+fn parse_declaration(words: &[String]) -> Declaration {
+    let mut declaration = Declaration::default();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        if let Some(long) = word.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let takes_value = matches!(
+                name,
+                "command"
+                    | "short-option"
+                    | "long-option"
+                    | "old-option"
+                    | "arguments"
+                    | "description"
+                    | "condition"
+                    | "wraps"
+                    | "path"
+            );
+            let value = if takes_value {
+                inline.or_else(|| words.next().cloned()).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            declaration.set(name, value);
+        } else if let Some(shorts) = word.strip_prefix('-') {
+            for (i, flag) in shorts.char_indices() {
+                let name = match flag {
+                    'c' => "command",
+                    's' => "short-option",
+                    'l' => "long-option",
+                    'o' => "old-option",
+                    'a' => "arguments",
+                    'd' => "description",
+                    'n' => "condition",
+                    'w' => "wraps",
+                    'p' => "path",
+                    'r' => "require-parameter",
+                    'x' => "exclusive",
+                    'f' => "no-files",
+                    _ => continue,
+                };
+                if flag.is_ascii_lowercase() && "cslaodnwp".contains(flag) {
+                    // The rest of the word, or the next word, is the value
+                    let rest = &shorts[i + flag.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        words.next().cloned().unwrap_or_default()
+                    } else {
+                        rest.to_string()
+                    };
+                    declaration.set(name, value);
+                    break;
+                }
+                declaration.set(name, String::new());
+            }
+        }
+    }
+    declaration
+}
+
+impl Declaration {
+    fn set(&mut self, name: &str, value: String) {
+        match name {
+            "command" => self.commands.push(value),
+            "short-option" | "old-option" => self.options.push(format!("-{}", value)),
+            "long-option" => self.options.push(format!("--{}", value)),
+            "arguments" => self.arguments.push(value),
+            "condition" => self.condition = Some(value),
+            "require-parameter" => self.requires_value = true,
+            "exclusive" => {
+                self.requires_value = true;
+                self.no_files = true;
+            }
+            "no-files" => self.no_files = true,
+            _ => {}
+        }
+    }
+}
+
+fn scope(condition: Option<&str>) -> Scope {
+    let Some(condition) = condition else {
+        return Scope::Root;
+    };
+    let first_arg = [
+        "__fish_use_subcommand",
+        "__fish_is_first_arg",
+        "__fish_is_first_token",
+    ];
+    if first_arg.iter().any(|helper| condition.contains(helper)) {
+        return Scope::SubcommandPosition;
+    }
+    const SEEN: &str = "__fish_seen_subcommand_from";
+    let Some(at) = condition.find(SEEN) else {
+        return Scope::Root;
+    };
+    if condition[..at].trim_end().ends_with("not") {
+        return Scope::Root;
+    }
+    // The names run up to the end of that command
+    let rest = &condition[at + SEEN.len()..];
+    let rest = rest.split([';', '&', '|', ')']).next().unwrap_or_default();
+    let names: Vec<String> = rest
+        .split_whitespace()
+        .take_while(|word| {
+            !matches!(*word, "and" | "or" | "not")
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+        })
+        .map(String::from)
+        .collect();
+    if names.is_empty() {
+        Scope::Root
+    } else {
+        Scope::Subcommands(names)
+    }
+}
+
+/// Static words of `-a` arguments; command substitutions and variables
+/// would need fish to evaluate them
+fn argument_values(arguments: &[String]) -> Vec<String> {
+    arguments
+        .iter()
+        .flat_map(|argument| tokenize(argument))
+        .filter_map(|word| {
+            // `value<TAB>description`
+            let value = word
+                .split('\t')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            let dynamic = value.contains(['(', '$', '{']);
+            (!value.is_empty() && !dynamic).then_some(value)
+        })
+        .collect()
+}
+
+/// Lines with backslash continuations joined and comments removed
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        if let Some(continued) = line.strip_suffix('\\') {
+            current.push_str(continued);
+            current.push(' ');
+            continue;
+        }
+        current.push_str(line);
+        lines.push(std::mem::take(&mut current));
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Split a fish command line into words, honouring quotes and escapes
+fn tokenize(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\'' => break,
+                        '\\' if matches!(chars.peek(), Some('\'' | '\\')) => {
+                            word.extend(chars.next());
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"' | '\\' | '$')) => {
+                            word.extend(chars.next());
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some('t') => word.push('\t'),
+                    Some('n') => word.push('\n'),
+                    Some(c) => word.push(c),
+                    None => {}
+                }
+            }
+            '#' if !in_word => break,
+            ';' if !in_word => {
+                // Only the first command of the line matters here
+                break;
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compspec::SpecSet;
+
+    const FISH: &str = r#"
+# Completions for tool
+complete -c tool -f
+complete -c tool -n '__fish_use_subcommand' -xa 'deploy\t"Deploy the app" status'
+complete -c tool -n "__fish_use_subcommand" -a "logs" -d 'Show logs'
+complete -c tool -s v -l verbose -d 'Verbose output'
+complete -c tool -n '__fish_seen_subcommand_from deploy' -l env -xa 'staging production'
+complete -c tool -n '__fish_seen_subcommand_from deploy logs' -a '(__tool_services)' \
+    -a 'web worker'
+complete -c tool -n '__fish_seen_subcommand_from status' -s o -l output -r
+complete -c other -l ignored
+"#;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(r#"complete -c 'a b' -d "say \"hi\"" x\ y # comment"#),
+            ["complete", "-c", "a b", "-d", "say \"hi\"", "x y"]
+        );
+        assert_eq!(tokenize(r"add\t'Add files'"), ["add\tAdd files"]);
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let spec = parse_fish(FISH, "tool");
+        assert_eq!(spec.flags, ["-v", "--verbose"]);
+        assert_eq!(
+            spec.subcommands.keys().collect::<Vec<_>>(),
+            ["deploy", "logs", "status"]
+        );
+        // `complete -c tool -f`: no file names at the top level
+        assert!(spec.args.as_ref().is_some_and(|a| a.providers.is_empty()));
+
+        let set = SpecSet::builtin();
+        set.add_fish("tool", spec);
+        let words = |args: &[&str], word: &str| -> (Vec<String>, bool) {
+            let candidates = set.complete("tool", args, word).unwrap();
+            (candidates.words.into_iter().collect(), candidates.files)
+        };
+        assert_eq!(words(&[], "d"), (vec!["deploy".to_string()], false));
+        assert_eq!(
+            words(&["deploy", "--env"], ""),
+            (vec!["production".to_string(), "staging".to_string()], false)
+        );
+        assert_eq!(
+            words(&["logs"], "w"),
+            (vec!["web".to_string(), "worker".to_string()], true)
+        );
+        assert_eq!(words(&["status", "-o"], "x"), (vec![], true));
+    }
+
+    #[test]
+    fn test_scope() {
+        assert_eq!(scope(None), Scope::Root);
+        assert_eq!(
+            scope(Some(
+                "__fish_seen_subcommand_from a b; and not __fish_seen_argument -l x"
+            )),
+            Scope::Subcommands(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            scope(Some("not __fish_seen_subcommand_from a b")),
+            Scope::Root
+        );
+        assert_eq!(
+            scope(Some("__fish_is_first_arg")),
+            Scope::SubcommandPosition
+        );
+    }
+
+    #[test]
+    fn test_external_provider() {
+        let mut config = CompletionConfig::default();
+        // `#` drops the appended words; the script reads them as $1, $2
+        config.external.insert(
+            "tool".to_string(),
+            "printf 'one\\tdesc\\n'; echo only; echo :4; echo \"arg=$1\" #".to_string(),
+        );
+        let providers = ExternalProviders::new(&config);
+        assert_eq!(
+            providers.complete("tool", &["x"], "o"),
+            Some(vec!["one".to_string(), "only".to_string()])
+        );
+        assert_eq!(
+            providers.complete("tool", &["x"], "arg"),
+            Some(vec!["arg=x".to_string()])
+        );
+        assert_eq!(providers.complete("tool", &["x"], "z"), None);
+        assert_eq!(providers.complete("other", &[], ""), None);
+    }
+
+    #[test]
+    fn test_find_fish_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("tool.fish"), FISH).unwrap();
+        let dirs = vec![PathBuf::from("/nonexistent"), dir.path().to_path_buf()];
+        assert_eq!(
+            find_fish_file(&dirs, "tool"),
+            Some(dir.path().join("tool.fish"))
+        );
+        assert_eq!(find_fish_file(&dirs, "../tool"), None);
+    }
+}
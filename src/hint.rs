@@ -0,0 +1,162 @@
+//! Hint module for Fool Shell
+//! Suggests history completions ranked by where and how a command was last run
+
+use crate::config::HistoryHintConfig;
+use crate::context::find_git_root;
+use crate::history::{self, HistoryEntry, SharedHistory};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// History hinter that prefers commands from the current directory or git
+/// repository that succeeded, falling back to the rest of history
+pub struct ContextHinter {
+    history: SharedHistory,
+    config: HistoryHintConfig,
+    git_roots: Mutex<HashMap<PathBuf, Option<PathBuf>>>, // Cache of directory -> repo root
+}
+
+impl ContextHinter {
+    pub fn new(history: SharedHistory, config: HistoryHintConfig) -> Self {
+        Self {
+            history,
+            config,
+            git_roots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the rest of the best history command starting with `line`
+    pub fn hint(&self, line: &str) -> Option<String> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        let cwd = std::env::current_dir().ok();
+        let history = history::lock(&self.history);
+        let best = self.best_match(&history.search_prefix(line), line, cwd.as_deref())?;
+        Some(best[line.len()..].to_string())
+    }
+
+    /// Pick the highest scoring command among prefix matches (oldest first)
+    fn best_match<'a>(
+        &self,
+        candidates: &[&'a HistoryEntry],
+        line: &str,
+        cwd: Option<&Path>,
+    ) -> Option<&'a str> {
+        let cwd_root = cwd.and_then(|dir| self.git_root(dir));
+        let count = candidates.len() as f64;
+
+        let mut best: Option<(&str, f64)> = None;
+        // Newest first, so ties keep the most recent command
+        for (i, entry) in candidates.iter().enumerate().rev() {
+            if entry.command == line {
+                continue;
+            }
+
+            let mut score = self.config.recency_weight * (i + 1) as f64 / count;
+            if entry.exit_code == Some(0) {
+                score += self.config.success_weight;
+            }
+            if let Some(dir) = entry.cwd.as_deref().map(Path::new) {
+                if Some(dir) == cwd {
+                    score += self.config.same_dir_weight;
+                }
+                if cwd_root.is_some() && self.git_root(dir) == cwd_root {
+                    score += self.config.same_repo_weight;
+                }
+            }
+
+            if best.is_none_or(|(_, top)| score > top) {
+                best = Some((&entry.command, score));
+            }
+        }
+
+        best.map(|(command, _)| command)
+    }
+
+    fn git_root(&self, dir: &Path) -> Option<PathBuf> {
+        let mut cache = self.git_roots.lock().ok()?;
+        cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| find_git_root(dir))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn entry(command: &str, exit_code: i32, cwd: &Path) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(command.to_string()).with_exit_code(exit_code);
+        entry.cwd = Some(cwd.to_string_lossy().to_string());
+        entry
+    }
+
+    fn hinter() -> ContextHinter {
+        let history = Arc::new(Mutex::new(History::new_memory_only(100)));
+        ContextHinter::new(history, HistoryHintConfig::default())
+    }
+
+    #[test]
+    fn test_prefers_same_directory_then_global() {
+        let dir = tempdir().unwrap();
+        let here = dir.path().join("here");
+        let there = dir.path().join("there");
+
+        let local = entry("make run", 0, &here);
+        let global = entry("make deploy", 0, &there);
+        let candidates = vec![&local, &global];
+        let hinter = hinter();
+
+        // The newer global command loses to the one run here...
+        assert_eq!(
+            hinter.best_match(&candidates, "make", Some(&here)),
+            Some("make run")
+        );
+        // ...but is still suggested from anywhere else
+        assert_eq!(
+            hinter.best_match(&candidates, "make", Some(dir.path())),
+            Some("make deploy")
+        );
+    }
+
+    #[test]
+    fn test_prefers_same_repo_and_success() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("src")).unwrap();
+        let outside = dir.path().join("other");
+
+        let in_repo = entry("cargo test --all", 0, &repo);
+        let failed = entry("cargo test --lib", 101, &outside);
+        let elsewhere = entry("cargo test --doc", 0, &outside);
+        let candidates = vec![&in_repo, &failed, &elsewhere];
+        let hinter = hinter();
+
+        assert_eq!(
+            hinter.best_match(&candidates, "cargo t", Some(&repo.join("src"))),
+            Some("cargo test --all")
+        );
+        assert_eq!(
+            hinter.best_match(&candidates[1..], "cargo t", Some(&outside)),
+            Some("cargo test --doc")
+        );
+    }
+
+    #[test]
+    fn test_hint_returns_suffix() {
+        let hinter = hinter();
+        history::lock(&hinter.history)
+            .add(HistoryEntry::new("git status".to_string()).with_exit_code(0))
+            .unwrap();
+
+        assert_eq!(hinter.hint("git s"), Some("tatus".to_string()));
+        assert_eq!(hinter.hint("git status"), None);
+        assert_eq!(hinter.hint(""), None);
+    }
+}
@@ -1,17 +1,16 @@
 //! History module for Fool Shell
 //! Manages command history with exit codes and timestamps
 
-use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use fs2::FileExt;
+use crate::config::{HistoryBackend, HistoryConfig};
+use crate::crypt::{self, Header, RecordCipher};
+use crate::store::{HistoryStore, JsonlStore, SqliteStore};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
-use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-
-#[cfg(unix)]
-use std::os::unix::fs::OpenOptionsExt;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
 
 /// A single history entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +21,12 @@ pub struct HistoryEntry {
     pub cwd: Option<String>,
     #[serde(default)]
     pub stdout_summary: Option<String>,
+    /// Shell session that ran the command (see `session_id`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// How long the command ran, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
 }
 
 impl HistoryEntry {
@@ -34,9 +39,16 @@ impl HistoryEntry {
                 .ok()
                 .map(|p| p.to_string_lossy().to_string()),
             stdout_summary: None,
+            session_id: Some(session_id().to_string()),
+            duration_ms: None,
         }
     }
 
+    /// Whether this entry was recorded by the current shell process
+    pub fn is_from_this_session(&self) -> bool {
+        self.session_id.as_deref() == Some(session_id())
+    }
+
     #[allow(dead_code)] // Builder pattern for tests and future use
     pub fn with_exit_code(mut self, code: i32) -> Self {
         self.exit_code = Some(code);
@@ -50,114 +62,367 @@ impl HistoryEntry {
     }
 }
 
-/// History manager
-pub struct History {
-    entries: VecDeque<HistoryEntry>,
-    file_path: Option<PathBuf>, // None = memory-only mode
-    max_entries: usize,
-    entries_since_compact: usize, // Track entries added since last compaction
-    pending_entry: bool,          // Track if last entry needs exit code update
+/// Identifier of this shell process, recorded on every entry it creates
+pub fn session_id() -> &'static str {
+    static SESSION_ID: OnceLock<String> = OnceLock::new();
+    SESSION_ID.get_or_init(|| format!("{}-{:x}", std::process::id(), Utc::now().timestamp_millis()))
 }
 
-impl History {
-    /// Get the path to the lock file (sidecar file for coordinating access)
-    fn get_lock_path(file_path: &Path) -> PathBuf {
-        file_path.with_extension("lock")
-    }
+/// Output format of the `history` builtin
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryFormat {
+    #[default]
+    Plain,
+    Timestamps,
+    Json,
+}
 
-    /// Acquire exclusive lock on the sidecar lock file
-    /// M-07: Using a sidecar lock file ensures that after rename operations,
-    /// all processes still coordinate through the same persistent lock file
-    fn acquire_lock(file_path: &Path) -> Result<File> {
-        let lock_path = Self::get_lock_path(file_path);
+/// Filters for listing history; an empty query matches everything
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub contains: Option<String>,
+    pub prefix: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub failed_only: bool,
+    pub session_only: bool,
+    pub since: Option<DateTime<Utc>>,
+    pub pattern: Option<Regex>,
+    pub limit: Option<usize>, // Keep only the newest N matches
+    pub format: HistoryFormat,
+}
+
+impl HistoryQuery {
+    /// Parse the arguments of the `history` builtin
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut query = Self::default();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{} requires an argument", name))
+            };
+            match arg.as_str() {
+                "--cwd" => {
+                    let dir = crate::config::expand_path(&value("--cwd")?);
+                    query.cwd = Some(std::env::current_dir()?.join(dir));
+                }
+                "--here" => query.cwd = Some(std::env::current_dir()?),
+                "--failed" => query.failed_only = true,
+                "--session" => query.session_only = true,
+                "--since" => {
+                    let since = value("--since")?;
+                    query.since = Some(Utc::now() - parse_duration(&since)?);
+                }
+                "--grep" => {
+                    let pattern = value("--grep")?;
+                    query.pattern = Some(
+                        Regex::new(&pattern)
+                            .with_context(|| format!("invalid regex '{}'", pattern))?,
+                    );
+                }
+                "--prefix" => query.prefix = Some(value("--prefix")?),
+                "-n" => {
+                    let count = value("-n")?;
+                    query.limit = Some(
+                        count
+                            .parse()
+                            .map_err(|_| anyhow!("invalid count '{}'", count))?,
+                    );
+                }
+                "-t" | "--time" => query.format = HistoryFormat::Timestamps,
+                "--json" => query.format = HistoryFormat::Json,
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    bail!("unknown option '{}'", arg)
+                }
+                _ => {
+                    // Remaining words form one substring, like `history git push`
+                    let text = match query.contains.take() {
+                        Some(text) => format!("{} {}", text, arg),
+                        None => arg.clone(),
+                    };
+                    query.contains = Some(text);
+                }
+            }
+        }
 
-        let mut lock_options = OpenOptions::new();
-        lock_options.read(true).write(true).create(true);
+        Ok(query)
+    }
 
-        #[cfg(unix)]
-        lock_options.mode(0o600);
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        if let Some(text) = &self.contains {
+            if !entry.command.contains(text.as_str()) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.prefix {
+            if !entry.command.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(dir) = &self.cwd {
+            // Path equality ignores trailing slashes
+            if entry.cwd.as_deref().map(Path::new) != Some(dir.as_path()) {
+                return false;
+            }
+        }
+        if self.failed_only && !matches!(entry.exit_code, Some(code) if code != 0) {
+            return false;
+        }
+        if self.session_only && !entry.is_from_this_session() {
+            return false;
+        }
+        if let Some(since) = self.since {
+            if entry.timestamp < since {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&entry.command) {
+                return false;
+            }
+        }
+        true
+    }
+}
 
-        let lock_file = lock_options
-            .open(&lock_path)
-            .with_context(|| format!("Failed to open lock file: {:?}", lock_path))?;
+/// Parse a relative duration such as `90s`, `30m`, `2h`, `7d` or `1w`
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration '{}' (use e.g. 30m, 2h, 7d)", text);
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = text.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+    let duration = match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    };
+    duration.ok_or_else(invalid)
+}
 
-        lock_file
-            .lock_exclusive()
-            .with_context(|| format!("Failed to acquire lock: {:?}", lock_path))?;
+/// Format a UTC timestamp in local time for display
+pub fn format_local_time(timestamp: &DateTime<Utc>) -> String {
+    timestamp
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
 
-        Ok(lock_file)
+/// Compact duration such as "850ms", "3.2s", "4m 05s" or "1h 02m"
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let ms = duration.as_millis();
+    let secs = duration.as_secs();
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
     }
+}
 
-    pub fn new(file_path: String, max_entries: usize) -> Result<Self> {
-        let file_path = Self::expand_path(&file_path);
+/// History shared between the REPL, the executor and the line editor helpers
+pub type SharedHistory = Arc<Mutex<History>>;
 
-        // Ensure parent directory exists
-        if let Some(parent) = file_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create history directory: {:?}", parent))?;
+/// Lock shared history; a panic elsewhere never makes history unusable
+pub fn lock(history: &SharedHistory) -> MutexGuard<'_, History> {
+    history.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Rules for keeping commands out of history
+#[derive(Debug, Clone, Default)]
+pub struct HistoryPrivacy {
+    pub ignore_patterns: Vec<Regex>,
+    pub ignore_space: bool,
+    pub ignore_dups: bool,
+    pub erase_dups: bool,
+}
+
+impl HistoryPrivacy {
+    pub fn from_config(config: &HistoryConfig) -> Self {
+        Self {
+            // Invalid patterns were already dropped with a warning by config validation
+            ignore_patterns: config
+                .ignore_patterns
+                .iter()
+                .filter_map(|p| Regex::new(p).ok())
+                .collect(),
+            ignore_space: config.ignore_space,
+            ignore_dups: config.ignore_dups,
+            erase_dups: config.erase_dups,
         }
+    }
+}
 
-        let mut history = Self {
-            entries: VecDeque::with_capacity(max_entries),
-            file_path: Some(file_path),
+/// History manager
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    store: Option<Box<dyn HistoryStore>>, // None = memory-only mode
+    max_entries: usize,
+    entries_since_compact: usize, // Track entries added since last compaction
+    pending_entry: bool,          // Track if last entry needs exit code update
+    privacy: HistoryPrivacy,
+}
+
+impl History {
+    /// Open history with the backend selected in the config
+    pub fn open(config: &HistoryConfig) -> Result<Self> {
+        let file_path = crate::config::expand_path(&config.file_path);
+        let store: Box<dyn HistoryStore> = match config.backend {
+            HistoryBackend::Jsonl if config.encryption.enabled => {
+                let header = Header::read(&file_path)?;
+                let secret = crypt::load_secret(&config.encryption, header.is_none())?;
+                let cipher = match &header {
+                    Some(header) => RecordCipher::unlock(header, &secret)?,
+                    None => RecordCipher::create(&secret)?,
+                };
+                let (store, converted) = JsonlStore::open_encrypted(file_path.clone(), cipher)?;
+                if converted > 0 {
+                    eprintln!("Encrypted {} history entries in {:?}", converted, file_path);
+                }
+                Box::new(store)
+            }
+            HistoryBackend::Jsonl => Box::new(JsonlStore::open(file_path)?),
+            HistoryBackend::Sqlite => {
+                let db_path = crate::config::expand_path(&config.db_path);
+                let mut store = SqliteStore::open(db_path.clone(), config.max_entries)?;
+                let imported = store.migrate_jsonl(&file_path)?;
+                if imported > 0 {
+                    eprintln!(
+                        "Imported {} history entries from {:?} into {:?}",
+                        imported, file_path, db_path
+                    );
+                }
+                Box::new(store)
+            }
+        };
+        let mut history = Self::with_store(store, config.max_entries)?;
+        history.privacy = HistoryPrivacy::from_config(config);
+        Ok(history)
+    }
+
+    /// Open a JSONL history file
+    #[allow(dead_code)] // Used in tests
+    pub fn new(file_path: String, max_entries: usize) -> Result<Self> {
+        let store = JsonlStore::open(crate::config::expand_path(&file_path))?;
+        Self::with_store(Box::new(store), max_entries)
+    }
+
+    /// Create history backed by `store`, loading its newest entries
+    pub fn with_store(mut store: Box<dyn HistoryStore>, max_entries: usize) -> Result<Self> {
+        let entries = store.load_recent(max_entries)?;
+        Ok(Self {
+            entries: entries.into(),
+            store: Some(store),
             max_entries,
             entries_since_compact: 0,
             pending_entry: false,
-        };
-
-        history.load()?;
-        Ok(history)
+            privacy: HistoryPrivacy::default(),
+        })
     }
 
     /// Create a memory-only history (no file persistence)
     pub fn new_memory_only(max_entries: usize) -> Self {
         Self {
             entries: VecDeque::with_capacity(max_entries),
-            file_path: None,
+            store: None,
             max_entries,
             entries_since_compact: 0,
             pending_entry: false,
+            privacy: HistoryPrivacy::default(),
         }
     }
 
-    fn expand_path(path: &str) -> PathBuf {
-        if let Some(stripped) = path.strip_prefix("~/") {
-            if let Some(home) = dirs::home_dir() {
-                return home.join(stripped);
-            }
+    /// A memory-only copy of this history for incognito mode
+    pub fn to_memory_only(&self) -> Self {
+        let mut history = Self::new_memory_only(self.max_entries);
+        history.entries = self.entries.clone();
+        history.pending_entry = self.pending_entry;
+        history.privacy = self.privacy.clone();
+        history
+    }
+
+    /// Take the entry still waiting for `finish_last`, if any
+    ///
+    /// Switching incognito mode swaps histories while the switching command
+    /// is pending; it is handed over so it isn't finished in the wrong one.
+    pub fn take_pending(&mut self) -> Option<HistoryEntry> {
+        if !self.pending_entry {
+            return None;
         }
-        PathBuf::from(path)
+        self.pending_entry = false;
+        self.entries.pop_back()
     }
 
-    /// Load history from file
-    fn load(&mut self) -> Result<()> {
-        let file_path = match &self.file_path {
-            Some(path) => path,
-            None => return Ok(()), // Memory-only mode, skip loading
-        };
+    /// Finish the pending entry with `code`, leaving finished ones alone
+    pub fn finish_pending(&mut self, code: i32) -> Result<()> {
+        if self.pending_entry {
+            self.update_last_exit_code(code)?;
+        }
+        Ok(())
+    }
 
-        if !file_path.exists() {
-            return Ok(());
+    /// Start `entry` as the pending entry, without writing it yet
+    pub fn restore_pending(&mut self, entry: HistoryEntry) {
+        self.entries.push_back(entry);
+        if self.entries.len() > self.max_entries {
+            self.entries.pop_front();
         }
+        self.pending_entry = true;
+    }
 
-        let file = File::open(file_path)
-            .with_context(|| format!("Failed to open history file: {:?}", file_path))?;
-        let reader = BufReader::new(file);
+    /// Whether persistence is switched off
+    pub fn is_memory_only(&self) -> bool {
+        self.store.is_none()
+    }
 
-        for line in reader.lines().map_while(Result::ok) {
-            if let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) {
-                self.entries.push_back(entry);
-                if self.entries.len() > self.max_entries {
-                    self.entries.pop_front();
-                }
-            }
+    #[allow(dead_code)] // Used in tests
+    pub fn set_privacy(&mut self, privacy: HistoryPrivacy) {
+        self.privacy = privacy;
+    }
+
+    /// Whether `raw_line`, exactly as typed, may be recorded
+    pub fn should_record(&self, raw_line: &str) -> bool {
+        if self.privacy.ignore_space && raw_line.starts_with(char::is_whitespace) {
+            return false;
+        }
+        let command = raw_line.trim();
+        if self
+            .privacy
+            .ignore_patterns
+            .iter()
+            .any(|p| p.is_match(command))
+        {
+            return false;
+        }
+        if self.privacy.ignore_dups && self.last().is_some_and(|e| e.command == command) {
+            return false;
         }
+        true
+    }
 
-        Ok(())
+    /// Whether what `raw_line` produces, such as an AI answer, may be written
+    /// to disk: not when the line itself is kept out of history or while
+    /// history is memory-only (incognito)
+    pub fn should_persist(&self, raw_line: &str) -> bool {
+        !self.is_memory_only() && self.should_record(raw_line)
     }
 
     /// Add a new entry to history (without exit code initially)
     pub fn add(&mut self, entry: HistoryEntry) -> Result<()> {
+        if self.privacy.erase_dups {
+            self.erase_command(&entry.command)?;
+        }
+
         // Add to memory first
         self.entries.push_back(entry);
         if self.entries.len() > self.max_entries {
@@ -172,7 +437,7 @@ impl History {
 
         // Compact file periodically to prevent unbounded growth
         // Trigger compaction every max_entries additions to keep file size reasonable
-        if self.file_path.is_some() && self.entries_since_compact >= self.max_entries {
+        if self.store.is_some() && self.entries_since_compact >= self.max_entries {
             self.compact()?;
             self.entries_since_compact = 0;
         }
@@ -180,6 +445,74 @@ impl History {
         Ok(())
     }
 
+    /// Remove earlier copies of `command` from memory and storage
+    fn erase_command(&mut self, command: &str) -> Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.command != command);
+        // Storage is only rewritten when a copy is in memory, so repeating a
+        // new command stays cheap; copies older than the window are left alone
+        if self.entries.len() < before {
+            if let Some(store) = self.store.as_mut() {
+                store.remove_command(command)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the entry shown as `number` by the `history` builtin
+    pub fn delete(&mut self, number: usize) -> Result<HistoryEntry> {
+        let index = number
+            .checked_sub(1)
+            .filter(|i| *i < self.entries.len())
+            .ok_or_else(|| anyhow!("{}: history position out of range", number))?;
+        if self.pending_entry && index == self.entries.len() - 1 {
+            bail!("{}: cannot delete the running command", number);
+        }
+
+        let removed = self
+            .entries
+            .remove(index)
+            .ok_or_else(|| anyhow!("{}: history position out of range", number))?;
+        if let Some(store) = self.store.as_mut() {
+            store.remove(&removed)?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete every entry whose command matches `pattern`, returning how many
+    /// were removed (from storage when history is persisted)
+    pub fn delete_matching(&mut self, pattern: &Regex) -> Result<usize> {
+        // The running command stays so its exit code can still be recorded
+        let keep_last = self.pending_entry as usize;
+        let window = self.entries.len() - keep_last.min(self.entries.len());
+        let mut index = 0;
+        let mut removed = 0;
+        self.entries.retain(|e| {
+            index += 1;
+            let delete = index <= window && pattern.is_match(&e.command);
+            removed += delete as usize;
+            !delete
+        });
+
+        match self.store.as_mut() {
+            Some(store) => store.remove_matching(pattern),
+            None => Ok(removed),
+        }
+    }
+
+    /// Merge entries from another shell by timestamp, skipping ones already
+    /// recorded, and return how many were added
+    pub fn import(&mut self, entries: &[HistoryEntry]) -> Result<usize> {
+        let store = self
+            .store
+            .as_mut()
+            .ok_or_else(|| anyhow!("history is not being saved"))?;
+        let added = store.merge(entries)?;
+        self.entries = store.load_recent(self.max_entries)?.into();
+        self.pending_entry = false;
+        Ok(added)
+    }
+
     /// Get recent entries for AI context
     pub fn get_recent(&self, count: usize) -> Vec<&HistoryEntry> {
         self.entries
@@ -197,66 +530,103 @@ impl History {
         self.entries.iter().map(|e| e.command.as_str()).collect()
     }
 
+    /// The entry with 1-based history number `number`
+    pub fn get(&self, number: usize) -> Option<&HistoryEntry> {
+        self.entries.get(number.checked_sub(1)?)
+    }
+
     /// Search history by prefix
-    #[allow(dead_code)] // Public API for history search
     pub fn search_prefix(&self, prefix: &str) -> Vec<&HistoryEntry> {
-        self.entries
-            .iter()
-            .filter(|e| e.command.starts_with(prefix))
-            .collect()
+        let query = HistoryQuery {
+            prefix: Some(prefix.to_string()),
+            ..HistoryQuery::default()
+        };
+        self.query(&query).into_iter().map(|(_, e)| e).collect()
     }
 
     /// Search history by substring
     #[allow(dead_code)] // Used in tests
     pub fn search(&self, query: &str) -> Vec<&HistoryEntry> {
-        self.entries
+        let query = HistoryQuery {
+            contains: Some(query.to_string()),
+            ..HistoryQuery::default()
+        };
+        self.query(&query).into_iter().map(|(_, e)| e).collect()
+    }
+
+    /// Entries matching `query`, oldest first, with their 1-based history numbers
+    pub fn query(&self, query: &HistoryQuery) -> Vec<(usize, &HistoryEntry)> {
+        let mut matches: Vec<(usize, &HistoryEntry)> = self
+            .entries
             .iter()
-            .filter(|e| e.command.contains(query))
-            .collect()
+            .enumerate()
+            .filter(|(_, e)| query.matches(e))
+            .map(|(i, e)| (i + 1, e))
+            .collect();
+
+        if let Some(limit) = query.limit {
+            let skip = matches.len().saturating_sub(limit);
+            matches.drain(..skip);
+        }
+        matches
     }
 
     /// Get the last entry
-    #[allow(dead_code)] // Public API for history access
     pub fn last(&self) -> Option<&HistoryEntry> {
         self.entries.back()
     }
 
+    /// Merge entries other sessions saved since the last sync
+    ///
+    /// Returns the merged entries so the line editor's history can follow.
+    pub fn sync(&mut self) -> Result<Vec<HistoryEntry>> {
+        // An unfinished entry has to stay last; pick the rest up next time
+        if self.pending_entry {
+            return Ok(Vec::new());
+        }
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return Ok(Vec::new()),
+        };
+
+        let merged: Vec<HistoryEntry> = store
+            .load_new()?
+            .into_iter()
+            .filter(|e| !e.is_from_this_session())
+            .collect();
+        for entry in &merged {
+            self.entries.push_back(entry.clone());
+            if self.entries.len() > self.max_entries {
+                self.entries.pop_front();
+            }
+        }
+        Ok(merged)
+    }
+
     /// Get the last entry mutably
     pub fn last_mut(&mut self) -> Option<&mut HistoryEntry> {
         self.entries.back_mut()
     }
 
+    /// Record how the last command ended and write the complete entry to disk
+    pub fn finish_last(&mut self, code: i32, duration: std::time::Duration) -> Result<()> {
+        if self.pending_entry {
+            if let Some(entry) = self.entries.back_mut() {
+                entry.duration_ms = Some(duration.as_millis() as u64);
+            }
+        }
+        self.update_last_exit_code(code)
+    }
+
     /// Update the exit code of the last entry and write complete entry to disk
     pub fn update_last_exit_code(&mut self, code: i32) -> Result<()> {
         if let Some(entry) = self.entries.back_mut() {
             entry.exit_code = Some(code);
 
             // Now write the complete entry to disk (append-only)
-            if let Some(file_path) = &self.file_path {
+            if let Some(store) = self.store.as_mut() {
                 if self.pending_entry {
-                    // M-07: Acquire lock via sidecar lock file
-                    let _lock_file = Self::acquire_lock(file_path)?;
-
-                    let mut options = OpenOptions::new();
-                    options.create(true).append(true);
-
-                    // Set secure permissions (0o600) on Unix to protect command history
-                    #[cfg(unix)]
-                    options.mode(0o600);
-
-                    let mut file = options.open(file_path).with_context(|| {
-                        format!("Failed to open history file for writing: {:?}", file_path)
-                    })?;
-
-                    let json = serde_json::to_string(&entry)
-                        .with_context(|| "Failed to serialize history entry")?;
-                    writeln!(file, "{}", json).with_context(|| "Failed to write history entry")?;
-
-                    // Ensure data is flushed before releasing lock
-                    file.flush()
-                        .with_context(|| "Failed to flush history entry")?;
-
-                    // Lock released when _lock_file is dropped
+                    store.append(entry)?;
                     self.pending_entry = false;
                 }
             }
@@ -280,63 +650,30 @@ impl History {
     #[allow(dead_code)] // Public API for history management
     pub fn clear(&mut self) -> Result<()> {
         self.entries.clear();
-        if let Some(file_path) = &self.file_path {
-            if file_path.exists() {
-                fs::remove_file(file_path)
-                    .with_context(|| format!("Failed to remove history file: {:?}", file_path))?;
-            }
+        if let Some(store) = self.store.as_mut() {
+            store.clear()?;
         }
         Ok(())
     }
 
-    /// Compact history file (remove old entries and rewrite)
+    /// Drop old entries from storage
     /// M-10 FIX: Skip pending entry to avoid duplicate writes
-    /// M-07 FIX: Use sidecar lock file to prevent concurrent compaction conflicts
     pub fn compact(&mut self) -> Result<()> {
-        let file_path = match &self.file_path {
-            Some(path) => path,
+        let store = match self.store.as_mut() {
+            Some(store) => store,
             None => return Ok(()), // Memory-only mode, nothing to compact
         };
 
-        // M-07: Acquire exclusive lock via sidecar lock file
-        // Using a sidecar file ensures all processes coordinate through the same
-        // persistent lock file even after rename operations
-        let _lock_file = Self::acquire_lock(file_path)?;
-
-        let temp_path = file_path.with_extension("tmp");
-
-        {
-            // Create temp file with secure permissions (0o600)
-            let mut options = OpenOptions::new();
-            options.create(true).write(true).truncate(true);
-
-            #[cfg(unix)]
-            options.mode(0o600);
-
-            let mut file = options
-                .open(&temp_path)
-                .with_context(|| format!("Failed to create temp history file: {:?}", temp_path))?;
-
-            // M-10 FIX: If there's a pending entry (last one without exit code),
-            // skip it during compaction to avoid writing it twice
-            let entries_to_write = if self.pending_entry && !self.entries.is_empty() {
-                self.entries.len() - 1
-            } else {
-                self.entries.len()
-            };
-
-            for entry in self.entries.iter().take(entries_to_write) {
-                let json = serde_json::to_string(entry)?;
-                writeln!(file, "{}", json)?;
-            }
-
-            file.flush()?;
-        }
-
-        fs::rename(&temp_path, file_path).with_context(|| "Failed to rename temp history file")?;
+        // M-10 FIX: If there's a pending entry (last one without exit code),
+        // skip it during compaction to avoid writing it twice
+        let entries_to_write = if self.pending_entry && !self.entries.is_empty() {
+            self.entries.len() - 1
+        } else {
+            self.entries.len()
+        };
 
-        // Lock released when _lock_file is dropped
-        Ok(())
+        let window: Vec<&HistoryEntry> = self.entries.iter().take(entries_to_write).collect();
+        store.compact(&window)
     }
 
     /// Format history entries for AI context
@@ -421,4 +758,139 @@ mod tests {
         let results = history.search("git");
         assert_eq!(results.len(), 2);
     }
+
+    #[test]
+    fn test_history_query_filters() {
+        let mut history = History::new_memory_only(100);
+        let mut old = HistoryEntry::new("cargo build".to_string()).with_exit_code(101);
+        old.timestamp = Utc::now() - Duration::days(3);
+        old.cwd = Some("/work/fool".to_string());
+        history.add(old).unwrap();
+
+        let mut recent = HistoryEntry::new("cargo test".to_string()).with_exit_code(0);
+        recent.cwd = Some("/work/fool".to_string());
+        history.add(recent).unwrap();
+        history
+            .add(HistoryEntry::new("cargo fmt".to_string()).with_exit_code(1))
+            .unwrap();
+
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+
+        let query = HistoryQuery::from_args(&args("--failed")).unwrap();
+        let numbers: Vec<usize> = history.query(&query).iter().map(|(n, _)| *n).collect();
+        assert_eq!(numbers, vec![1, 3]);
+
+        let query = HistoryQuery::from_args(&args("--since 2h --cwd /work/fool/")).unwrap();
+        let results = history.query(&query);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1.command, "cargo test");
+
+        let query = HistoryQuery::from_args(&args("--grep ^cargo\\s+(t|f) -n 1 --json")).unwrap();
+        assert_eq!(query.format, HistoryFormat::Json);
+        let results = history.query(&query);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, 3);
+    }
+
+    #[test]
+    fn test_history_sync_merges_other_sessions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history").to_string_lossy().to_string();
+        let mut ours = History::new(path.clone(), 100).unwrap();
+        let mut theirs = History::new(path, 100).unwrap();
+
+        ours.add(HistoryEntry::new("echo ours".to_string()))
+            .unwrap();
+        ours.update_last_exit_code(0).unwrap();
+
+        let mut other = HistoryEntry::new("echo theirs".to_string());
+        other.session_id = Some("other-session".to_string());
+        theirs.add(other).unwrap();
+        theirs.update_last_exit_code(0).unwrap();
+
+        let merged = ours.sync().unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].command, "echo theirs");
+        assert_eq!(ours.get_all_commands(), vec!["echo ours", "echo theirs"]);
+        assert!(ours.sync().unwrap().is_empty());
+
+        let query = HistoryQuery::from_args(&["--session".to_string()]).unwrap();
+        assert_eq!(ours.query(&query).len(), 1);
+    }
+
+    #[test]
+    fn test_history_privacy() {
+        let mut history = History::new_memory_only(100);
+        history.set_privacy(HistoryPrivacy {
+            ignore_patterns: vec![Regex::new("^export .*TOKEN").unwrap()],
+            ignore_space: true,
+            ignore_dups: true,
+            erase_dups: false,
+        });
+
+        assert!(!history.should_record(" secret-command"));
+        assert!(!history.should_record("export GH_TOKEN=abc"));
+        assert!(history.should_record("ls"));
+        history.add(HistoryEntry::new("ls".to_string())).unwrap();
+        assert!(!history.should_record("ls"));
+        assert!(history.should_record("pwd"));
+    }
+
+    #[test]
+    fn test_should_persist() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history").to_string_lossy().to_string();
+        let mut history = History::new(path, 100).unwrap();
+        history.set_privacy(HistoryPrivacy {
+            ignore_patterns: vec![Regex::new("password").unwrap()],
+            ignore_space: true,
+            ..HistoryPrivacy::default()
+        });
+
+        assert!(history.should_persist("! how do I rotate logs"));
+        assert!(!history.should_persist(" ! how do I rotate logs"));
+        assert!(!history.should_persist("! reset my password"));
+        // Incognito swaps in a memory-only copy
+        let incognito = history.to_memory_only();
+        assert!(!incognito.should_persist("! how do I rotate logs"));
+    }
+
+    #[test]
+    fn test_history_delete_and_erase_dups() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history").to_string_lossy().to_string();
+        let mut history = History::new(path.clone(), 100).unwrap();
+        history.set_privacy(HistoryPrivacy {
+            erase_dups: true,
+            ..HistoryPrivacy::default()
+        });
+
+        for command in ["make", "curl -H 'Authorization: x'", "ls", "make"] {
+            history.add(HistoryEntry::new(command.to_string())).unwrap();
+            history.update_last_exit_code(0).unwrap();
+        }
+        assert_eq!(
+            history.get_all_commands(),
+            vec!["curl -H 'Authorization: x'", "ls", "make"]
+        );
+
+        let pattern = Regex::new("Authorization").unwrap();
+        assert_eq!(history.delete_matching(&pattern).unwrap(), 1);
+        assert_eq!(history.delete(1).unwrap().command, "ls");
+        assert!(history.delete(5).is_err());
+
+        // The file was rewritten too
+        let reloaded = History::new(path, 100).unwrap();
+        assert_eq!(reloaded.get_all_commands(), vec!["make"]);
+    }
+
+    #[test]
+    fn test_history_query_errors() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert!(HistoryQuery::from_args(&args("--since")).is_err());
+        assert!(HistoryQuery::from_args(&args("--since 2y")).is_err());
+        assert!(HistoryQuery::from_args(&args("--grep (")).is_err());
+        assert!(HistoryQuery::from_args(&args("--bogus")).is_err());
+        assert_eq!(parse_duration("90m").unwrap(), Duration::minutes(90));
+    }
 }
@@ -0,0 +1,450 @@
+//! Interop module for Fool Shell
+//! Imports history from bash, zsh and fish, and exports it back to their formats
+
+use crate::config::Config;
+use crate::history::{History, HistoryEntry};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// zsh escapes bytes that clash with its internal tokens in history files
+const ZSH_META: u8 = 0x83;
+
+/// A command read from another shell's history file
+#[derive(Debug, Default)]
+struct Imported {
+    timestamp: Option<i64>,
+    duration_secs: Option<u64>, // Only zsh records it
+    command: String,
+}
+
+impl Imported {
+    fn new(timestamp: Option<i64>, command: String) -> Self {
+        Self {
+            timestamp,
+            command,
+            ..Self::default()
+        }
+    }
+}
+
+/// A shell whose history file format we understand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => bail!("unknown shell '{}' (expected bash, zsh or fish)", name),
+        }
+    }
+
+    /// Where the shell keeps its history by default
+    fn default_path(self) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        Some(match self {
+            Self::Bash => home.join(".bash_history"),
+            Self::Zsh => std::env::var_os("ZDOTDIR")
+                .map(PathBuf::from)
+                .unwrap_or(home)
+                .join(".zsh_history"),
+            // fish uses XDG paths on every platform
+            Self::Fish => std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".local/share"))
+                .join("fish/fish_history"),
+        })
+    }
+
+    /// Parse a history file; commands without a timestamp are given ones just
+    /// before `fallback`, keeping their order
+    pub fn read(self, bytes: &[u8], fallback: DateTime<Utc>) -> Vec<HistoryEntry> {
+        let commands = match self {
+            Self::Bash => parse_bash(&String::from_utf8_lossy(bytes)),
+            Self::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(bytes))),
+            Self::Fish => parse_fish(&String::from_utf8_lossy(bytes)),
+        };
+        into_entries(commands, fallback)
+    }
+
+    /// Format entries as this shell's history file
+    pub fn write(self, entries: &[&HistoryEntry]) -> Vec<u8> {
+        let mut out = String::new();
+        for entry in entries {
+            let timestamp = entry.timestamp.timestamp();
+            match self {
+                // Timestamps are read back when HISTTIMEFORMAT is set
+                Self::Bash => out.push_str(&format!("#{}\n{}\n", timestamp, entry.command)),
+                Self::Zsh => out.push_str(&format!(
+                    ": {}:{};{}\n",
+                    timestamp,
+                    entry.duration_ms.unwrap_or(0) / 1000,
+                    entry.command.replace('\n', "\\\n")
+                )),
+                Self::Fish => out.push_str(&format!(
+                    "- cmd: {}\n  when: {}\n",
+                    escape_fish(&entry.command),
+                    timestamp
+                )),
+            }
+        }
+
+        match self {
+            Self::Zsh => metafy(out.as_bytes()),
+            _ => out.into_bytes(),
+        }
+    }
+}
+
+/// `#1700000000` lines written by bash when HISTTIMEFORMAT is set
+fn bash_timestamp(line: &str) -> Option<i64> {
+    let digits = line.strip_prefix('#')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn parse_bash(text: &str) -> Vec<Imported> {
+    let mut commands: Vec<Imported> = Vec::new();
+    for line in text.lines() {
+        if let Some(timestamp) = bash_timestamp(line) {
+            commands.push(Imported::new(Some(timestamp), String::new()));
+            continue;
+        }
+        match commands.last_mut() {
+            // With timestamps, everything up to the next one is one command
+            Some(last) if last.timestamp.is_some() => {
+                if !last.command.is_empty() {
+                    last.command.push('\n');
+                }
+                last.command.push_str(line);
+            }
+            _ => commands.push(Imported::new(None, line.to_string())),
+        }
+    }
+    commands.retain(|c| !c.command.trim().is_empty());
+    commands
+}
+
+/// Plain lines or extended history (`: <start>:<duration>;<command>`), with
+/// multi-line commands continued by a trailing backslash
+fn parse_zsh(text: &str) -> Vec<Imported> {
+    let mut commands = Vec::new();
+    let mut lines = text.lines();
+    while let Some(first) = lines.next() {
+        let mut line = first.to_string();
+        while line.ends_with('\\') {
+            line.pop();
+            line.push('\n');
+            match lines.next() {
+                Some(next) => line.push_str(next),
+                None => break,
+            }
+        }
+
+        let extended = line.strip_prefix(": ").and_then(|rest| {
+            let (meta, command) = rest.split_once(';')?;
+            let (start, duration) = meta.split_once(':')?;
+            Some(Imported {
+                timestamp: Some(start.trim().parse().ok()?),
+                duration_secs: duration.trim().parse().ok(),
+                command: command.to_string(),
+            })
+        });
+        let imported = extended.unwrap_or_else(|| Imported::new(None, line));
+        if !imported.command.trim().is_empty() {
+            commands.push(imported);
+        }
+    }
+    commands
+}
+
+/// fish's YAML-like `- cmd:` / `when:` records; `paths:` lists are skipped
+fn parse_fish(text: &str) -> Vec<Imported> {
+    let mut commands: Vec<Imported> = Vec::new();
+    for line in text.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            commands.push(Imported::new(None, unescape_fish(command)));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let Some(last) = commands.last_mut() {
+                last.timestamp = when.trim().parse().ok();
+            }
+        }
+    }
+    commands.retain(|c| !c.command.trim().is_empty());
+    commands
+}
+
+fn escape_fish(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_fish(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Bytes zsh stores as `ZSH_META` followed by the byte XOR 32
+fn is_zsh_meta(byte: u8) -> bool {
+    byte == 0 || (ZSH_META..=0xa2).contains(&byte)
+}
+
+fn metafy(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        if is_zsh_meta(byte) {
+            out.push(ZSH_META);
+            out.push(byte ^ 32);
+        } else {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        match (byte, iter.clone().next()) {
+            (ZSH_META, Some(&next)) => {
+                out.push(next ^ 32);
+                iter.next();
+            }
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+fn into_entries(commands: Vec<Imported>, fallback: DateTime<Utc>) -> Vec<HistoryEntry> {
+    let timestamp = |c: &Imported| c.timestamp.and_then(|t| DateTime::from_timestamp(t, 0));
+    let untimed = commands.iter().filter(|c| timestamp(c).is_none()).count() as i64;
+
+    let mut seen_untimed = 0;
+    commands
+        .into_iter()
+        .map(|imported| HistoryEntry {
+            timestamp: timestamp(&imported).unwrap_or_else(|| {
+                seen_untimed += 1;
+                fallback - Duration::seconds(untimed - seen_untimed + 1)
+            }),
+            duration_ms: imported.duration_secs.map(|secs| secs * 1000),
+            command: imported.command,
+            exit_code: None,
+            cwd: None,
+            stdout_summary: None,
+            session_id: None,
+        })
+        .collect()
+}
+
+fn print_usage() {
+    println!("Usage: fool history import --from <bash|zsh|fish> [FILE]");
+    println!("       fool history export --to <bash|zsh|fish> [FILE]");
+    println!();
+    println!("Import merges another shell's history into Fool's by timestamp, skipping");
+    println!("commands already recorded. Export appends to FILE, or prints to stdout.");
+}
+
+/// `fool history import|export ...`
+pub fn run(args: &[String], config: &Config) -> Result<()> {
+    let (action, flag) = match args.first().map(String::as_str) {
+        Some("import") => ("import", "--from"),
+        Some("export") => ("export", "--to"),
+        Some("-h" | "--help") | None => {
+            print_usage();
+            return Ok(());
+        }
+        Some(other) => bail!("unknown history command '{}'", other),
+    };
+
+    let mut shell = None;
+    let mut path = None;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == flag {
+            let name = rest
+                .next()
+                .with_context(|| format!("{} requires a shell name", flag))?;
+            shell = Some(Shell::parse(name)?);
+        } else if arg.starts_with('-') {
+            bail!("unknown option '{}'", arg);
+        } else if path.is_none() {
+            path = Some(crate::config::expand_path(arg));
+        } else {
+            bail!("unexpected argument '{}'", arg);
+        }
+    }
+    let shell = shell.with_context(|| format!("{} <bash|zsh|fish> is required", flag))?;
+
+    let mut history = History::open(&config.history)?;
+    if action == "import" {
+        import(shell, path, &mut history, config.history.max_entries)
+    } else {
+        export(shell, path, &history, &config.ai.trigger_prefix)
+    }
+}
+
+fn import(
+    shell: Shell,
+    path: Option<PathBuf>,
+    history: &mut History,
+    max_entries: usize,
+) -> Result<()> {
+    let path = path
+        .or_else(|| shell.default_path())
+        .context("no history file given and no home directory found")?;
+    let bytes =
+        fs::read(&path).with_context(|| format!("Failed to read history file: {:?}", path))?;
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+
+    let entries = shell.read(&bytes, modified);
+    let added = history.import(&entries)?;
+    println!(
+        "Imported {} of {} commands from {:?}",
+        added,
+        entries.len(),
+        path
+    );
+    if added > max_entries {
+        println!(
+            "Only the newest {} are kept (history.max_entries)",
+            max_entries
+        );
+    }
+    Ok(())
+}
+
+fn export(shell: Shell, path: Option<PathBuf>, history: &History, ai_trigger: &str) -> Result<()> {
+    // AI questions mean nothing to other shells
+    let entries: Vec<&HistoryEntry> = history
+        .get_recent(usize::MAX)
+        .into_iter()
+        .filter(|e| ai_trigger.is_empty() || !e.command.starts_with(ai_trigger))
+        .collect();
+    let bytes = shell.write(&entries);
+
+    let Some(path) = path else {
+        std::io::stdout().write_all(&bytes)?;
+        return Ok(());
+    };
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to open {:?} for writing", path))?;
+    file.write_all(&bytes)?;
+    eprintln!("Exported {} commands to {:?}", entries.len(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.command.as_str()).collect()
+    }
+
+    fn fallback() -> DateTime<Utc> {
+        DateTime::from_timestamp(2_000_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_read_bash() {
+        let plain = Shell::Bash.read(b"ls -la\n\ncd /tmp\n", fallback());
+        assert_eq!(commands(&plain), vec!["ls -la", "cd /tmp"]);
+        // Untimed commands keep their order just before the fallback time
+        assert_eq!(plain[0].timestamp.timestamp(), 2_000_000_000 - 2);
+        assert_eq!(plain[1].timestamp.timestamp(), 2_000_000_000 - 1);
+
+        let timed = Shell::Bash.read(
+            b"old\n#1700000000\necho hi\n#1700000060\nfor x in 1 2; do\necho $x\ndone\n",
+            fallback(),
+        );
+        assert_eq!(
+            commands(&timed),
+            vec!["old", "echo hi", "for x in 1 2; do\necho $x\ndone"]
+        );
+        assert_eq!(timed[1].timestamp.timestamp(), 1_700_000_000);
+        assert!(timed[1].exit_code.is_none() && timed[1].session_id.is_none());
+    }
+
+    #[test]
+    fn test_read_zsh() {
+        let mut bytes = b": 1700000000:3;make test\n: 1700000005:0;echo a\\\nb\nplain\n".to_vec();
+        // The UTF-8 bytes of "…" (e2 80 a6) fall in zsh's meta range
+        bytes.extend_from_slice(b": 1700000009:0;echo ");
+        bytes.extend_from_slice(&metafy("…".as_bytes()));
+        bytes.push(b'\n');
+
+        let entries = Shell::Zsh.read(&bytes, fallback());
+        assert_eq!(
+            commands(&entries),
+            vec!["make test", "echo a\nb", "plain", "echo …"]
+        );
+        assert_eq!(entries[0].timestamp.timestamp(), 1_700_000_000);
+        assert_eq!(entries[0].duration_ms, Some(3000));
+        assert_eq!(entries[2].timestamp, fallback() - Duration::seconds(1));
+    }
+
+    #[test]
+    fn test_read_fish() {
+        let text = "- cmd: git status\n  when: 1700000000\n  paths:\n    - src\n\
+                    - cmd: echo one\\ntwo \\\\n\n  when: 1700000010\n";
+        let entries = Shell::Fish.read(text.as_bytes(), fallback());
+        assert_eq!(commands(&entries), vec!["git status", "echo one\ntwo \\n"]);
+        assert_eq!(entries[1].timestamp.timestamp(), 1_700_000_010);
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let mut first = HistoryEntry::new("echo \"multi\nline\" \\ …".to_string());
+        first.timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut second = HistoryEntry::new("ls".to_string());
+        second.timestamp = DateTime::from_timestamp(1_700_000_001, 0).unwrap();
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let bytes = shell.write(&[&first, &second]);
+            let entries = shell.read(&bytes, fallback());
+            assert_eq!(commands(&entries), vec![first.command.as_str(), "ls"]);
+            assert_eq!(entries[1].timestamp, second.timestamp, "{:?}", shell);
+        }
+    }
+}
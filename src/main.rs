@@ -4,15 +4,36 @@
 //! - State machine based command parsing
 //! - Native AI integration via OpenAI API (triggered by !)
 //! - Syntax highlighting and auto-completion
+//! - Colour themes adapted to the terminal (honours NO_COLOR)
+//! - Optional inline AI autosuggestions
 //! - Command history with context
 //! - Pipe and redirection support
 
+mod abbr;
 mod ai;
+mod answers;
+mod attach;
+mod bindings;
+mod completion;
+mod compspec;
 mod config;
+mod context;
+mod crypt;
+mod editor;
 mod executor;
+mod extcomp;
+mod hint;
 mod history;
+mod interop;
+mod notify;
 mod parser;
+mod picker;
+mod prompt;
 mod repl;
+mod stats;
+mod store;
+mod suggest;
+mod theme;
 
 use anyhow::Result;
 use config::Config;
@@ -34,6 +55,10 @@ fn print_usage() {
     println!("  -v, --version    Print version information");
     println!("  -c <command>     Execute a command and exit");
     println!("  --init-config    Generate default config file");
+    println!();
+    println!("Commands:");
+    println!("  history import --from <bash|zsh|fish> [FILE]");
+    println!("  history export --to <bash|zsh|fish> [FILE]");
 }
 
 /// Initialize config file with secure permissions
@@ -102,12 +127,26 @@ async fn execute_command(cmd: &str, config: Config) -> Result<i32> {
                 return Ok(1);
             }
 
+            if ai::AiBuiltin::parse(&query) == Some(ai::AiBuiltin::Context) {
+                let builder = context::ContextBuilder::new(config.ai.context.clone());
+                match context::format_sections(&builder.collect()) {
+                    Some(context) => println!("{}", context),
+                    None => println!("No context providers enabled"),
+                }
+                return Ok(0);
+            }
+
             if !ai_agent.is_configured() {
-                eprintln!("Error: AI not configured. Set FOOL_AI_KEY or OPENAI_API_KEY.");
+                eprintln!("Error: {}", ai_agent.setup_error());
                 return Ok(1);
             }
 
-            match ai_agent.query_stream(&query, &history).await {
+            let expanded = attach::expand_query(&query, &config.ai.attachments);
+            for attachment in &expanded.attachments {
+                eprintln!("Attached: {}", attachment.summary());
+            }
+
+            match ai_agent.query_stream(&expanded.prompt, &history).await {
                 Ok(_) => Ok(0),
                 Err(e) => {
                     eprintln!("AI Error: {}", e);
@@ -144,12 +183,21 @@ async fn main() -> Result<()> {
             "--init-config" => {
                 return init_config();
             }
+            "history" => {
+                let config = Config::load()?;
+                if let Err(e) = interop::run(&args[2..], &config) {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
             "-c" => {
                 if args.len() < 3 {
                     eprintln!("Error: -c requires a command");
                     std::process::exit(1);
                 }
                 let config = Config::load()?;
+                theme::init(theme::Theme::from_config(&config.ui));
                 let cmd = args[2..].join(" ");
                 let exit_code = execute_command(&cmd, config).await?;
                 std::process::exit(exit_code);
@@ -164,6 +212,7 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let config = Config::load()?;
+    theme::init(theme::Theme::from_config(&config.ui));
 
     // Create and run REPL
     let mut repl = Repl::new(config)?;
@@ -0,0 +1,290 @@
+//! Notify module for Fool Shell
+//! Desktop/terminal notifications when long-running commands finish
+
+use crate::config::{NotifyConfig, NotifyMethod};
+use crate::executor::ExecutionResult;
+use crate::history::format_duration;
+use crate::stats::program;
+use anyhow::{bail, Context, Result};
+use crossterm::event::{
+    self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers,
+};
+use crossterm::{execute, terminal};
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const TITLE: &str = "Fool Shell";
+
+/// How long to wait for the terminal to report whether it has focus
+const FOCUS_REPLY_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Longest command shown in a notification
+const MAX_COMMAND_CHARS: usize = 80;
+
+/// Keys typed while a command ran that were read on the way to the
+/// terminal's focus report, to be handed back to the line editor
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Typeahead {
+    pub lines: Vec<String>, // Finished with Enter, run as if typed
+    pub partial: String,    // The start of the next line
+}
+
+impl Typeahead {
+    /// Replay one key the way the line editor would have
+    ///
+    /// Text, Enter and Backspace come through; other keys are dropped.
+    fn push(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+        match key.code {
+            KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => {
+                self.partial.push(c)
+            }
+            KeyCode::Enter => self.lines.push(std::mem::take(&mut self.partial)),
+            KeyCode::Backspace => {
+                self.partial.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends a notification after commands that ran longer than configured
+pub struct Notifier {
+    config: NotifyConfig,
+}
+
+impl Notifier {
+    pub fn new(config: NotifyConfig) -> Self {
+        Self { config }
+    }
+
+    /// Called once a command line has finished running
+    ///
+    /// Asking the terminal about focus may read keys typed ahead; they are
+    /// returned so the caller can replay them.
+    pub fn command_finished(
+        &self,
+        command: &str,
+        result: &ExecutionResult,
+        duration: Duration,
+    ) -> Typeahead {
+        if !self.wants(command, duration) {
+            return Typeahead::default();
+        }
+        let mut typeahead = Typeahead::default();
+        // Terminals that never report focus are never notified
+        if self.config.unfocused_only && terminal_focused(&mut typeahead) != Some(false) {
+            return typeahead;
+        }
+        if let Err(e) = self.send(command, result, duration) {
+            eprintln!("Warning: Failed to send notification: {:#}", e);
+        }
+        typeahead
+    }
+
+    /// Whether `command` qualifies, before looking at focus
+    fn wants(&self, command: &str, duration: Duration) -> bool {
+        self.config.enabled
+            && duration.as_secs() >= self.config.min_duration_secs
+            && !self.config.ignore.iter().any(|p| p == program(command))
+    }
+
+    fn send(&self, command: &str, result: &ExecutionResult, duration: Duration) -> Result<()> {
+        let body = message(command, result, duration);
+        match self.config.method {
+            NotifyMethod::Osc9 | NotifyMethod::Osc777 => {
+                let in_tmux = std::env::var_os("TMUX").is_some();
+                let mut stdout = io::stdout();
+                stdout.write_all(osc_sequence(self.config.method, &body, in_tmux).as_bytes())?;
+                stdout.flush()?;
+            }
+            NotifyMethod::Command => {
+                let status = if result.succeeded() {
+                    "success"
+                } else {
+                    "failure"
+                };
+                let args: Vec<String> = self
+                    .config
+                    .command
+                    .iter()
+                    .map(|arg| {
+                        arg.replace("{title}", TITLE)
+                            .replace("{body}", &body)
+                            .replace("{command}", command)
+                            .replace("{status}", status)
+                            .replace("{duration}", &format_duration(duration))
+                    })
+                    .collect();
+                let Some((program, args)) = args.split_first() else {
+                    bail!("notify.command is empty");
+                };
+                // Don't wait for the notifier; it may stay around for a while
+                Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .with_context(|| format!("Failed to run {}", program))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Notification text; the exit code picks the success or failure wording
+fn message(command: &str, result: &ExecutionResult, duration: Duration) -> String {
+    let mut shown: String = command
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_COMMAND_CHARS)
+        .collect();
+    if command.chars().count() > MAX_COMMAND_CHARS {
+        shown.push('…');
+    }
+
+    let took = format_duration(duration);
+    if result.succeeded() {
+        format!("✓ {} finished in {}", shown, took)
+    } else {
+        format!(
+            "✗ {} failed with exit code {} after {}",
+            shown, result.exit_code, took
+        )
+    }
+}
+
+/// The escape sequence for an OSC notification, wrapped for tmux passthrough
+fn osc_sequence(method: NotifyMethod, body: &str, in_tmux: bool) -> String {
+    // Semicolons separate OSC 777 fields, and control characters would end
+    // the sequence early
+    let clean = |text: &str| -> String {
+        text.chars()
+            .filter(|c| !c.is_control())
+            .map(|c| if c == ';' { ',' } else { c })
+            .collect()
+    };
+    let osc = match method {
+        NotifyMethod::Osc777 => format!("\x1b]777;notify;{};{}\x07", TITLE, clean(body)),
+        _ => format!("\x1b]9;{}\x07", clean(body)),
+    };
+
+    if in_tmux {
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+    } else {
+        osc
+    }
+}
+
+/// Ask the terminal whether it has focus; `None` if it doesn't say
+///
+/// Focus reporting is switched on just for this, never while a command runs,
+/// and most terminals answer with their current state right away. Keys typed
+/// earlier are queued ahead of the answer, so they go into `typeahead`.
+fn terminal_focused(typeahead: &mut Typeahead) -> Option<bool> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal::enable_raw_mode().ok()?;
+    let mut stdout = io::stdout();
+    let _ = execute!(stdout, EnableFocusChange);
+
+    let deadline = Instant::now() + FOCUS_REPLY_TIMEOUT;
+    let mut focused = None;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        if !event::poll(left).unwrap_or(false) {
+            break;
+        }
+        match event::read() {
+            Ok(Event::FocusGained) => focused = Some(true),
+            Ok(Event::FocusLost) => focused = Some(false),
+            Ok(Event::Key(key)) => {
+                typeahead.push(key);
+                continue;
+            }
+            Ok(_) => continue,
+            Err(_) => {}
+        }
+        break;
+    }
+
+    let _ = execute!(stdout, DisableFocusChange);
+    let _ = terminal::disable_raw_mode();
+    focused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_wording() {
+        let took = Duration::from_secs(75);
+        assert_eq!(
+            message("cargo build", &ExecutionResult::success(), took),
+            "✓ cargo build finished in 1m 15s"
+        );
+        assert_eq!(
+            message("make\x1b[31m", &ExecutionResult::with_code(2), took),
+            "✗ make[31m failed with exit code 2 after 1m 15s"
+        );
+        assert!(message(&"x".repeat(200), &ExecutionResult::success(), took).contains("x…"));
+    }
+
+    #[test]
+    fn test_osc_sequence() {
+        assert_eq!(
+            osc_sequence(NotifyMethod::Osc9, "done\x07", false),
+            "\x1b]9;done\x07"
+        );
+        assert_eq!(
+            osc_sequence(NotifyMethod::Osc777, "a; b", false),
+            "\x1b]777;notify;Fool Shell;a, b\x07"
+        );
+        assert_eq!(
+            osc_sequence(NotifyMethod::Osc9, "done", true),
+            "\x1bPtmux;\x1b\x1b]9;done\x07\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_wants() {
+        let notifier = Notifier::new(NotifyConfig {
+            enabled: true,
+            ..NotifyConfig::default()
+        });
+        assert!(notifier.wants("cargo build", Duration::from_secs(10)));
+        assert!(!notifier.wants("cargo build", Duration::from_secs(9)));
+        assert!(!notifier.wants("EDITOR=x vim notes", Duration::from_secs(600)));
+
+        let disabled = Notifier::new(NotifyConfig::default());
+        assert!(!disabled.wants("cargo build", Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn test_typeahead_replay() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut typeahead = Typeahead::default();
+        for c in "lsx".chars() {
+            typeahead.push(key(KeyCode::Char(c)));
+        }
+        typeahead.push(key(KeyCode::Backspace));
+        typeahead.push(key(KeyCode::Enter));
+        typeahead.push(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT));
+        typeahead.push(key(KeyCode::Up));
+        typeahead.push(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        typeahead.push(key(KeyCode::Char('i')));
+        assert_eq!(
+            typeahead,
+            Typeahead {
+                lines: vec!["ls".to_string()],
+                partial: "Gi".to_string(),
+            }
+        );
+    }
+}
@@ -2,6 +2,7 @@
 //! Implements a DFA-based parser for command line input
 
 use std::fmt;
+use std::ops::Range;
 
 /// Parser states for the state machine
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,6 +19,8 @@ pub enum ParserState {
     DoubleQuote,
     /// After a pipe character, ready for next command
     Pipe,
+    /// After &&, ready for the command that runs if the previous one succeeded
+    And,
     /// After > for output redirection
     RedirectOut,
     /// After >> for append redirection
@@ -40,6 +43,7 @@ impl fmt::Display for ParserState {
             ParserState::SingleQuote => write!(f, "SingleQuote"),
             ParserState::DoubleQuote => write!(f, "DoubleQuote"),
             ParserState::Pipe => write!(f, "Pipe"),
+            ParserState::And => write!(f, "And"),
             ParserState::RedirectOut => write!(f, "RedirectOut"),
             ParserState::RedirectAppend => write!(f, "RedirectAppend"),
             ParserState::RedirectIn => write!(f, "RedirectIn"),
@@ -57,6 +61,8 @@ pub struct Command {
     pub stdin_redirect: Option<String>,
     pub stdout_redirect: Option<String>,
     pub stdout_append: bool,
+    /// `&&` follows: the next command runs only if this pipeline succeeds
+    pub and_then: bool,
 }
 
 impl Command {
@@ -68,6 +74,7 @@ impl Command {
             stdin_redirect: None,
             stdout_redirect: None,
             stdout_append: false,
+            and_then: false,
         }
     }
 
@@ -79,14 +86,64 @@ impl Command {
 /// Result of parsing a command line
 #[derive(Debug, Clone)]
 pub enum ParseResult {
-    /// Regular shell command(s) connected by pipes
+    /// Regular shell command(s) connected by pipes, and pipelines joined by
+    /// `&&` (see `Command::and_then`)
     Commands(Vec<Command>),
     /// AI query (triggered by prefix)
     AIQuery(String),
     /// Empty input
     Empty,
     /// Parse error
-    Error(String),
+    Error(ParseError),
+}
+
+/// A syntax error and the part of the input it's about
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte range in the input passed to `parse`
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: &str, span: Range<usize>) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// What a piece of the command line is, as the state machine saw it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// The program of a pipeline stage
+    Command,
+    Argument,
+    /// A quoted part of a word, quotes included
+    Quote,
+    /// `|`, `&&`, `>`, `>>` or `<`
+    Operator,
+    /// The file after a redirection
+    RedirectTarget,
+    /// The AI trigger prefix
+    AiTrigger,
+    /// The text of an AI query
+    AiQuery,
+}
+
+/// A span of the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range in the input
+    pub span: Range<usize>,
 }
 
 /// State machine parser for shell commands
@@ -94,6 +151,14 @@ pub struct Parser {
     ai_trigger: String,
 }
 
+/// Everything one pass of the state machine produces
+struct Scan {
+    commands: Vec<Command>,
+    state: ParserState, // Where the input left the state machine
+    tokens: Vec<Token>,
+    error: Option<ParseError>,
+}
+
 impl Parser {
     pub fn new(ai_trigger: String) -> Self {
         Self { ai_trigger }
@@ -114,26 +179,84 @@ impl Parser {
         }
 
         // Parse as shell command
-        self.parse_commands(trimmed)
+        let offset = input.len() - input.trim_start().len();
+        let scan = self.scan(trimmed, offset);
+        if let Some(error) = scan.error {
+            ParseResult::Error(error)
+        } else if scan.commands.is_empty() {
+            ParseResult::Empty
+        } else {
+            ParseResult::Commands(scan.commands)
+        }
+    }
+
+    /// The tokens of `input` and the error `parse` would report, if any
+    pub fn tokenize(&self, input: &str) -> (Vec<Token>, Option<ParseError>) {
+        let trimmed = input.trim();
+        let offset = input.len() - input.trim_start().len();
+        if trimmed.starts_with(&self.ai_trigger) && !trimmed.is_empty() {
+            let query = offset + self.ai_trigger.len();
+            let mut tokens = vec![Token {
+                kind: TokenKind::AiTrigger,
+                span: offset..query,
+            }];
+            if query < offset + trimmed.len() {
+                tokens.push(Token {
+                    kind: TokenKind::AiQuery,
+                    span: query..offset + trimmed.len(),
+                });
+            }
+            return (tokens, None);
+        }
+        let scan = self.scan(trimmed, offset);
+        (scan.tokens, scan.error)
     }
 
-    fn parse_commands(&self, input: &str) -> ParseResult {
+    /// Whether `input` stops in the middle of a command (after `|`, `&&` or
+    /// `\`, or inside quotes) and so goes on in the next line
+    pub fn is_continued(&self, input: &str) -> bool {
+        // Trailing whitespace stays: `echo a\ ` ends in an escaped space
+        let trimmed = input.trim_start();
+        if trimmed.trim_end().is_empty() || trimmed.starts_with(&self.ai_trigger) {
+            return false;
+        }
+        matches!(
+            self.scan(trimmed, 0).state,
+            ParserState::Escape
+                | ParserState::SingleQuote
+                | ParserState::DoubleQuote
+                | ParserState::Pipe
+                | ParserState::And
+        )
+    }
+
+    /// Run the state machine over `input`, which starts `offset` bytes into
+    /// the line; spans are relative to the line
+    fn scan(&self, input: &str, offset: usize) -> Scan {
         let mut commands: Vec<Command> = Vec::new();
         let mut current_command = Command::default();
         let mut current_token = String::new();
         let mut state = ParserState::Idle;
         let mut prev_state = ParserState::Idle;
-
-        let chars: Vec<char> = input.chars().collect();
+        let mut tokens = Tokens::default();
+        let mut error = None;
+
+        let chars: Vec<(usize, char)> = input
+            .char_indices()
+            .map(|(at, c)| (offset + at, c))
+            .collect();
+        let end = offset + input.len();
         let mut i = 0;
 
         while i < chars.len() {
-            let c = chars[i];
+            let (at, c) = chars[i];
+            let next_at = at + c.len_utf8();
 
             match state {
                 ParserState::Idle | ParserState::CommandStart | ParserState::Argument => {
                     match c {
-                        ' ' | '\t' => {
+                        ' ' | '\t' | '\n' => {
+                            tokens.end_word(at, word_kind(&state, &current_command));
                             if !current_token.is_empty() {
                                 self.add_token(&mut current_command, &current_token, &state);
                                 current_token.clear();
@@ -141,18 +264,23 @@ impl Parser {
                             }
                         }
                         '\'' => {
+                            tokens.open_quote(at, word_kind(&state, &current_command));
                             prev_state = state.clone();
                             state = ParserState::SingleQuote;
                         }
                         '"' => {
+                            tokens.open_quote(at, word_kind(&state, &current_command));
                             prev_state = state.clone();
                             state = ParserState::DoubleQuote;
                         }
                         '\\' => {
+                            tokens.extend_word(at);
                             prev_state = state.clone();
                             state = ParserState::Escape;
                         }
                         '|' => {
+                            tokens.end_word(at, word_kind(&state, &current_command));
+                            tokens.operator(at..next_at);
                             if !current_token.is_empty() {
                                 self.add_token(&mut current_command, &current_token, &state);
                                 current_token.clear();
@@ -163,20 +291,45 @@ impl Parser {
                             }
                             state = ParserState::Pipe;
                         }
+                        '&' if chars.get(i + 1).is_some_and(|&(_, next)| next == '&') => {
+                            tokens.end_word(at, word_kind(&state, &current_command));
+                            tokens.operator(at..next_at + 1);
+                            if !current_token.is_empty() {
+                                self.add_token(&mut current_command, &current_token, &state);
+                                current_token.clear();
+                            }
+                            if current_command.is_empty() {
+                                error = Some(ParseError::new(
+                                    "Syntax error: && without a command before it",
+                                    at..next_at + 1,
+                                ));
+                                break;
+                            }
+                            current_command.and_then = true;
+                            commands.push(current_command);
+                            current_command = Command::default();
+                            state = ParserState::And;
+                            i += 1;
+                        }
                         '>' => {
+                            tokens.end_word(at, word_kind(&state, &current_command));
                             if !current_token.is_empty() {
                                 self.add_token(&mut current_command, &current_token, &state);
                                 current_token.clear();
                             }
                             // Check for >>
-                            if i + 1 < chars.len() && chars[i + 1] == '>' {
+                            if i + 1 < chars.len() && chars[i + 1].1 == '>' {
+                                tokens.operator(at..next_at + 1);
                                 state = ParserState::RedirectAppend;
                                 i += 1;
                             } else {
+                                tokens.operator(at..next_at);
                                 state = ParserState::RedirectOut;
                             }
                         }
                         '<' => {
+                            tokens.end_word(at, word_kind(&state, &current_command));
+                            tokens.operator(at..next_at);
                             if !current_token.is_empty() {
                                 self.add_token(&mut current_command, &current_token, &state);
                                 current_token.clear();
@@ -184,6 +337,7 @@ impl Parser {
                             state = ParserState::RedirectIn;
                         }
                         _ => {
+                            tokens.extend_word(at);
                             current_token.push(c);
                             if state == ParserState::Idle {
                                 state = ParserState::CommandStart;
@@ -193,6 +347,7 @@ impl Parser {
                 }
                 ParserState::SingleQuote => {
                     if c == '\'' {
+                        tokens.close_quote(next_at);
                         state = prev_state.clone();
                     } else {
                         current_token.push(c);
@@ -200,10 +355,11 @@ impl Parser {
                 }
                 ParserState::DoubleQuote => {
                     if c == '"' {
+                        tokens.close_quote(next_at);
                         state = prev_state.clone();
                     } else if c == '\\' && i + 1 < chars.len() {
                         // Handle escape in double quotes
-                        let next = chars[i + 1];
+                        let next = chars[i + 1].1;
                         if next == '"' || next == '\\' || next == '$' {
                             current_token.push(next);
                             i += 1;
@@ -215,17 +371,23 @@ impl Parser {
                     }
                 }
                 ParserState::Escape => {
-                    current_token.push(c);
+                    // A backslash before a newline continues the line
+                    if c != '\n' {
+                        current_token.push(c);
+                    }
                     state = prev_state.clone();
                 }
-                ParserState::Pipe => {
+                ParserState::Pipe | ParserState::And => {
                     if !c.is_whitespace() {
-                        current_token.push(c);
+                        // The command's first character goes through the
+                        // CommandStart rules, so quotes and escapes work
                         state = ParserState::CommandStart;
+                        continue;
                     }
                 }
                 ParserState::RedirectOut | ParserState::RedirectAppend => match c {
-                    ' ' | '\t' => {
+                    ' ' | '\t' | '\n' => {
+                        tokens.end_word(at, TokenKind::RedirectTarget);
                         if !current_token.is_empty() {
                             current_command.stdout_redirect = Some(current_token.clone());
                             current_command.stdout_append = state == ParserState::RedirectAppend;
@@ -234,23 +396,28 @@ impl Parser {
                         }
                     }
                     '\'' => {
+                        tokens.open_quote(at, TokenKind::RedirectTarget);
                         prev_state = state.clone();
                         state = ParserState::SingleQuote;
                     }
                     '"' => {
+                        tokens.open_quote(at, TokenKind::RedirectTarget);
                         prev_state = state.clone();
                         state = ParserState::DoubleQuote;
                     }
                     '\\' => {
+                        tokens.extend_word(at);
                         prev_state = state.clone();
                         state = ParserState::Escape;
                     }
                     _ => {
+                        tokens.extend_word(at);
                         current_token.push(c);
                     }
                 },
                 ParserState::RedirectIn => match c {
-                    ' ' | '\t' => {
+                    ' ' | '\t' | '\n' => {
+                        tokens.end_word(at, TokenKind::RedirectTarget);
                         if !current_token.is_empty() {
                             current_command.stdin_redirect = Some(current_token.clone());
                             current_token.clear();
@@ -258,18 +425,22 @@ impl Parser {
                         }
                     }
                     '\'' => {
+                        tokens.open_quote(at, TokenKind::RedirectTarget);
                         prev_state = state.clone();
                         state = ParserState::SingleQuote;
                     }
                     '"' => {
+                        tokens.open_quote(at, TokenKind::RedirectTarget);
                         prev_state = state.clone();
                         state = ParserState::DoubleQuote;
                     }
                     '\\' => {
+                        tokens.extend_word(at);
                         prev_state = state.clone();
                         state = ParserState::Escape;
                     }
                     _ => {
+                        tokens.extend_word(at);
                         current_token.push(c);
                     }
                 },
@@ -281,6 +452,22 @@ impl Parser {
             i += 1;
         }
 
+        // Close the last word (or the quote left open)
+        let last_word_state = match state {
+            ParserState::SingleQuote | ParserState::DoubleQuote | ParserState::Escape => {
+                &prev_state
+            }
+            _ => &state,
+        };
+        let last_word_kind = match last_word_state {
+            ParserState::RedirectOut | ParserState::RedirectAppend | ParserState::RedirectIn => {
+                TokenKind::RedirectTarget
+            }
+            other => word_kind(other, &current_command),
+        };
+        tokens.close_quote(end);
+        tokens.end_word(end, last_word_kind);
+
         // Handle remaining token
         if !current_token.is_empty() {
             match state {
@@ -309,40 +496,41 @@ impl Parser {
             commands.push(current_command);
         }
 
-        // M-06 FIX: Check for trailing backslash (incomplete escape sequence)
-        if state == ParserState::Escape {
-            return ParseResult::Error("Syntax error: trailing backslash".to_string());
-        }
-
-        // Check for unclosed quotes
-        if state == ParserState::SingleQuote || state == ParserState::DoubleQuote {
-            return ParseResult::Error("Unclosed quote".to_string());
-        }
-
-        // Validate final state: check for incomplete pipelines or redirections
-        match state {
-            ParserState::Pipe => {
-                return ParseResult::Error(
-                    "Syntax error: pipe without following command".to_string(),
-                );
-            }
-            ParserState::RedirectOut | ParserState::RedirectAppend => {
-                return ParseResult::Error(
-                    "Syntax error: output redirection without file".to_string(),
-                );
-            }
-            ParserState::RedirectIn => {
-                return ParseResult::Error(
-                    "Syntax error: input redirection without file".to_string(),
-                );
+        let error = error.or(match state {
+            // M-06 FIX: Check for trailing backslash (incomplete escape sequence)
+            ParserState::Escape => Some(ParseError::new(
+                "Syntax error: trailing backslash",
+                end - 1..end,
+            )),
+            // Check for unclosed quotes
+            ParserState::SingleQuote | ParserState::DoubleQuote => {
+                Some(ParseError::new("Unclosed quote", tokens.last_quote..end))
             }
-            _ => {}
-        }
-
-        if commands.is_empty() {
-            ParseResult::Empty
-        } else {
-            ParseResult::Commands(commands)
+            // Validate final state: check for incomplete pipelines or redirections
+            ParserState::Pipe => Some(ParseError::new(
+                "Syntax error: pipe without following command",
+                tokens.last_operator(),
+            )),
+            ParserState::RedirectOut | ParserState::RedirectAppend => Some(ParseError::new(
+                "Syntax error: output redirection without file",
+                tokens.last_operator(),
+            )),
+            ParserState::RedirectIn => Some(ParseError::new(
+                "Syntax error: input redirection without file",
+                tokens.last_operator(),
+            )),
+            ParserState::And => Some(ParseError::new(
+                "Syntax error: && without following command",
+                tokens.last_operator(),
+            )),
+            _ => None,
+        });
+
+        Scan {
+            commands,
+            state,
+            tokens: tokens.tokens,
+            error,
         }
     }
 
@@ -355,6 +543,73 @@ impl Parser {
     }
 }
 
+/// What the word being read will become, mirroring `add_token`
+fn word_kind(state: &ParserState, command: &Command) -> TokenKind {
+    if command.program.is_empty() || *state == ParserState::CommandStart {
+        TokenKind::Command
+    } else {
+        TokenKind::Argument
+    }
+}
+
+/// Collects token spans while the state machine runs
+#[derive(Default)]
+struct Tokens {
+    tokens: Vec<Token>,
+    /// Start of the unquoted run of the current word
+    word_start: Option<usize>,
+    /// Start of the quote being read
+    quote_start: Option<usize>,
+    /// Start of the most recent quote, for the unclosed quote error
+    last_quote: usize,
+}
+
+impl Tokens {
+    fn extend_word(&mut self, at: usize) {
+        self.word_start.get_or_insert(at);
+    }
+
+    fn end_word(&mut self, at: usize, kind: TokenKind) {
+        if let Some(start) = self.word_start.take() {
+            self.tokens.push(Token {
+                kind,
+                span: start..at,
+            });
+        }
+    }
+
+    fn open_quote(&mut self, at: usize, kind: TokenKind) {
+        self.end_word(at, kind);
+        self.quote_start = Some(at);
+        self.last_quote = at;
+    }
+
+    fn close_quote(&mut self, end: usize) {
+        if let Some(start) = self.quote_start.take() {
+            self.tokens.push(Token {
+                kind: TokenKind::Quote,
+                span: start..end,
+            });
+        }
+    }
+
+    fn operator(&mut self, span: Range<usize>) {
+        self.tokens.push(Token {
+            kind: TokenKind::Operator,
+            span,
+        });
+    }
+
+    fn last_operator(&self) -> Range<usize> {
+        self.tokens
+            .iter()
+            .rev()
+            .find(|token| token.kind == TokenKind::Operator)
+            .map(|token| token.span.clone())
+            .unwrap_or_default()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -438,7 +693,7 @@ mod tests {
         let parser = Parser::new("!".to_string());
         match parser.parse("ls |") {
             ParseResult::Error(e) => {
-                assert!(e.contains("pipe"));
+                assert!(e.message.contains("pipe"));
             }
             _ => panic!("Expected Error for incomplete pipe"),
         }
@@ -449,7 +704,7 @@ mod tests {
         let parser = Parser::new("!".to_string());
         match parser.parse("echo test >") {
             ParseResult::Error(e) => {
-                assert!(e.contains("redirection"));
+                assert!(e.message.contains("redirection"));
             }
             _ => panic!("Expected Error for incomplete redirect"),
         }
@@ -460,7 +715,7 @@ mod tests {
         let parser = Parser::new("!".to_string());
         match parser.parse("cat <") {
             ParseResult::Error(e) => {
-                assert!(e.contains("redirection"));
+                assert!(e.message.contains("redirection"));
             }
             _ => panic!("Expected Error for incomplete redirect"),
         }
@@ -520,7 +775,7 @@ mod tests {
         match parser.parse("echo test\\") {
             ParseResult::Error(e) => {
                 assert!(
-                    e.contains("backslash"),
+                    e.message.contains("backslash"),
                     "Expected backslash error, got: {}",
                     e
                 );
@@ -532,7 +787,7 @@ mod tests {
         match parser.parse("\\") {
             ParseResult::Error(e) => {
                 assert!(
-                    e.contains("backslash"),
+                    e.message.contains("backslash"),
                     "Expected backslash error, got: {}",
                     e
                 );
@@ -540,4 +795,115 @@ mod tests {
             other => panic!("Expected Error for single backslash, got: {:?}", other),
         }
     }
+
+    fn kinds(parser: &Parser, line: &str) -> Vec<(TokenKind, String)> {
+        let (tokens, _) = parser.tokenize(line);
+        tokens
+            .into_iter()
+            .map(|t| (t.kind, line[t.span].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        let parser = Parser::new("!".to_string());
+        assert_eq!(
+            kinds(&parser, r#"  echo "a b"c >> 'out file' | grep x\ y"#),
+            [
+                (Command, "echo".to_string()),
+                (Quote, "\"a b\"".to_string()),
+                (Argument, "c".to_string()),
+                (Operator, ">>".to_string()),
+                (Quote, "'out file'".to_string()),
+                (Operator, "|".to_string()),
+                (Command, "grep".to_string()),
+                (Argument, "x\\ y".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds(&parser, "sort <in > out"),
+            [
+                (Command, "sort".to_string()),
+                (Operator, "<".to_string()),
+                (RedirectTarget, "in".to_string()),
+                (Operator, ">".to_string()),
+                (RedirectTarget, "out".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds(&parser, " !why"),
+            [(AiTrigger, "!".to_string()), (AiQuery, "why".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let parser = Parser::new("!".to_string());
+        let span = |line: &str| match parser.parse(line) {
+            ParseResult::Error(e) => {
+                assert_eq!(parser.tokenize(line).1, Some(e.clone()));
+                line[e.span].to_string()
+            }
+            other => panic!("Expected Error, got: {:?}", other),
+        };
+        assert_eq!(span("ls | wc |"), "|");
+        assert_eq!(span("  echo hi >> "), ">>");
+        assert_eq!(span("echo 'it''s here"), "'s here");
+        assert_eq!(span("echo a\\"), "\\");
+        assert_eq!(span("make &&"), "&&");
+        assert_eq!(span("&& ls"), "&&");
+    }
+
+    #[test]
+    fn test_and_and_continuations() {
+        let parser = Parser::new("!".to_string());
+        match parser.parse("make &&\n  make install | tee log && echo a&b") {
+            ParseResult::Commands(cmds) => {
+                let programs: Vec<_> = cmds.iter().map(|c| c.program.as_str()).collect();
+                assert_eq!(programs, ["make", "make", "tee", "echo"]);
+                let and_then: Vec<_> = cmds.iter().map(|c| c.and_then).collect();
+                assert_eq!(and_then, [true, false, true, false]);
+                assert_eq!(cmds[1].args, ["install"]);
+                assert_eq!(cmds[3].args, ["a&b"]);
+            }
+            other => panic!("Expected Commands, got: {:?}", other),
+        }
+        match parser.parse("ls \\\n  -la |\n wc") {
+            ParseResult::Commands(cmds) => {
+                assert_eq!(cmds[0].args, ["-la"]);
+                assert_eq!(cmds[1].program, "wc");
+            }
+            other => panic!("Expected Commands, got: {:?}", other),
+        }
+        let (tokens, _) = parser.tokenize("a && b");
+        assert_eq!(tokens[1].kind, TokenKind::Operator);
+        assert_eq!(tokens[1].span, 2..4);
+    }
+
+    #[test]
+    fn test_is_continued() {
+        let parser = Parser::new("!".to_string());
+        for line in [
+            "ls |",
+            "make && ",
+            "ls |  \n",
+            "echo a \\",
+            "echo 'open",
+            "ls |\n grep x |",
+        ] {
+            assert!(parser.is_continued(line), "{:?}", line);
+        }
+        for line in [
+            "ls",
+            "ls | wc",
+            "echo a\\ b",
+            "echo a\\ ",
+            "! what's |",
+            "",
+            "echo '|'",
+        ] {
+            assert!(!parser.is_continued(line), "{:?}", line);
+        }
+    }
 }
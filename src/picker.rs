@@ -0,0 +1,462 @@
+//! Picker module for Fool Shell
+//! Full-screen fuzzy finder over history entries, ranked by frecency
+
+use crate::history::HistoryEntry;
+use crate::theme::{self, Role};
+use chrono::{DateTime, Duration, Utc};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, ContentStyle, Print, PrintStyledContent},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Screen lines used by the query and status lines
+const HEADER_LINES: u16 = 2;
+
+/// Widest cwd column before it is shortened from the left
+const CWD_WIDTH: usize = 24;
+
+/// Fuzzy score points per unit of log frecency: a command used a few times
+/// in the last hour outranks a one-off that matches a little more tightly
+const FRECENCY_WEIGHT: f64 = 4.0;
+
+/// A unique command with its frecency and most recent use
+#[derive(Debug, Clone)]
+struct Candidate {
+    command: String,
+    exit_code: Option<i32>,
+    cwd: Option<String>,
+    last_used: DateTime<Utc>,
+    frecency: f64,
+    score: i64, // Fuzzy match score for the current query
+}
+
+impl Candidate {
+    /// Match quality and frecency combined; frecency counts on a log scale
+    /// so a habit can't bury every better match
+    fn rank(&self) -> f64 {
+        self.score as f64 + FRECENCY_WEIGHT * self.frecency.ln_1p()
+    }
+}
+
+/// What a key press did to the picker
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Accept(String),
+    Cancel,
+}
+
+/// Interactive history picker
+pub struct Picker {
+    entries: Vec<HistoryEntry>,
+    cwd: Option<String>,
+    query: String,
+    here_only: bool,
+    success_only: bool,
+    matches: Vec<Candidate>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl Picker {
+    /// Create a picker over `entries` (oldest first), starting with `query`
+    pub fn new(entries: Vec<HistoryEntry>, query: &str) -> Self {
+        let cwd = std::env::current_dir()
+            .ok()
+            .map(|p| p.to_string_lossy().to_string());
+        let mut picker = Self {
+            entries,
+            cwd,
+            query: query.to_string(),
+            here_only: false,
+            success_only: false,
+            matches: Vec::new(),
+            selected: 0,
+            scroll: 0,
+        };
+        picker.refresh();
+        picker
+    }
+
+    /// Show the picker and return the chosen command, or `None` if cancelled
+    pub fn run(&mut self) -> io::Result<Option<String>> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, cursor::Hide)?;
+
+        let result = self.event_loop(&mut out);
+
+        // Always give the screen back to the line editor
+        let _ = execute!(out, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        result
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> io::Result<Option<String>> {
+        loop {
+            self.draw(out)?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match self.handle_key(key) {
+                    Action::Continue => {}
+                    Action::Accept(command) => return Ok(Some(command)),
+                    Action::Cancel => return Ok(None),
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => {
+                return match self.matches.get(self.selected) {
+                    Some(candidate) => Action::Accept(candidate.command.clone()),
+                    None => Action::Cancel,
+                }
+            }
+            KeyCode::Esc => return Action::Cancel,
+            KeyCode::Char('c' | 'g') if ctrl => return Action::Cancel,
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Char('p') if ctrl => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('n' | 'r') if ctrl => self.move_selection(1),
+            KeyCode::Char('d') if ctrl => {
+                self.here_only = !self.here_only;
+                self.refresh();
+            }
+            KeyCode::Char('s') if ctrl => {
+                self.success_only = !self.success_only;
+                self.refresh();
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.refresh();
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let last = self.matches.len() - 1;
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Recompute candidates for the current query and toggles
+    fn refresh(&mut self) {
+        let now = Utc::now();
+        let mut candidates: Vec<Candidate> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+
+        for entry in &self.entries {
+            if self.here_only && (entry.cwd.is_none() || entry.cwd != self.cwd) {
+                continue;
+            }
+            if self.success_only && entry.exit_code != Some(0) {
+                continue;
+            }
+
+            let weight = recency_weight(now - entry.timestamp);
+            match index.get(entry.command.as_str()) {
+                Some(&i) => {
+                    let candidate = &mut candidates[i];
+                    candidate.frecency += weight;
+                    candidate.exit_code = entry.exit_code;
+                    candidate.cwd = entry.cwd.clone();
+                    candidate.last_used = entry.timestamp;
+                }
+                None => {
+                    index.insert(&entry.command, candidates.len());
+                    candidates.push(Candidate {
+                        command: entry.command.clone(),
+                        exit_code: entry.exit_code,
+                        cwd: entry.cwd.clone(),
+                        last_used: entry.timestamp,
+                        frecency: weight,
+                        score: 0,
+                    });
+                }
+            }
+        }
+
+        candidates.retain_mut(|c| match fuzzy_score(&self.query, &c.command) {
+            Some(score) => {
+                c.score = score;
+                true
+            }
+            None => false,
+        });
+        candidates.sort_by(|a, b| {
+            b.rank()
+                .total_cmp(&a.rank())
+                .then(b.last_used.cmp(&a.last_used))
+        });
+
+        self.matches = candidates;
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let width = width as usize;
+        let rows = height.saturating_sub(HEADER_LINES).max(1) as usize;
+
+        // Keep the selection on screen
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        queue!(out, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let theme = theme::current();
+        queue!(
+            out,
+            PrintStyledContent(theme.paint(Role::Symbol, "history❯ ")),
+            Print(truncate(&self.query, width.saturating_sub(9)))
+        )?;
+
+        let toggle = |on: bool| if on { "on" } else { "off" };
+        let status = format!(
+            "{}/{}  ^D this dir: {}  ^S successful only: {}  ↑↓ select  ⏎ accept  esc cancel",
+            self.matches.len(),
+            self.entries.len(),
+            toggle(self.here_only),
+            toggle(self.success_only)
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, 1),
+            PrintStyledContent(theme.paint(Role::Muted, truncate(&status, width)))
+        )?;
+
+        let now = Utc::now();
+        for (row, candidate) in self.matches.iter().skip(self.scroll).take(rows).enumerate() {
+            let selected = self.scroll + row == self.selected;
+            let (status, role) = match candidate.exit_code {
+                Some(0) => ("  ✓ ".to_string(), Role::Success),
+                Some(code) => (format!("{:>3} ", code), Role::Error),
+                None => ("  ? ".to_string(), Role::Muted),
+            };
+            let meta = format!(
+                "{:>8}  {:<width$}  ",
+                relative_time(now - candidate.last_used),
+                shorten_cwd(candidate.cwd.as_deref().unwrap_or("?")),
+                width = CWD_WIDTH
+            );
+            let command_width = width.saturating_sub(status.chars().count() + meta.chars().count());
+
+            // Styled text resets attributes after itself, so each part carries the highlight
+            let style = |mut style: ContentStyle| {
+                if selected {
+                    style.attributes.set(Attribute::Reverse);
+                }
+                style
+            };
+            queue!(
+                out,
+                cursor::MoveTo(0, row as u16 + HEADER_LINES),
+                PrintStyledContent(style(theme.get(role)).apply(status)),
+                PrintStyledContent(style(theme.get(Role::Muted)).apply(meta)),
+                PrintStyledContent(
+                    style(ContentStyle::new()).apply(truncate(&candidate.command, command_width))
+                )
+            )?;
+        }
+
+        out.flush()
+    }
+}
+
+/// Frecency weight of one use, by how long ago it happened
+fn recency_weight(age: Duration) -> f64 {
+    if age < Duration::hours(1) {
+        4.0
+    } else if age < Duration::days(1) {
+        2.0
+    } else if age < Duration::weeks(1) {
+        1.0
+    } else {
+        0.25
+    }
+}
+
+/// Score `text` against `query` as an in-order subsequence, or `None` if it
+/// doesn't match. Consecutive and word-start matches score higher; the match
+/// is case-insensitive unless the query has uppercase letters.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_ascii_lowercase()
+        }
+    };
+    let text: Vec<char> = text.chars().collect();
+
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()).map(fold) {
+        let found = (next..text.len()).find(|&i| fold(text[i]) == q)?;
+        score += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        match previous {
+            Some(p) if p + 1 == found => score += 5,
+            Some(p) => score -= (found - p - 1).min(3) as i64,
+            None => {}
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+/// Compact age such as "now", "5m ago" or "3d ago"
+fn relative_time(age: Duration) -> String {
+    if age < Duration::minutes(1) {
+        "now".to_string()
+    } else if age < Duration::hours(1) {
+        format!("{}m ago", age.num_minutes())
+    } else if age < Duration::days(1) {
+        format!("{}h ago", age.num_hours())
+    } else if age < Duration::weeks(1) {
+        format!("{}d ago", age.num_days())
+    } else {
+        format!("{}w ago", age.num_weeks())
+    }
+}
+
+/// Replace the home directory with `~` and keep the tail of long paths
+pub fn shorten_cwd(cwd: &str) -> String {
+    let cwd = match dirs::home_dir() {
+        Some(home) => match std::path::Path::new(cwd).strip_prefix(&home) {
+            Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+            Ok(rest) => format!("~/{}", rest.display()),
+            Err(_) => cwd.to_string(),
+        },
+        None => cwd.to_string(),
+    };
+
+    let len = cwd.chars().count();
+    if len <= CWD_WIDTH {
+        cwd
+    } else {
+        let tail: String = cwd.chars().skip(len - (CWD_WIDTH - 1)).collect();
+        format!("…{}", tail)
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    // Commands may span lines; keep each row on one screen line
+    let text = text.replace('\n', "⏎");
+    if text.chars().count() <= width {
+        text
+    } else {
+        let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, exit_code: i32, cwd: &str, hours_ago: i64) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(command.to_string()).with_exit_code(exit_code);
+        entry.cwd = Some(cwd.to_string());
+        entry.timestamp = Utc::now() - Duration::hours(hours_ago);
+        entry
+    }
+
+    fn commands(picker: &Picker) -> Vec<&str> {
+        picker.matches.iter().map(|c| c.command.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gco", "git checkout").is_some());
+        assert!(fuzzy_score("xyz", "git checkout").is_none());
+        // Consecutive matches beat scattered ones
+        assert!(fuzzy_score("check", "git checkout") > fuzzy_score("check", "cargo heck"));
+        // Smart case
+        assert!(fuzzy_score("Make", "make").is_none());
+        assert!(fuzzy_score("make", "Makefile").is_some());
+    }
+
+    #[test]
+    fn test_frecency_ranking() {
+        let entries = vec![
+            entry("cargo build", 0, "/a", 24 * 30),
+            entry("cargo build", 0, "/a", 24 * 20),
+            entry("cargo test", 0, "/a", 0),
+            entry("cargo test", 0, "/a", 0),
+            entry("ls", 0, "/a", 0),
+        ];
+        let picker = Picker::new(entries, "cargo");
+        assert_eq!(commands(&picker), vec!["cargo test", "cargo build"]);
+    }
+
+    #[test]
+    fn test_frecency_outweighs_slightly_tighter_match() {
+        let mut entries = vec![entry("gc --aggressive", 0, "/a", 24 * 30)];
+        entries.extend((0..5).map(|_| entry("git commit", 0, "/a", 0)));
+        // "gc" matches the one-off contiguously, the habit with a gap
+        assert!(fuzzy_score("gc", "gc --aggressive") > fuzzy_score("gc", "git commit"));
+        let picker = Picker::new(entries, "gc");
+        assert_eq!(commands(&picker), vec!["git commit", "gc --aggressive"]);
+    }
+
+    #[test]
+    fn test_toggles_and_accept() {
+        let mut picker = Picker::new(
+            vec![
+                entry("make", 2, "/elsewhere", 1),
+                entry("make test", 0, "/elsewhere", 1),
+            ],
+            "",
+        );
+        picker.cwd = Some("/here".to_string());
+        assert_eq!(picker.matches.len(), 2);
+
+        picker.handle_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        assert_eq!(commands(&picker), vec!["make test"]);
+
+        picker.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        assert!(picker.matches.is_empty());
+        assert_eq!(
+            picker.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+            Action::Cancel
+        );
+
+        picker.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        assert_eq!(
+            picker.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+            Action::Accept("make test".to_string())
+        );
+    }
+}
@@ -0,0 +1,666 @@
+//! Prompt module for Fool Shell
+//! Prompt templates built from segments, styles and conditional groups
+//!
+//! Template syntax:
+//! - `{name}` or `{name:arg}` inserts a segment (see `Segment`)
+//! - `<green bold>...</>` styles the enclosed text; `</>` may be omitted at the end.
+//!   Theme styles can be named too, as in `<cwd>` or `<error bold>`
+//! - `(...)` is shown only if a segment inside it has a value
+//! - `\` escapes the next character
+
+use crate::config::{PromptConfig, UiConfig};
+use crate::context;
+use crate::history::format_duration;
+use crate::theme::{self, merge, Role, Theme};
+use anyhow::{bail, Result};
+use chrono::format::{Item, StrftimeItems};
+use crossterm::style::{ContentStyle, StyledContent};
+use std::cell::OnceCell;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use unicode_width::UnicodeWidthStr;
+
+/// The built-in prompt: [N] user cwd [took 3.2s] ❯
+pub const DEFAULT_TEMPLATE: &str =
+    "(<muted>[{mode}]</> )<user>{user}</> <cwd>{cwd}</> (<duration>took {duration} </>)<symbol>❯</> ";
+
+/// What accepted lines keep of the prompt with `prompt.transient`
+pub const DEFAULT_TRANSIENT_TEMPLATE: &str = "<symbol>❯</> ";
+
+const DEFAULT_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// Values from the shell itself that segments show
+#[derive(Debug, Clone, Default)]
+pub struct PromptState {
+    pub last_status: Option<i32>, // Exit code of the previous command
+    pub took: Option<Duration>,   // How long the previous command ran
+    pub ai_model: Option<String>, // Set when AI is configured
+    pub vi: bool,                 // Editing with vi keys, so `{mode}` has a value
+}
+
+/// Vi editing mode shown by the `{mode}` segment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ViMode {
+    #[default]
+    Insert,
+    Normal,
+}
+
+/// A piece of information a template can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    User,
+    Host,
+    Cwd,      // arg: number of trailing directories to keep
+    Git,      // Branch name
+    GitDirty, // arg: marker shown for uncommitted changes (default "*")
+    Status,   // Exit code of the previous command, if it failed
+    Duration, // Previous command's duration, past prompt.duration_threshold_ms
+    Time,     // arg: strftime format
+    Venv,     // Python virtualenv or conda environment
+    Ai,       // arg: text shown instead of the model name
+    Mode,     // Vi insert/normal indicator
+}
+
+impl Segment {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "user" => Some(Self::User),
+            "host" => Some(Self::Host),
+            "cwd" => Some(Self::Cwd),
+            "git" => Some(Self::Git),
+            "git_dirty" => Some(Self::GitDirty),
+            "status" => Some(Self::Status),
+            "duration" => Some(Self::Duration),
+            "time" => Some(Self::Time),
+            "venv" => Some(Self::Venv),
+            "ai" => Some(Self::Ai),
+            "mode" => Some(Self::Mode),
+            _ => None,
+        }
+    }
+
+    fn check_arg(self, arg: &str) -> Result<()> {
+        match self {
+            Self::Cwd => {
+                if arg.parse::<usize>().map_or(true, |n| n == 0) {
+                    bail!("{{cwd:{}}} needs a positive number of directories", arg);
+                }
+            }
+            Self::Time => {
+                if StrftimeItems::new(arg).any(|item| matches!(item, Item::Error)) {
+                    bail!("invalid time format '{}'", arg);
+                }
+            }
+            Self::GitDirty | Self::Ai => {}
+            _ => bail!("prompt segment does not take an argument: '{}'", arg),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Segment(Segment, Option<String>),
+    Styled(ContentStyle, Vec<Node>),
+    Group(Vec<Node>), // Dropped when none of its segments has a value
+}
+
+/// A parsed prompt template
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Styles are resolved against `theme` while parsing
+    pub fn parse(source: &str, theme: &Theme) -> Result<Self> {
+        enum Frame {
+            Styled(ContentStyle),
+            Group,
+        }
+        // Each open `<style>` or `(` collects its children in its own list
+        let mut frames: Vec<(Frame, Vec<Node>)> = Vec::new();
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars();
+
+        fn current<'a>(
+            frames: &'a mut [(Frame, Vec<Node>)],
+            nodes: &'a mut Vec<Node>,
+        ) -> &'a mut Vec<Node> {
+            match frames.last_mut() {
+                Some((_, children)) => children,
+                None => nodes,
+            }
+        }
+        let flush = |text: &mut String, target: &mut Vec<Node>| {
+            if !text.is_empty() {
+                target.push(Node::Text(std::mem::take(text)));
+            }
+        };
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => text.extend(chars.next()),
+                '{' => {
+                    let body = take_until(&mut chars, '}')?;
+                    let (name, arg) = match body.split_once(':') {
+                        Some((name, arg)) => (name.trim(), Some(arg.to_string())),
+                        None => (body.trim(), None),
+                    };
+                    let Some(segment) = Segment::parse(name) else {
+                        bail!("unknown prompt segment '{{{}}}'", name);
+                    };
+                    if let Some(arg) = &arg {
+                        segment.check_arg(arg)?;
+                    }
+                    let target = current(&mut frames, &mut nodes);
+                    flush(&mut text, target);
+                    target.push(Node::Segment(segment, arg));
+                }
+                '<' => {
+                    let body = take_until(&mut chars, '>')?;
+                    flush(&mut text, current(&mut frames, &mut nodes));
+                    if body.starts_with('/') {
+                        match frames.pop() {
+                            Some((Frame::Styled(style), children)) => {
+                                current(&mut frames, &mut nodes)
+                                    .push(Node::Styled(style, children));
+                            }
+                            _ => bail!("'</>' without a matching style"),
+                        }
+                    } else {
+                        frames.push((Frame::Styled(theme.style(&body)?), Vec::new()));
+                    }
+                }
+                '(' => {
+                    flush(&mut text, current(&mut frames, &mut nodes));
+                    frames.push((Frame::Group, Vec::new()));
+                }
+                ')' => {
+                    flush(&mut text, current(&mut frames, &mut nodes));
+                    // Styles left open inside the group end with it
+                    loop {
+                        match frames.pop() {
+                            Some((Frame::Styled(style), children)) => {
+                                current(&mut frames, &mut nodes)
+                                    .push(Node::Styled(style, children));
+                            }
+                            Some((Frame::Group, children)) => {
+                                current(&mut frames, &mut nodes).push(Node::Group(children));
+                                break;
+                            }
+                            None => bail!("')' without a matching '('"),
+                        }
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+
+        flush(&mut text, current(&mut frames, &mut nodes));
+        while let Some((frame, children)) = frames.pop() {
+            match frame {
+                Frame::Styled(style) => {
+                    current(&mut frames, &mut nodes).push(Node::Styled(style, children))
+                }
+                Frame::Group => bail!("'(' is never closed"),
+            }
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn render(&self, values: &Values) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, ContentStyle::new(), values, &mut out);
+        out
+    }
+}
+
+/// Read up to `end`, consuming it
+fn take_until(chars: &mut std::str::Chars, end: char) -> Result<String> {
+    let mut body = String::new();
+    for c in chars.by_ref() {
+        if c == end {
+            return Ok(body);
+        }
+        body.push(c);
+    }
+    bail!("missing '{}' in prompt template", end)
+}
+
+/// Returns whether any segment in `nodes` had a value
+fn render_nodes(nodes: &[Node], style: ContentStyle, values: &Values, out: &mut String) -> bool {
+    let mut any = false;
+    for node in nodes {
+        match node {
+            Node::Text(text) => push_styled(out, style, text),
+            Node::Segment(segment, arg) => {
+                if let Some(value) = values.get(*segment, arg.as_deref()) {
+                    push_styled(out, style, &value);
+                    any = true;
+                }
+            }
+            Node::Styled(inner, children) => {
+                any |= render_nodes(children, merge(style, *inner), values, out);
+            }
+            Node::Group(children) => {
+                let mut group = String::new();
+                if render_nodes(children, style, values, &mut group) {
+                    out.push_str(&group);
+                    any = true;
+                }
+            }
+        }
+    }
+    any
+}
+
+fn push_styled(out: &mut String, style: ContentStyle, text: &str) {
+    let _ = write!(out, "{}", StyledContent::new(style, text));
+}
+
+/// Segment values for one prompt, looked up lazily so unused segments
+/// (git in particular) cost nothing
+struct Values<'a> {
+    state: &'a PromptState,
+    config: &'a PromptConfig,
+    cwd: Option<PathBuf>,
+    git_dirty: OnceCell<Option<bool>>,
+    vi_mode: Option<ViMode>, // Set while rendering for vi editing
+}
+
+impl Values<'_> {
+    fn get(&self, segment: Segment, arg: Option<&str>) -> Option<String> {
+        let value = match segment {
+            Segment::User => Some(std::env::var("USER").unwrap_or_else(|_| "user".to_string())),
+            Segment::Host => hostname(),
+            Segment::Cwd => Some(match &self.cwd {
+                Some(cwd) => display_cwd(cwd, arg.and_then(|n| n.parse().ok())),
+                None => "?".to_string(),
+            }),
+            Segment::Git => context::git_branch(self.cwd.as_deref()?),
+            Segment::GitDirty => {
+                let dirty = self
+                    .git_dirty
+                    .get_or_init(|| context::git_dirty(self.cwd.as_deref()?));
+                (*dirty == Some(true)).then(|| arg.unwrap_or("*").to_string())
+            }
+            Segment::Status => self
+                .state
+                .last_status
+                .filter(|code| *code != 0)
+                .map(|code| code.to_string()),
+            Segment::Duration => self
+                .state
+                .took
+                .filter(|d| {
+                    self.config.show_duration
+                        && d.as_millis() >= self.config.duration_threshold_ms as u128
+                })
+                .map(format_duration),
+            Segment::Time => Some(
+                chrono::Local::now()
+                    .format(arg.unwrap_or(DEFAULT_TIME_FORMAT))
+                    .to_string(),
+            ),
+            Segment::Venv => std::env::var("VIRTUAL_ENV")
+                .ok()
+                .and_then(|path| {
+                    Path::new(&path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                })
+                .or_else(|| std::env::var("CONDA_DEFAULT_ENV").ok()),
+            Segment::Ai => self
+                .state
+                .ai_model
+                .as_ref()
+                .map(|model| arg.unwrap_or(model).to_string()),
+            Segment::Mode => self.vi_mode.map(|mode| self.mode_indicator(mode)),
+        };
+        value.filter(|v| !v.is_empty())
+    }
+}
+
+impl Values<'_> {
+    /// Both indicators are padded to the same width, so switching modes
+    /// never moves the cursor
+    fn mode_indicator(&self, mode: ViMode) -> String {
+        let insert = &self.config.vi_insert_indicator;
+        let normal = &self.config.vi_normal_indicator;
+        let text = match mode {
+            ViMode::Insert => insert,
+            ViMode::Normal => normal,
+        };
+        let width = insert.width().max(normal.width());
+        format!("{}{}", text, " ".repeat(width - text.width()))
+    }
+}
+
+/// `cwd` with the home directory as `~`, keeping only the last `keep` directories
+fn display_cwd(cwd: &Path, keep: Option<usize>) -> String {
+    let home = dirs::home_dir();
+    let (prefix, rest) = match home.as_deref().and_then(|h| cwd.strip_prefix(h).ok()) {
+        Some(rest) => ("~", rest),
+        None => ("", cwd),
+    };
+    let parts: Vec<String> = rest
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    match keep {
+        Some(keep) if parts.len() > keep => {
+            format!("…/{}", parts[parts.len() - keep..].join("/"))
+        }
+        _ if prefix.is_empty() => cwd.display().to_string(),
+        _ if parts.is_empty() => "~".to_string(),
+        _ => format!("~/{}", parts.join("/")),
+    }
+}
+
+/// Short host name, without the domain
+fn hostname() -> Option<String> {
+    let name = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())?;
+    let name = name.trim();
+    Some(name.split('.').next().unwrap_or(name).to_string())
+}
+
+/// Width of `text` on screen, ignoring escape sequences
+pub fn display_width(text: &str) -> usize {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain.width()
+}
+
+/// Prompts for one line of input
+#[derive(Debug, Clone, Default)]
+pub struct Prompts {
+    pub left: String,
+    pub right: String,                       // May be empty
+    pub vi_normal: Option<(String, String)>, // Left and right in vi normal mode
+    pub transient: Option<String>,           // Replaces left once the line is accepted
+}
+
+/// Shell prompt generator
+pub struct Prompt {
+    left: Template,
+    right: Template,
+    transient: Option<Template>, // Set when prompt.transient is on
+    config: PromptConfig,
+}
+
+impl Prompt {
+    /// Templates were checked by `Config::load`; any that still fail to
+    /// parse fall back to the built-in prompt
+    pub fn new(ui: &UiConfig, config: &PromptConfig) -> Self {
+        let theme = theme::current();
+        let left = Template::parse(&ui.prompt, theme)
+            .or_else(|_| Template::parse(DEFAULT_TEMPLATE, theme))
+            .expect("built-in prompt template parses");
+        let right =
+            Template::parse(&ui.right_prompt, theme).unwrap_or(Template { nodes: Vec::new() });
+        let transient = config.transient.then(|| {
+            Template::parse(&config.transient_prompt, theme)
+                .or_else(|_| Template::parse(DEFAULT_TRANSIENT_TEMPLATE, theme))
+                .expect("built-in transient prompt template parses")
+        });
+        Self {
+            left,
+            right,
+            transient,
+            config: config.clone(),
+        }
+    }
+
+    /// The left and right prompts, and in vi mode their normal-mode versions
+    pub fn generate(&self, state: &PromptState) -> Prompts {
+        let mut values = Values {
+            state,
+            config: &self.config,
+            cwd: std::env::current_dir().ok(),
+            git_dirty: OnceCell::new(),
+            vi_mode: state.vi.then_some(ViMode::Insert),
+        };
+        let (left, right) = self.render(&values);
+        let transient = self.transient.as_ref().map(|t| t.render(&values));
+        let vi_normal = state.vi.then(|| {
+            values.vi_mode = Some(ViMode::Normal);
+            self.render(&values)
+        });
+        Prompts {
+            left,
+            right,
+            vi_normal,
+            transient,
+        }
+    }
+
+    fn render(&self, values: &Values) -> (String, String) {
+        let right = if self.right.is_empty() {
+            String::new()
+        } else {
+            self.right.render(values)
+        };
+        (self.left.render(values), right)
+    }
+
+    /// Uncoloured prompt for when stdout is not a terminal
+    pub fn generate_plain() -> String {
+        let cwd = std::env::current_dir()
+            .map(|p| display_cwd(&p, None))
+            .unwrap_or_else(|_| "?".to_string());
+
+        let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+        format!("{} {} ❯ ", user, cwd)
+    }
+}
+
+/// A rule `width` columns wide after a command: ── ✔ 0 · 1.2s ───
+pub fn separator(status: i32, took: Duration, fill: &str, width: usize) -> String {
+    let (role, mark) = if status == 0 {
+        (Role::Success, "✔")
+    } else {
+        (Role::Error, "✘")
+    };
+    let label = format!("{} {}", mark, status);
+    let took = format_duration(took);
+    // Two fill characters, then the label, a dot and the duration between spaces
+    let used = 2 + label.width() + took.width() + 5;
+    format!(
+        "{} {} {} {} {}",
+        theme::paint(Role::Muted, fill.repeat(2)),
+        theme::paint(role, label),
+        theme::paint(Role::Muted, "·"),
+        theme::paint(Role::Duration, took),
+        theme::paint(Role::Muted, fill.repeat(width.saturating_sub(used)))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::ColorSupport;
+    use crossterm::style::Color;
+
+    fn theme() -> Theme {
+        Theme::builtin("dracula", ColorSupport::TrueColor).unwrap()
+    }
+
+    fn render(template: &str, state: &PromptState) -> String {
+        let values = Values {
+            state,
+            config: &PromptConfig::default(),
+            cwd: Some(PathBuf::from("/srv/app/src/bin")),
+            git_dirty: OnceCell::from(Some(true)),
+            vi_mode: state.vi.then_some(ViMode::Normal),
+        };
+        Template::parse(template, &theme()).unwrap().render(&values)
+    }
+
+    #[test]
+    fn test_segments_and_groups() {
+        let failed = PromptState {
+            last_status: Some(2),
+            took: Some(Duration::from_secs(5)),
+            ai_model: None,
+            vi: false,
+        };
+        assert_eq!(
+            render("{cwd:2}( [{status}]) took {duration}{git_dirty:!}", &failed),
+            "…/src/bin [2] took 5.0s!"
+        );
+
+        // Groups vanish with their segments; quick successful commands show neither
+        let ok = PromptState {
+            last_status: Some(0),
+            took: Some(Duration::from_millis(300)),
+            ai_model: Some("gpt-4o".to_string()),
+            vi: false,
+        };
+        assert_eq!(
+            render("{cwd}( [{status}])( {duration})( {ai}) \\(x\\)", &ok),
+            "/srv/app/src/bin gpt-4o (x)"
+        );
+        assert_eq!(render("({ai:✦} )❯", &ok), "✦ ❯");
+        assert_eq!(render("({mode} )❯", &ok), "❯");
+
+        // Indicators are padded to the same width
+        let vi = PromptState {
+            vi: true,
+            ..PromptState::default()
+        };
+        assert_eq!(render("({mode} )❯", &vi), "N ❯");
+        let config = PromptConfig {
+            vi_insert_indicator: "INSERT".to_string(),
+            ..PromptConfig::default()
+        };
+        let values = Values {
+            state: &vi,
+            config: &config,
+            cwd: None,
+            git_dirty: OnceCell::new(),
+            vi_mode: Some(ViMode::Normal),
+        };
+        assert_eq!(values.mode_indicator(ViMode::Normal), "N     ");
+    }
+
+    #[test]
+    fn test_styles() {
+        let state = PromptState::default();
+        let theme = theme();
+        let red = render("<red>a<bold>b</>c", &state);
+        assert_eq!(
+            red,
+            format!(
+                "{}{}{}",
+                StyledContent::new(theme.style("red").unwrap(), "a"),
+                StyledContent::new(theme.style("red bold").unwrap(), "b"),
+                StyledContent::new(theme.style("red").unwrap(), "c"),
+            )
+        );
+        assert_eq!(
+            render("<cwd>x", &state),
+            theme.paint(Role::Cwd, "x").to_string()
+        );
+        assert_eq!(display_width(&red), 3);
+        assert_eq!(display_width("用户 ❯ "), 7);
+
+        assert_eq!(
+            theme.style("bg:#ff8700,208").unwrap().background_color,
+            Some(Color::Rgb {
+                r: 0xff,
+                g: 0x87,
+                b: 0
+            })
+        );
+        assert!(Template::parse(DEFAULT_TEMPLATE, &theme).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "{nope}",
+            "{cwd",
+            "{cwd:0}",
+            "{user:x}",
+            "{time:%Q}",
+            "<purple>x",
+            "a</>",
+            "(x",
+            "x)",
+        ] {
+            assert!(
+                Template::parse(bad, &theme()).is_err(),
+                "{} should not parse",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_transient_and_separator() {
+        let config = PromptConfig {
+            transient: true,
+            transient_prompt: "{status}❯ ".to_string(),
+            ..PromptConfig::default()
+        };
+        let prompt = Prompt::new(&UiConfig::default(), &config);
+        let state = PromptState {
+            last_status: Some(3),
+            ..PromptState::default()
+        };
+        let prompts = prompt.generate(&state);
+        assert_eq!(prompts.transient.as_deref().map(display_width), Some(3));
+        let prompt = Prompt::new(&UiConfig::default(), &PromptConfig::default());
+        assert!(prompt.generate(&state).transient.is_none());
+
+        let line = separator(1, Duration::from_millis(1500), "─", 40);
+        assert_eq!(display_width(&line), 40);
+        assert!(line.contains("✘ 1"));
+        // Narrow terminals get no trailing rule rather than a wrapped one
+        assert_eq!(
+            display_width(&separator(0, Duration::from_secs(1), "=", 5)),
+            display_width(&separator(0, Duration::from_secs(1), "=", 0))
+        );
+    }
+
+    #[test]
+    fn test_display_cwd() {
+        assert_eq!(display_cwd(Path::new("/"), None), "/");
+        assert_eq!(display_cwd(Path::new("/a/b/c"), Some(2)), "…/b/c");
+        assert_eq!(display_cwd(Path::new("/a/b"), Some(2)), "/a/b");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(display_cwd(&home, None), "~");
+            assert_eq!(display_cwd(&home.join("x/y"), None), "~/x/y");
+        }
+    }
+}
@@ -1,104 +1,239 @@
 //! REPL module for Fool Shell
 //! Handles interactive shell with syntax highlighting and completions
 
-use crate::ai::AiAgent;
-use crate::config::Config;
-use crate::executor::Executor;
-use crate::history::{History, HistoryEntry};
-use crate::parser::{ParseResult, Parser};
+use crate::abbr::{self, Abbreviations, SharedAbbreviations};
+use crate::ai::{render_markdown, AiAgent, AiBuiltin, ChatMessage};
+use crate::answers::{AnswerEntry, AnswerStore};
+use crate::attach;
+use crate::bindings::{self, Action, Deferred, DeferredHandler, DeferredSlot, ViModeTracker};
+use crate::completion::ShellCompleter;
+use crate::config::{self, Config};
+use crate::context::ContextBuilder;
+use crate::editor;
+use crate::executor::{BuiltinCommand, ExecutionResult, Executor};
+use crate::hint::ContextHinter;
+use crate::history::{self, format_local_time, History, HistoryEntry, SharedHistory};
+use crate::notify::{Notifier, Typeahead};
+use crate::parser::{ParseResult, Parser, TokenKind};
+use crate::picker::Picker;
+use crate::prompt::{self, Prompt, PromptState, Prompts, ViMode};
+use crate::suggest::{AiSuggester, Repaint};
+use crate::theme::{paint, Role};
 use anyhow::Result;
-use crossterm::style::{Color, Stylize};
-use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use crossterm::style::Stylize;
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
 use rustyline::hint::{Hinter, HistoryHinter};
 use rustyline::history::DefaultHistory;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
-use rustyline::{CompletionType, Config as RLConfig, Context, EditMode, Editor, Helper};
+use rustyline::{
+    Cmd, CompletionType, ConditionalEventHandler, Config as RLConfig, Context, EditMode, Editor,
+    Event, EventContext, EventHandler, ExternalPrinter, Helper, InputMode, KeyEvent, Movement,
+    RepeatCount,
+};
 use std::borrow::Cow;
-use std::collections::HashSet;
-
-/// Shell prompt generator
-pub struct Prompt;
-
-impl Prompt {
-    pub fn generate() -> String {
-        let cwd = std::env::current_dir()
-            .map(|p| {
-                let home = dirs::home_dir();
-                if let Some(ref home) = home {
-                    if p.starts_with(home) {
-                        format!("~{}", p.strip_prefix(home).unwrap().display())
-                    } else {
-                        p.display().to_string()
-                    }
-                } else {
-                    p.display().to_string()
-                }
-            })
-            .unwrap_or_else(|_| "?".to_string());
-
-        let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
-
-        // Colorful prompt: user@cwd >
-        format!(
-            "{} {} {} ",
-            user.with(Color::Green).bold(),
-            cwd.with(Color::Blue).bold(),
-            "❯".with(Color::Magenta).bold()
-        )
-    }
-
-    #[allow(dead_code)] // Alternative prompt for non-TTY environments
-    pub fn generate_plain() -> String {
-        let cwd = std::env::current_dir()
-            .map(|p| {
-                let home = dirs::home_dir();
-                if let Some(ref home) = home {
-                    if p.starts_with(home) {
-                        format!("~{}", p.strip_prefix(home).unwrap().display())
-                    } else {
-                        p.display().to_string()
-                    }
-                } else {
-                    p.display().to_string()
-                }
-            })
-            .unwrap_or_else(|_| "?".to_string());
+use std::collections::VecDeque;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-        let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
-        format!("{} {} ❯ ", user, cwd)
-    }
+/// Lock state shared with editor handlers, even if one of them panicked
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-/// Known shell commands for highlighting
-fn get_known_commands() -> HashSet<String> {
-    let commands = vec![
-        "ls", "cd", "pwd", "cat", "grep", "find", "echo", "rm", "cp", "mv", "mkdir", "rmdir",
-        "touch", "chmod", "chown", "head", "tail", "less", "more", "vim", "nano", "git", "docker",
-        "cargo", "npm", "python", "pip", "node", "make", "gcc", "g++", "rustc", "ssh", "scp",
-        "curl", "wget", "tar", "zip", "unzip", "ps", "top", "htop", "kill", "man", "which",
-        "whereis", "history", "export", "unset", "alias", "source", "exit", "clear", "help",
-        "sudo", "apt", "yum", "dnf", "pacman",
-    ];
-    commands.into_iter().map(String::from).collect()
+/// What the editor is given to print to get a redraw: rustyline puts
+/// printed text on its own line above the input, and moving up first
+/// leaves nothing behind
+const REPAINT: &str = "\x1b[A";
+
+/// How a streamed AI request ended
+enum AiOutcome {
+    Success(String),
+    Error(String),
+    Cancelled,
 }
 
 /// Custom helper for rustyline with highlighting and completion
 pub struct FoolHelper {
-    completer: FilenameCompleter,
+    completer: ShellCompleter,
     hinter: HistoryHinter,
-    known_commands: HashSet<String>,
+    context_hinter: Option<ContextHinter>, // None = plain most-recent history hints
+    parser: Parser,                        // Tokens for highlighting
     ai_trigger: String,
+    suggester: Option<AiSuggester>,
+    recent_commands: Vec<String>, // Context for AI suggestions, refreshed before each prompt
+    prompts: Prompts,             // The right prompt is drawn at the right edge of the line
+    prompt_width: usize,          // Width of the prompt's last line
+    vi_mode: Arc<Mutex<ViMode>>,  // Kept up to date by `ViModeTracker`
+    deferred: DeferredSlot,       // Binding to run once readline returns
 }
 
 impl FoolHelper {
-    pub fn new(ai_trigger: String) -> Self {
+    pub fn new(
+        ai_trigger: String,
+        completer: ShellCompleter,
+        context_hinter: Option<ContextHinter>,
+        suggester: Option<AiSuggester>,
+    ) -> Self {
         Self {
-            completer: FilenameCompleter::new(),
+            completer,
             hinter: HistoryHinter::new(),
-            known_commands: get_known_commands(),
+            context_hinter,
+            parser: Parser::new(ai_trigger.clone()),
             ai_trigger,
+            suggester,
+            recent_commands: Vec::new(),
+            prompts: Prompts::default(),
+            prompt_width: 0,
+            vi_mode: Arc::new(Mutex::new(ViMode::Insert)),
+            deferred: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Set before each prompt; `prompts.left` is the prompt passed to readline
+    pub fn set_prompts(&mut self, prompts: Prompts) {
+        let left = &prompts.left;
+        self.prompt_width = prompt::display_width(left.rsplit('\n').next().unwrap_or(left));
+        self.prompts = prompts;
+        // Every line starts in insert mode
+        *lock(&self.vi_mode) = ViMode::Insert;
+    }
+
+    /// Shared with the handler that follows vi mode changes
+    pub fn vi_mode_handle(&self) -> Arc<Mutex<ViMode>> {
+        Arc::clone(&self.vi_mode)
+    }
+
+    /// Shared with the handlers of deferred bindings
+    pub fn deferred_slot(&self) -> DeferredSlot {
+        Arc::clone(&self.deferred)
+    }
+
+    /// Column the right prompt starts at, if it fits beside `used` columns
+    fn right_prompt_column(&self, used: usize) -> Option<usize> {
+        if self.prompts.right.is_empty() {
+            return None;
+        }
+        let (cols, _) = crossterm::terminal::size().ok()?;
+        // Both vi variants have the same width
+        let width = prompt::display_width(&self.prompts.right);
+        // Leave a gap so the cursor never sits on the right prompt
+        (used + width + 2 <= cols as usize).then(|| cols as usize - width + 1)
+    }
+
+    /// Whether AI suggestions are active for this editor
+    pub fn has_suggester(&self) -> bool {
+        self.suggester.is_some()
+    }
+
+    /// Let AI suggestions redraw the line when they arrive
+    pub fn set_repaint(&self, repaint: Repaint) {
+        if let Some(suggester) = &self.suggester {
+            suggester.set_repaint(repaint);
+        }
+    }
+
+    /// Update the alias names offered in command position
+    pub fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.completer.set_aliases(aliases);
+    }
+
+    /// Update the recent commands sent to the AI suggester
+    pub fn set_recent_commands(&mut self, commands: Vec<String>) {
+        self.recent_commands = commands;
+    }
+}
+
+impl FoolHelper {
+    /// Complete the file name after an `@` at the start of the current word
+    fn complete_attachment(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let word_start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let Some(path) = line[word_start..pos].strip_prefix('@') else {
+            return Ok((pos, vec![]));
+        };
+
+        let (start, candidates) = self.completer.complete_path(path, path.len())?;
+        Ok((word_start + 1 + start, candidates))
+    }
+}
+
+/// Accept the current hint with Ctrl-F, which otherwise only moves forward
+struct AcceptHintHandler;
+
+impl ConditionalEventHandler for AcceptHintHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        if ctx.has_hint() && ctx.pos() == ctx.line().len() {
+            Some(Cmd::CompleteHint)
+        } else {
+            None // Fall back to the default binding
+        }
+    }
+}
+
+/// Expand an abbreviation when space is typed right after it
+struct AbbreviationHandler {
+    abbreviations: SharedAbbreviations,
+}
+
+impl ConditionalEventHandler for AbbreviationHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        // Space is a movement in vi normal mode
+        if ctx.input_mode() == InputMode::Command {
+            return None;
+        }
+        let abbreviations = abbr::lock(&self.abbreviations);
+        let (start, expansion) = abbreviations.expansion_at(ctx.line(), ctx.pos())?;
+        let name_len = ctx.line()[start..ctx.pos()].chars().count();
+        Some(Cmd::Replace(
+            Movement::BackwardChar(name_len as RepeatCount),
+            Some(format!("{} ", expansion)),
+        ))
+    }
+}
+
+/// Open the fuzzy history picker on Ctrl-R
+struct HistoryPickerHandler {
+    history: SharedHistory,
+}
+
+impl ConditionalEventHandler for HistoryPickerHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        let entries: Vec<HistoryEntry> = {
+            let history = history::lock(&self.history);
+            history
+                .get_recent(history.len())
+                .into_iter()
+                .cloned()
+                .collect()
+        };
+
+        match Picker::new(entries, ctx.line()).run() {
+            Ok(Some(command)) => Some(Cmd::Replace(Movement::WholeBuffer, Some(command))),
+            Ok(None) => Some(Cmd::Repaint),
+            Err(_) => None, // No usable terminal: fall back to reverse search
         }
     }
 }
@@ -110,15 +245,14 @@ impl Completer for FoolHelper {
         &self,
         line: &str,
         pos: usize,
-        ctx: &Context<'_>,
+        _ctx: &Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Pair>)> {
-        // Don't complete in AI mode
+        // In AI mode only `@path` attachments are completed
         if line.trim_start().starts_with(&self.ai_trigger) {
-            return Ok((pos, vec![]));
+            return self.complete_attachment(line, pos);
         }
 
-        // Use filename completer
-        self.completer.complete(line, pos, ctx)
+        self.completer.complete(line, pos)
     }
 }
 
@@ -131,86 +265,59 @@ impl Hinter for FoolHelper {
             return None;
         }
 
-        self.hinter.hint(line, pos, ctx)
+        let hint = match &self.context_hinter {
+            Some(hinter) if pos == line.len() => hinter.hint(line),
+            Some(_) => None,
+            None => self.hinter.hint(line, pos, ctx),
+        };
+        if hint.is_some() {
+            return hint;
+        }
+
+        // Ghost text only makes sense with the cursor at the end of the line
+        if pos < line.len() {
+            return None;
+        }
+        self.suggester.as_ref()?.hint(line, &self.recent_commands)
     }
 }
 
 impl Highlighter for FoolHelper {
     fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
-        let trimmed = line.trim_start();
-
-        // AI mode highlighting
-        if trimmed.starts_with(&self.ai_trigger) {
-            return Cow::Owned(format!(
-                "{}{}",
-                self.ai_trigger.clone().with(Color::Yellow).bold(),
-                &trimmed[self.ai_trigger.len()..].with(Color::Cyan)
-            ));
+        let mut highlighted = self.highlight_line(line);
+        // Once the input reaches the right prompt, clear what's left of it
+        let reaches_right = !self.prompts.right.is_empty()
+            && (line.contains('\n')
+                || self
+                    .right_prompt_column(self.prompt_width + prompt::display_width(line))
+                    .is_none());
+        if reaches_right {
+            highlighted.push_str("\x1b[K");
         }
-
-        // Simple syntax highlighting
-        let mut result = String::new();
-        let mut in_string = false;
-        let mut string_char = ' ';
-        let mut current_word = String::new();
-        let mut is_first_word = true;
-
-        for c in line.chars() {
-            if in_string {
-                current_word.push(c);
-                if c == string_char {
-                    result.push_str(&current_word.clone().with(Color::Green).to_string());
-                    current_word.clear();
-                    in_string = false;
-                }
-            } else if c == '"' || c == '\'' {
-                // Flush current word
-                if !current_word.is_empty() {
-                    result.push_str(&self.colorize_word(&current_word, is_first_word));
-                    is_first_word = false;
-                    current_word.clear();
-                }
-                in_string = true;
-                string_char = c;
-                current_word.push(c);
-            } else if c.is_whitespace() || c == '|' || c == '>' || c == '<' {
-                if !current_word.is_empty() {
-                    result.push_str(&self.colorize_word(&current_word, is_first_word));
-                    is_first_word = c == '|'; // Reset after pipe
-                    current_word.clear();
-                }
-                if c == '|' || c == '>' || c == '<' {
-                    result.push_str(&c.to_string().with(Color::Magenta).to_string());
-                } else {
-                    result.push(c);
-                }
-            } else {
-                current_word.push(c);
-            }
-        }
-
-        // Flush remaining
-        if !current_word.is_empty() {
-            if in_string {
-                result.push_str(&current_word.with(Color::Green).to_string());
-            } else {
-                result.push_str(&self.colorize_word(&current_word, is_first_word));
-            }
-        }
-
-        Cow::Owned(result)
+        Cow::Owned(highlighted)
     }
 
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
-        _default: bool,
+        default: bool,
     ) -> Cow<'b, str> {
-        Cow::Borrowed(prompt)
+        let (left, right) = match &self.prompts.vi_normal {
+            Some((left, right)) if default && *lock(&self.vi_mode) == ViMode::Normal => {
+                (left.as_str(), right.as_str())
+            }
+            _ => (prompt, self.prompts.right.as_str()),
+        };
+        match self.right_prompt_column(self.prompt_width) {
+            // Save the cursor, draw at the right edge and come back
+            Some(column) if default => {
+                Cow::Owned(format!("{}\x1b[s\x1b[{}G{}\x1b[u", left, column, right))
+            }
+            _ => Cow::Borrowed(left),
+        }
     }
-
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
-        Cow::Owned(hint.with(Color::DarkGrey).to_string())
+        Cow::Owned(paint(Role::Hint, hint).to_string())
     }
 
     fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
@@ -219,48 +326,63 @@ impl Highlighter for FoolHelper {
 }
 
 impl FoolHelper {
-    fn colorize_word(&self, word: &str, is_command: bool) -> String {
-        if word.starts_with('-') {
-            // Flag/option
-            word.to_string().with(Color::Cyan).to_string()
-        } else if word.starts_with('$') {
-            // Variable
-            word.to_string().with(Color::Yellow).to_string()
-        } else if is_command {
-            if self.known_commands.contains(word) {
-                word.to_string().with(Color::Green).bold().to_string()
-            } else {
-                word.to_string().with(Color::White).to_string()
-            }
-        } else {
-            word.to_string()
+    /// Colour the line from the parser's tokens and underline a syntax error
+    fn highlight_line(&self, line: &str) -> String {
+        let (tokens, error) = self.parser.tokenize(line);
+        let error = error.map(|e| e.span).filter(|span| !span.is_empty());
+
+        let mut result = String::new();
+        let mut pos = 0;
+        for token in tokens {
+            let (start, end) = (token.span.start, token.span.end);
+            result.push_str(&line[pos..start]);
+            let role = self.token_role(token.kind, &line[start..end]);
+            // The error may cover only part of the token, e.g. a trailing `\`
+            let (from, to) = match &error {
+                Some(e) => (e.start.clamp(start, end), e.end.clamp(start, end)),
+                None => (end, end),
+            };
+            push_painted(&mut result, role, &line[start..from]);
+            push_painted(&mut result, Some(Role::SyntaxError), &line[from..to]);
+            push_painted(&mut result, role, &line[to..end]);
+            pos = end;
+        }
+        result.push_str(&line[pos..]);
+        result
+    }
+
+    fn token_role(&self, kind: TokenKind, text: &str) -> Option<Role> {
+        match kind {
+            TokenKind::Command if self.completer.is_command(text) => Some(Role::Command),
+            TokenKind::Command => Some(Role::UnknownCommand),
+            TokenKind::Argument if text.starts_with('-') => Some(Role::Flag),
+            TokenKind::Argument if text.starts_with('$') => Some(Role::Variable),
+            TokenKind::Argument => None,
+            TokenKind::Quote => Some(Role::String),
+            TokenKind::Operator => Some(Role::Operator),
+            TokenKind::RedirectTarget => Some(Role::Redirect),
+            TokenKind::AiTrigger => Some(Role::AiTrigger),
+            TokenKind::AiQuery => Some(Role::AiQuery),
         }
     }
 }
 
+fn push_painted(result: &mut String, role: Option<Role>, text: &str) {
+    match role {
+        _ if text.is_empty() => {}
+        Some(role) => result.push_str(&paint(role, text).to_string()),
+        None => result.push_str(text),
+    }
+}
+
 impl Validator for FoolHelper {
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        let input = ctx.input();
-
-        // Check for unclosed quotes
-        let mut in_single = false;
-        let mut in_double = false;
-        let mut escape = false;
-
-        for c in input.chars() {
-            if escape {
-                escape = false;
-                continue;
-            }
-            match c {
-                '\\' => escape = true,
-                '\'' if !in_double => in_single = !in_single,
-                '"' if !in_single => in_double = !in_double,
-                _ => {}
-            }
+        // A deferred binding ends the edit whatever the line looks like
+        if lock(&self.deferred).is_some() {
+            return Ok(ValidationResult::Valid(None));
         }
-
-        if in_single || in_double {
+        // Unclosed quotes and a trailing `|`, `&&` or `\` continue on the next line
+        if self.parser.is_continued(ctx.input()) {
             Ok(ValidationResult::Incomplete)
         } else {
             Ok(ValidationResult::Valid(None))
@@ -275,42 +397,230 @@ pub struct Repl {
     config: Config,
     parser: Parser,
     executor: Executor,
-    history: History,
+    history: SharedHistory,
+    abbreviations: SharedAbbreviations,
     ai_agent: AiAgent,
+    answers: AnswerStore,
+    prompt: Prompt,
+    last_duration: Option<Duration>, // How long the previous command ran
+    last_status: Option<i32>,        // Exit code of the previous command
+    notifier: Notifier,
+    next_line: Option<String>, // Text the next line starts with
+    queued: VecDeque<String>,  // Commands from the editor, run as if typed
 }
 
 impl Repl {
     pub fn new(config: Config) -> Result<Self> {
         let parser = Parser::new(config.ai.trigger_prefix.clone());
         // M-03: Pass AI trigger prefix to executor for source command
-        let executor = Executor::with_ai_trigger(config.ai.trigger_prefix.clone());
-        let history = History::new(config.history.file_path.clone(), config.history.max_entries)?;
+        let mut executor = Executor::with_ai_trigger(config.ai.trigger_prefix.clone());
+        let history = History::open(&config.history)?;
+        let history: SharedHistory = Arc::new(Mutex::new(history));
+        executor.set_history(Arc::clone(&history));
+        let abbreviations =
+            Abbreviations::new(config.abbreviations.clone(), Config::default_path());
+        let abbreviations: SharedAbbreviations = Arc::new(Mutex::new(abbreviations));
+        executor.set_abbreviations(Arc::clone(&abbreviations));
         let ai_agent = AiAgent::new(config.ai.clone());
+        let notifier = Notifier::new(config.notify.clone());
+        let prompt = Prompt::new(&config.ui, &config.prompt);
+        // Answers quote the questions, which are history lines; the answers
+        // file isn't encrypted, so with encryption on they stay in memory
+        let answers = if config.history.encryption.enabled {
+            AnswerStore::new_memory_only(config.ai.answers.max_entries)
+        } else {
+            AnswerStore::new(
+                config.ai.answers.file_path.clone(),
+                config.ai.answers.max_entries,
+            )?
+        };
 
         Ok(Self {
             config,
             parser,
             executor,
             history,
+            abbreviations,
             ai_agent,
+            answers,
+            prompt,
+            last_duration: None,
+            last_status: None,
+            notifier,
+            next_line: None,
+            queued: VecDeque::new(),
         })
     }
 
+    fn history(&self) -> MutexGuard<'_, History> {
+        history::lock(&self.history)
+    }
+
+    /// Redraw the prompt and line rustyline just accepted: `line` after
+    /// `transient` in place of the whole prompt, or after its last line
+    fn rewrite_accepted(
+        rl: &Editor<FoolHelper, DefaultHistory>,
+        prompt: &str,
+        raw_line: &str,
+        transient: Option<&str>,
+        line: &str,
+    ) {
+        let (Some(helper), Ok((cols, _))) = (rl.helper(), crossterm::terminal::size()) else {
+            return;
+        };
+        if !std::io::stdout().is_terminal() || cols == 0 {
+            return;
+        }
+        let cols = usize::from(cols);
+        let rows = |width: usize| width / cols + 1;
+        // The cursor is below the rows the prompt's last line and the input
+        // took; continuation lines start at the left edge
+        let (last, earlier) = prompt
+            .rsplit_once('\n')
+            .map_or((prompt, ""), |(e, l)| (l, e));
+        let mut input_lines = raw_line.split('\n');
+        let first = input_lines.next().unwrap_or_default();
+        let mut up = rows(prompt::display_width(last) + prompt::display_width(first));
+        up += input_lines
+            .map(|l| rows(prompt::display_width(l)))
+            .sum::<usize>();
+        let new_prompt = match transient {
+            Some(transient) if !earlier.is_empty() => {
+                up += earlier
+                    .split('\n')
+                    .map(|l| rows(prompt::display_width(l)))
+                    .sum::<usize>();
+                transient
+            }
+            Some(transient) => transient,
+            None => last,
+        };
+        print!(
+            "\x1b[{}A\r\x1b[J{}{}\n",
+            up,
+            new_prompt,
+            helper.highlight_line(line)
+        );
+        let _ = std::io::stdout().flush();
+    }
+
+    /// Rule with the exit code and duration after a command, if enabled
+    fn print_separator(&self, status: i32, elapsed: Duration) {
+        if !self.config.prompt.separator || !std::io::stdout().is_terminal() {
+            return;
+        }
+        let width = crossterm::terminal::size().map_or(80, |(cols, _)| usize::from(cols));
+        println!(
+            "{}",
+            prompt::separator(status, elapsed, &self.config.prompt.separator_char, width)
+        );
+    }
+
+    /// Prompts for the next line; the previous command's duration is shown once
+    fn generate_prompt(&mut self) -> Prompts {
+        if !std::io::stdout().is_terminal() {
+            return Prompts {
+                left: Prompt::generate_plain(),
+                ..Prompts::default()
+            };
+        }
+        let state = PromptState {
+            last_status: self.last_status,
+            took: self.last_duration.take(),
+            ai_model: self
+                .ai_agent
+                .is_configured()
+                .then(|| self.config.ai.model.clone()),
+            vi: self.config.ui.edit_mode == config::EditMode::Vi,
+        };
+        self.prompt.generate(&state)
+    }
+
+    /// Merge commands other sessions have run into both histories
+    fn sync_history(&self, rl: &mut Editor<FoolHelper, DefaultHistory>) {
+        match self.history().sync() {
+            Ok(merged) => {
+                for entry in merged {
+                    let _ = rl.add_history_entry(entry.command);
+                }
+            }
+            Err(e) => eprintln!(
+                "{}: Failed to read shared history: {}",
+                paint(Role::Warning, "Warning").bold(),
+                e
+            ),
+        }
+    }
+
     /// Run the REPL loop
     pub async fn run(&mut self) -> Result<()> {
         // Configure rustyline
         let rl_config = RLConfig::builder()
-            .history_ignore_space(true)
+            .history_ignore_space(self.config.history.ignore_space)
             .completion_type(CompletionType::List)
-            .edit_mode(EditMode::Emacs)
+            .edit_mode(match self.config.ui.edit_mode {
+                config::EditMode::Emacs => EditMode::Emacs,
+                config::EditMode::Vi => EditMode::Vi,
+            })
             .build();
 
-        let helper = FoolHelper::new(self.config.ai.trigger_prefix.clone());
+        let suggester = AiSuggester::new(&self.ai_agent, &self.config.ai);
+        let context_hinter = self.config.history.hints.enabled.then(|| {
+            ContextHinter::new(Arc::clone(&self.history), self.config.history.hints.clone())
+        });
+        let helper = FoolHelper::new(
+            self.config.ai.trigger_prefix.clone(),
+            ShellCompleter::new(&self.config.completion),
+            context_hinter,
+            suggester,
+        );
+        let vi_mode = helper.vi_mode_handle();
+        let deferred = helper.deferred_slot();
         let mut rl: Editor<FoolHelper, DefaultHistory> = Editor::with_config(rl_config)?;
         rl.set_helper(Some(helper));
+        if rl.helper().is_some_and(FoolHelper::has_suggester) {
+            if let Ok(printer) = rl.create_external_printer() {
+                let printer = Mutex::new(printer);
+                if let Some(helper) = rl.helper() {
+                    helper.set_repaint(Arc::new(move || {
+                        let _ = lock(&printer).print(REPAINT.to_string());
+                    }));
+                }
+            }
+        }
+        rl.bind_sequence(
+            KeyEvent::ctrl('F'),
+            EventHandler::Conditional(Box::new(AcceptHintHandler)),
+        );
+        rl.bind_sequence(
+            KeyEvent::ctrl('R'),
+            EventHandler::Conditional(Box::new(HistoryPickerHandler {
+                history: Arc::clone(&self.history),
+            })),
+        );
+        rl.bind_sequence(
+            KeyEvent::from(' '),
+            EventHandler::Conditional(Box::new(AbbreviationHandler {
+                abbreviations: Arc::clone(&self.abbreviations),
+            })),
+        );
+        if self.config.ui.edit_mode == config::EditMode::Vi {
+            rl.bind_sequence(
+                Event::Any,
+                EventHandler::Conditional(Box::new(ViModeTracker { mode: vi_mode })),
+            );
+        }
+        rl.bind_sequence(
+            Event::KeySeq(vec![KeyEvent::ctrl('X'), KeyEvent::ctrl('E')]),
+            EventHandler::Conditional(Box::new(DeferredHandler {
+                action: Action::EditCommand,
+                slot: Arc::clone(&deferred),
+            })),
+        );
+        self.bind_keys(&mut rl, &deferred);
 
         // Load history into rustyline
-        for cmd in self.history.get_all_commands() {
+        for cmd in self.history().get_all_commands() {
             let _ = rl.add_history_entry(cmd);
         }
 
@@ -318,17 +628,84 @@ impl Repl {
         self.print_welcome();
 
         loop {
-            let prompt = Prompt::generate();
+            if self.config.history.share {
+                self.sync_history(&mut rl);
+            }
+
+            let mut prompts = self.generate_prompt();
+            let prompt = prompts.left.clone();
+            let transient = prompts.transient.take();
+
+            if let Some(helper) = rl.helper_mut() {
+                helper.set_prompts(prompts);
+                // Abbreviation names are commands too until they expand
+                let mut names = self.executor.alias_names();
+                names.extend(abbr::lock(&self.abbreviations).entries().keys().cloned());
+                helper.set_aliases(names);
+                if helper.has_suggester() {
+                    let recent = self
+                        .history()
+                        .get_recent(self.config.ai.suggest.history_lines)
+                        .into_iter()
+                        .map(|e| e.command.clone())
+                        .collect();
+                    helper.set_recent_commands(recent);
+                }
+            }
+
+            let queued = self.queued.pop_front();
+            let input = match &queued {
+                Some(command) => {
+                    // Shown as if typed, then run like any other line
+                    if let Some(helper) = rl.helper() {
+                        let shown = transient.as_deref().unwrap_or(&prompt);
+                        println!("{}{}", shown, helper.highlight_line(command));
+                    }
+                    Ok(command.clone())
+                }
+                None => match self.next_line.take() {
+                    Some(initial) => rl.readline_with_initial(&prompt, (&initial, "")),
+                    None => rl.readline(&prompt),
+                },
+            };
+            match input {
+                Ok(raw_line) => {
+                    let pending = lock(&deferred).take();
+                    if let Some(pending) = pending {
+                        self.run_deferred(pending).await;
+                        continue;
+                    }
 
-            match rl.readline(&prompt) {
-                Ok(line) => {
-                    let line = line.trim();
+                    // Enter expands an abbreviation left at the end of the line
+                    let line = raw_line.trim();
+                    let expanded = abbr::lock(&self.abbreviations).expand_line(line);
+                    if queued.is_none() && (transient.is_some() || expanded.is_some()) {
+                        let shown = expanded.as_deref().unwrap_or(line);
+                        Self::rewrite_accepted(
+                            &rl,
+                            &prompt,
+                            &raw_line,
+                            transient.as_deref(),
+                            shown,
+                        );
+                    }
                     if line.is_empty() {
                         continue;
                     }
+                    let line = expanded.as_deref().unwrap_or(line);
+
+                    // `edit` is replaced in history by the command it runs
+                    if let Some(args) = self.edit_arguments(line) {
+                        self.edit_history(&args);
+                        continue;
+                    }
 
-                    // Add to rustyline history
-                    let _ = rl.add_history_entry(line);
+                    // Privacy rules look at the line as typed (leading space included)
+                    let record = self.history().should_record(&raw_line);
+                    let persist = self.history().should_persist(&raw_line);
+                    if record {
+                        let _ = rl.add_history_entry(line);
+                    }
 
                     // Parse and execute
                     let result = self.parser.parse(line);
@@ -336,144 +713,170 @@ impl Repl {
                         ParseResult::Commands(commands) => {
                             // M-02: Track if history add succeeded to avoid corrupting previous entry
                             let entry = HistoryEntry::new(line.to_string());
-                            let history_added = match self.history.add(entry) {
-                                Ok(()) => true,
-                                Err(e) => {
-                                    eprintln!(
-                                        "{}: Failed to write to history: {}",
-                                        "Warning".with(Color::Yellow).bold(),
-                                        e
-                                    );
-                                    false
-                                }
-                            };
-
-                            // Only sync history to executor when history command is being called
-                            // This avoids O(n) copy on every command execution
-                            let needs_history = commands.iter().any(|c| c.program == "history");
-                            if needs_history {
-                                self.executor.set_history(
-                                    self.history
-                                        .get_all_commands()
-                                        .iter()
-                                        .map(|s| s.to_string())
-                                        .collect(),
-                                );
-                            }
+                            let history_added = record
+                                && match self.history().add(entry) {
+                                    Ok(()) => true,
+                                    Err(e) => {
+                                        eprintln!(
+                                            "{}: Failed to write to history: {}",
+                                            paint(Role::Warning, "Warning").bold(),
+                                            e
+                                        );
+                                        false
+                                    }
+                                };
 
+                            // The executor locks history for `history`, so no guard
+                            // may be held while the pipeline runs
                             // Execute commands
-                            match self.executor.execute_pipeline(commands) {
+                            let started = Instant::now();
+                            let result = self.executor.execute_pipeline(commands);
+                            let elapsed = started.elapsed();
+                            let status = match &result {
+                                Ok(exec_result) => exec_result.exit_code,
+                                Err(_) => 1,
+                            };
+                            self.print_separator(status, elapsed);
+                            self.last_duration = Some(elapsed);
+                            self.last_status = Some(status);
+                            match result {
                                 Ok(exec_result) => {
                                     // M-02: Only update history if add succeeded
                                     if history_added {
-                                        if let Some(last_entry) = self.history.last_mut() {
+                                        let mut history = self.history();
+                                        if let Some(last_entry) = history.last_mut() {
                                             if let Some(ref stdout) = exec_result.stdout {
                                                 last_entry.stdout_summary = Some(stdout.clone());
                                             }
                                         }
-                                        if let Err(e) = self
-                                            .history
-                                            .update_last_exit_code(exec_result.exit_code)
+                                        if let Err(e) =
+                                            history.finish_last(exec_result.exit_code, elapsed)
                                         {
                                             eprintln!(
                                                 "{}: Failed to update history exit code: {}",
-                                                "Warning".with(Color::Yellow).bold(),
+                                                paint(Role::Warning, "Warning").bold(),
                                                 e
                                             );
                                         }
                                     }
+                                    let typeahead =
+                                        self.notifier.command_finished(line, &exec_result, elapsed);
+                                    self.replay(typeahead);
                                 }
                                 Err(e) => {
-                                    eprintln!("{}: {}", "Error".with(Color::Red).bold(), e);
+                                    eprintln!("{}: {}", paint(Role::Error, "Error").bold(), e);
                                     // M-02: Only update history if add succeeded
                                     if history_added {
-                                        if let Err(e) = self.history.update_last_exit_code(1) {
+                                        if let Err(e) = self.history().finish_last(1, elapsed) {
                                             eprintln!(
                                                 "{}: Failed to update history exit code: {}",
-                                                "Warning".with(Color::Yellow).bold(),
+                                                paint(Role::Warning, "Warning").bold(),
                                                 e
                                             );
                                         }
                                     }
+                                    let failed = ExecutionResult::with_code(1);
+                                    let typeahead =
+                                        self.notifier.command_finished(line, &failed, elapsed);
+                                    self.replay(typeahead);
                                 }
                             }
                         }
                         ParseResult::AIQuery(query) => {
                             if query.is_empty() {
-                                println!("{}", "Usage: ! <your question>".with(Color::Yellow));
+                                println!("{}", paint(Role::Warning, "Usage: ! <your question>"));
+                                continue;
+                            }
+
+                            if let Some(builtin) = AiBuiltin::parse(&query) {
+                                self.run_ai_builtin(builtin);
                                 continue;
                             }
 
                             // M-02: Track if history add succeeded
                             let entry = HistoryEntry::new(format!("! {}", query));
-                            let history_added = match self.history.add(entry) {
-                                Ok(()) => true,
-                                Err(e) => {
-                                    eprintln!(
-                                        "{}: Failed to write to history: {}",
-                                        "Warning".with(Color::Yellow).bold(),
-                                        e
-                                    );
-                                    false
-                                }
-                            };
+                            let history_added = record
+                                && match self.history().add(entry) {
+                                    Ok(()) => true,
+                                    Err(e) => {
+                                        eprintln!(
+                                            "{}: Failed to write to history: {}",
+                                            paint(Role::Warning, "Warning").bold(),
+                                            e
+                                        );
+                                        false
+                                    }
+                                };
 
                             // Execute AI query
                             if !self.ai_agent.is_configured() {
                                 eprintln!(
-                                    "{}: AI not configured. Set FOOL_AI_KEY or OPENAI_API_KEY environment variable.",
-                                    "Error".with(Color::Red).bold()
+                                    "{}: {}",
+                                    paint(Role::Error, "Error").bold(),
+                                    self.ai_agent.setup_error()
                                 );
                                 continue;
                             }
 
-                            // M-08: Use tokio::select! to allow Ctrl-C interruption during AI streaming
-                            // Select returns an enum to avoid borrowing issues
-                            enum AiOutcome {
-                                Success,
-                                Error(String),
-                                Cancelled,
+                            // Inline @path references; history keeps the short form
+                            let expanded =
+                                attach::expand_query(&query, &self.config.ai.attachments);
+                            for attachment in &expanded.attachments {
+                                let label = if attachment.is_included() {
+                                    paint(Role::Info, "Attached")
+                                } else {
+                                    paint(Role::Warning, "Warning")
+                                };
+                                println!("{}: {}", label.bold(), attachment.summary());
                             }
+                            let prompt = expanded.prompt;
 
-                            let outcome = {
-                                let ai_future = self.ai_agent.query_stream(&query, &self.history);
-                                tokio::pin!(ai_future);
+                            let started = Instant::now();
+                            // Build the request up front so history isn't locked while streaming
+                            let messages = self.ai_agent.build_messages(&prompt, &self.history());
+                            let outcome = self.stream_ai(messages).await;
 
-                                tokio::select! {
-                                    result = &mut ai_future => {
-                                        match result {
-                                            Ok(_) => AiOutcome::Success,
-                                            Err(e) => AiOutcome::Error(e.to_string()),
-                                        }
-                                    }
-                                    _ = tokio::signal::ctrl_c() => {
-                                        println!("\n{}", "^C (AI streaming cancelled)".with(Color::Yellow));
-                                        AiOutcome::Cancelled
-                                    }
-                                }
-                                // ai_future is dropped here at end of block
-                            };
+                            let elapsed = started.elapsed();
+                            self.last_duration = Some(elapsed);
+                            self.last_status = Some(match outcome {
+                                AiOutcome::Success(_) => 0,
+                                AiOutcome::Error(_) => 1,
+                                AiOutcome::Cancelled => 130,
+                            });
 
-                            // Now ai_future is dropped, we can mutate self.history
+                            // Now ai_future is dropped, we can update history
                             match outcome {
-                                AiOutcome::Success => {
+                                AiOutcome::Success(response) => {
+                                    // Link the answer to the history entry of its question
+                                    let question_timestamp = if history_added {
+                                        self.history().last().map(|e| e.timestamp)
+                                    } else {
+                                        None
+                                    };
+                                    let answer = AnswerEntry::new(
+                                        query.clone(),
+                                        response,
+                                        question_timestamp,
+                                    );
+                                    self.keep_answer(answer, persist);
+
                                     if history_added {
-                                        if let Err(e) = self.history.update_last_exit_code(0) {
+                                        if let Err(e) = self.history().finish_last(0, elapsed) {
                                             eprintln!(
                                                 "{}: Failed to update history exit code: {}",
-                                                "Warning".with(Color::Yellow).bold(),
+                                                paint(Role::Warning, "Warning").bold(),
                                                 e
                                             );
                                         }
                                     }
                                 }
                                 AiOutcome::Error(e) => {
-                                    eprintln!("{}: {}", "AI Error".with(Color::Red).bold(), e);
+                                    eprintln!("{}: {}", paint(Role::Error, "AI Error").bold(), e);
                                     if history_added {
-                                        if let Err(e) = self.history.update_last_exit_code(1) {
+                                        if let Err(e) = self.history().finish_last(1, elapsed) {
                                             eprintln!(
                                                 "{}: Failed to update history exit code: {}",
-                                                "Warning".with(Color::Yellow).bold(),
+                                                paint(Role::Warning, "Warning").bold(),
                                                 e
                                             );
                                         }
@@ -481,10 +884,10 @@ impl Repl {
                                 }
                                 AiOutcome::Cancelled => {
                                     if history_added {
-                                        if let Err(e) = self.history.update_last_exit_code(130) {
+                                        if let Err(e) = self.history().finish_last(130, elapsed) {
                                             eprintln!(
                                                 "{}: Failed to update history exit code: {}",
-                                                "Warning".with(Color::Yellow).bold(),
+                                                paint(Role::Warning, "Warning").bold(),
                                                 e
                                             );
                                         }
@@ -494,7 +897,7 @@ impl Repl {
                         }
                         ParseResult::Empty => {}
                         ParseResult::Error(e) => {
-                            eprintln!("{}: {}", "Parse Error".with(Color::Red).bold(), e);
+                            eprintln!("{}: {}", paint(Role::Error, "Parse Error").bold(), e);
                         }
                     }
                 }
@@ -518,9 +921,347 @@ impl Repl {
         Ok(())
     }
 
+    /// Stream an answer to `messages`
+    async fn stream_ai(&self, messages: Vec<ChatMessage>) -> AiOutcome {
+        // M-08: Use tokio::select! to allow Ctrl-C interruption during AI streaming
+        let ai_future = self.ai_agent.stream_messages(messages);
+        tokio::pin!(ai_future);
+
+        tokio::select! {
+            result = &mut ai_future => {
+                match result {
+                    Ok(response) => AiOutcome::Success(response),
+                    Err(e) => AiOutcome::Error(e.to_string()),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("\n{}", paint(Role::Warning, "^C (AI streaming cancelled)"));
+                AiOutcome::Cancelled
+            }
+        }
+    }
+
+    /// Install the `[keybindings]` table; entries override the defaults
+    fn bind_keys(&self, rl: &mut Editor<FoolHelper, DefaultHistory>, deferred: &DeferredSlot) {
+        // Config::load already warned about and dropped invalid entries
+        let entries = self
+            .config
+            .keybindings
+            .iter()
+            .filter_map(|(keys, action)| bindings::parse_binding(keys, action).ok());
+        for binding in entries {
+            let handler = match binding.action {
+                Action::Editor(cmd) => EventHandler::Simple(cmd),
+                Action::Insert(text) => EventHandler::Simple(Cmd::Insert(1, text)),
+                Action::HistoryPicker => {
+                    EventHandler::Conditional(Box::new(HistoryPickerHandler {
+                        history: Arc::clone(&self.history),
+                    }))
+                }
+                action => {
+                    debug_assert!(action.is_deferred());
+                    EventHandler::Conditional(Box::new(DeferredHandler {
+                        action,
+                        slot: Arc::clone(deferred),
+                    }))
+                }
+            };
+            rl.bind_sequence(Event::KeySeq(binding.keys), handler);
+        }
+    }
+
+    /// Run a binding that needs the terminal, then go back to the line it
+    /// was pressed on (or, for `ai-fix`, to the corrected line)
+    async fn run_deferred(&mut self, deferred: Deferred) {
+        let Deferred { action, line } = deferred;
+        match action {
+            Action::Run(command) => self.run_bound_command(&command),
+            Action::AiExplain | Action::AiFix if line.trim().is_empty() => {}
+            Action::AiExplain | Action::AiFix if !self.ai_agent.is_configured() => {
+                eprintln!(
+                    "{}: {}",
+                    paint(Role::Error, "Error").bold(),
+                    self.ai_agent.setup_error()
+                );
+            }
+            Action::AiExplain => {
+                let query = bindings::explain_query(&line);
+                let persist = self.history().should_persist(&line);
+                let messages = self.ai_agent.build_messages(&query, &self.history());
+                match self.stream_ai(messages).await {
+                    AiOutcome::Success(response) => {
+                        self.keep_answer(AnswerEntry::new(query, response, None), persist);
+                    }
+                    AiOutcome::Error(e) => {
+                        eprintln!("{}: {}", paint(Role::Error, "AI Error").bold(), e)
+                    }
+                    AiOutcome::Cancelled => {}
+                }
+            }
+            Action::AiFix => {
+                println!("{}", paint(Role::Muted, "Asking AI for a fix..."));
+                let messages = self
+                    .ai_agent
+                    .build_messages(&bindings::fix_query(&line), &self.history());
+                let outcome = tokio::select! {
+                    reply = self.ai_agent.complete(messages) => match reply {
+                        Ok(reply) => AiOutcome::Success(reply),
+                        Err(e) => AiOutcome::Error(e.to_string()),
+                    },
+                    _ = tokio::signal::ctrl_c() => {
+                        println!("{}", paint(Role::Warning, "^C (AI request cancelled)"));
+                        AiOutcome::Cancelled
+                    }
+                };
+                match outcome {
+                    AiOutcome::Success(reply) => match bindings::extract_fixed_command(&reply) {
+                        Some(fixed) => {
+                            self.next_line = Some(fixed);
+                            return;
+                        }
+                        None => eprintln!(
+                            "{}: The reply contained no command",
+                            paint(Role::Error, "AI Error").bold()
+                        ),
+                    },
+                    AiOutcome::Error(e) => {
+                        eprintln!("{}: {}", paint(Role::Error, "AI Error").bold(), e)
+                    }
+                    AiOutcome::Cancelled => {}
+                }
+            }
+            Action::EditCommand => {
+                if self.edit_and_queue(&line) {
+                    return;
+                }
+            }
+            Action::Editor(_) | Action::HistoryPicker | Action::Insert(_) => {}
+        }
+        if !line.is_empty() {
+            self.next_line = Some(line);
+        }
+    }
+
+    /// Open `text` in the editor and queue the commands it was saved with;
+    /// false if the editor couldn't be run
+    fn edit_and_queue(&mut self, text: &str) -> bool {
+        match editor::edit(&self.config.ui.editor, text) {
+            Ok(saved) => {
+                self.queued
+                    .extend(editor::split_commands(&self.parser, &saved));
+                true
+            }
+            Err(e) => {
+                eprintln!("{}: {:#}", paint(Role::Error, "Error").bold(), e);
+                false
+            }
+        }
+    }
+
+    /// The arguments of `line` if it is a lone `edit` (or `fc`) command
+    fn edit_arguments(&self, line: &str) -> Option<Vec<String>> {
+        let ParseResult::Commands(commands) = self.parser.parse(line) else {
+            return None;
+        };
+        match &commands[..] {
+            [command]
+                if matches!(
+                    BuiltinCommand::from_str(&command.program),
+                    Some(BuiltinCommand::Edit)
+                ) =>
+            {
+                Some(command.args.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// `edit [N | TEXT]`: open the previous command, history entry N or the
+    /// latest command starting with TEXT in the editor, then run it
+    fn edit_history(&mut self, args: &[String]) {
+        // No options are supported; `fc -l` must not become a prefix search
+        if args.first().is_some_and(|arg| arg.starts_with('-')) {
+            eprintln!("Usage: edit [N | TEXT] (options such as fc -l are not supported)");
+            return;
+        }
+        let command = {
+            let history = self.history();
+            let entry = match args {
+                [] => history.last(),
+                [number] if number.parse::<usize>().is_ok() => {
+                    history.get(number.parse().unwrap_or_default())
+                }
+                _ => history.search_prefix(&args.join(" ")).last().copied(),
+            };
+            entry.map(|entry| entry.command.clone())
+        };
+        match command {
+            Some(command) => {
+                self.edit_and_queue(&command);
+            }
+            None => eprintln!("edit: no such command in history"),
+        }
+    }
+
+    /// Hand keys typed during a command back to the editor: finished lines
+    /// run next, the rest starts the following line
+    fn replay(&mut self, typeahead: Typeahead) {
+        self.queued.extend(typeahead.lines);
+        if !typeahead.partial.is_empty() {
+            self.next_line = Some(typeahead.partial);
+        }
+    }
+
+    /// Store an AI answer, on disk only if its question may be persisted
+    fn keep_answer(&mut self, answer: AnswerEntry, persist: bool) {
+        if !persist {
+            self.answers.add_in_memory(answer);
+        } else if let Err(e) = self.answers.add(answer) {
+            eprintln!(
+                "{}: Failed to save AI answer: {}",
+                paint(Role::Warning, "Warning").bold(),
+                e
+            );
+        }
+    }
+
+    /// Run the command of a `run:` binding; it isn't added to history
+    fn run_bound_command(&mut self, command: &str) {
+        match self.parser.parse(command) {
+            ParseResult::Commands(commands) => {
+                let started = Instant::now();
+                let result = self.executor.execute_pipeline(commands);
+                self.last_duration = Some(started.elapsed());
+                self.last_status = Some(match result {
+                    Ok(exec_result) => exec_result.exit_code,
+                    Err(e) => {
+                        eprintln!("{}: {}", paint(Role::Error, "Error").bold(), e);
+                        1
+                    }
+                });
+            }
+            ParseResult::AIQuery(_) => eprintln!(
+                "{}: run: bindings take shell commands, not AI queries",
+                paint(Role::Error, "Error").bold()
+            ),
+            ParseResult::Empty => {}
+            ParseResult::Error(e) => {
+                eprintln!("{}: {}", paint(Role::Error, "Parse Error").bold(), e)
+            }
+        }
+    }
+
+    /// Handle a built-in AI command locally
+    fn run_ai_builtin(&self, builtin: AiBuiltin) {
+        match builtin {
+            AiBuiltin::Context => self.print_context(),
+            AiBuiltin::Last(n) => self.print_answer(n),
+            AiBuiltin::Save { block, path, force } => {
+                if let Err(e) = self.save_answer(block, &path, force) {
+                    eprintln!("{}: {}", paint(Role::Error, "Error").bold(), e);
+                }
+            }
+            AiBuiltin::Answers(query) => self.list_answers(query.as_deref()),
+        }
+    }
+
+    /// Re-display the `n`th most recent answer
+    fn print_answer(&self, n: usize) {
+        let Some(entry) = self.answers.get(n) else {
+            println!("No saved answer #{}", n);
+            return;
+        };
+        println!(
+            "{} {}",
+            paint(Role::Muted, format_local_time(&entry.timestamp)),
+            paint(Role::Info, &entry.question).bold()
+        );
+        render_markdown(&entry.answer);
+    }
+
+    /// Write the last answer, or its `block`th code block, to `path`
+    fn save_answer(&self, block: Option<usize>, path: &str, force: bool) -> Result<()> {
+        let entry = self
+            .answers
+            .get(1)
+            .ok_or_else(|| anyhow::anyhow!("No AI answer to save yet"))?;
+
+        let content = match block {
+            Some(n) => {
+                let blocks = entry.code_blocks();
+                let code = blocks.get(n - 1).ok_or_else(|| {
+                    anyhow::anyhow!("Last answer has {} code block(s), no #{}", blocks.len(), n)
+                })?;
+                format!("{}\n", code)
+            }
+            None => entry.answer.clone(),
+        };
+
+        let target = crate::config::expand_path(path);
+        if target.exists() && !force {
+            return Err(anyhow::anyhow!(
+                "{} already exists (use !:save -f to overwrite)",
+                target.display()
+            ));
+        }
+        std::fs::write(&target, &content)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", target.display(), e))?;
+
+        println!("Saved {} bytes to {}", content.len(), target.display());
+        Ok(())
+    }
+
+    /// List past questions, newest first, optionally filtered by `query`
+    fn list_answers(&self, query: Option<&str>) {
+        let entries = match query {
+            Some(q) => self.answers.search(q),
+            None => self.answers.list(),
+        };
+        if entries.is_empty() {
+            println!("No saved answers");
+            return;
+        }
+
+        for (n, entry) in entries {
+            let question = entry.question.lines().next().unwrap_or("");
+            let link = entry
+                .history_timestamp
+                .map(|ts| format!("  (history: {})", format_local_time(&ts)))
+                .unwrap_or_default();
+            println!(
+                "{:4}  {}  {}{}",
+                n,
+                paint(Role::Muted, format_local_time(&entry.timestamp)),
+                question,
+                paint(Role::Muted, link)
+            );
+        }
+    }
+
+    /// Show the environment snapshot that accompanies AI queries
+    fn print_context(&self) {
+        let sections = ContextBuilder::new(self.config.ai.context.clone()).collect();
+        if sections.is_empty() {
+            println!("No context providers enabled (see [ai.context] in config.toml)");
+        }
+        for section in sections {
+            println!(
+                "{} {}",
+                paint(Role::Info, format!("{}:", section.title)).bold(),
+                section.content
+            );
+        }
+        println!(
+            "{} last {} entries",
+            paint(Role::Info, "History:").bold(),
+            self.config.ai.context_lines
+        );
+    }
+
     #[allow(dead_code)] // Alternative history display method
     fn print_history(&self) {
-        let entries = self.history.get_all_commands();
+        let history = self.history();
+        let entries = history.get_all_commands();
         if entries.is_empty() {
             println!("No history available");
             return;
@@ -534,7 +1275,9 @@ impl Repl {
     fn print_welcome(&self) {
         println!(
             "{}",
-            r#"
+            paint(
+                Role::Info,
+                r#"
   ███████╗ ██████╗  ██████╗ ██╗
   ██╔════╝██╔═══██╗██╔═══██╗██║
   █████╗  ██║   ██║██║   ██║██║
@@ -542,21 +1285,21 @@ impl Repl {
   ██║     ╚██████╔╝╚██████╔╝███████╗
   ╚═╝      ╚═════╝  ╚═════╝ ╚══════╝
 "#
-            .with(Color::Cyan)
+            )
         );
         println!(
             "  {} v{}\n",
-            "Fool Shell".with(Color::Green).bold(),
+            paint(Role::Success, "Fool Shell").bold(),
             env!("CARGO_PKG_VERSION")
         );
         println!(
             "  Type {} for help, {} to exit",
-            "help".with(Color::Yellow),
-            "exit".with(Color::Yellow)
+            paint(Role::Command, "help"),
+            paint(Role::Command, "exit")
         );
         println!(
             "  Use {} to ask AI for help\n",
-            "! <question>".with(Color::Magenta)
+            paint(Role::AiTrigger, "! <question>")
         );
     }
 }
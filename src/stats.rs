@@ -0,0 +1,241 @@
+//! Stats module for Fool Shell
+//! Usage, timing and failure statistics over command history
+
+use crate::history::{self, HistoryEntry};
+use crate::picker::shorten_cwd;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Commands or directories with fewer finished runs get no failure rate
+const MIN_RUNS: usize = 3;
+
+/// Run counts for one command or directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub runs: usize,
+    pub finished: usize, // Runs with a known exit code
+    pub failures: usize,
+}
+
+impl Tally {
+    fn add(&mut self, entry: &HistoryEntry) {
+        self.runs += 1;
+        if let Some(code) = entry.exit_code {
+            self.finished += 1;
+            if code != 0 {
+                self.failures += 1;
+            }
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.finished == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.finished as f64
+        }
+    }
+}
+
+/// Statistics over a set of history entries
+pub struct HistoryStats<'a> {
+    pub total: usize,
+    pub by_program: Vec<(String, Tally)>, // Most used first
+    pub by_dir: Vec<(String, Tally)>,     // Most used first
+    pub slowest: Vec<&'a HistoryEntry>,   // Slowest run of each command, slowest first
+}
+
+impl<'a> HistoryStats<'a> {
+    pub fn compute(entries: &[&'a HistoryEntry]) -> Self {
+        let mut programs: HashMap<&str, Tally> = HashMap::new();
+        let mut dirs: HashMap<&str, Tally> = HashMap::new();
+        let mut slowest: HashMap<&str, &HistoryEntry> = HashMap::new();
+
+        for entry in entries {
+            let name = program(&entry.command);
+            if !name.is_empty() {
+                programs.entry(name).or_default().add(entry);
+            }
+            if let Some(cwd) = entry.cwd.as_deref() {
+                dirs.entry(cwd).or_default().add(entry);
+            }
+            if let Some(ms) = entry.duration_ms {
+                let slot = slowest.entry(&entry.command).or_insert(entry);
+                if slot.duration_ms < Some(ms) {
+                    *slot = entry;
+                }
+            }
+        }
+
+        let mut slowest: Vec<&HistoryEntry> = slowest.into_values().collect();
+        slowest.sort_by_key(|e| Reverse(e.duration_ms));
+
+        Self {
+            total: entries.len(),
+            by_program: by_runs(programs),
+            by_dir: by_runs(dirs),
+            slowest,
+        }
+    }
+
+    /// Groups that failed, worst rate first
+    fn failing(groups: &[(String, Tally)]) -> Vec<&(String, Tally)> {
+        let mut failing: Vec<&(String, Tally)> = groups
+            .iter()
+            .filter(|(_, t)| t.finished >= MIN_RUNS && t.failures > 0)
+            .collect();
+        failing.sort_by(|(_, a), (_, b)| {
+            b.failure_rate()
+                .total_cmp(&a.failure_rate())
+                .then(b.failures.cmp(&a.failures))
+        });
+        failing
+    }
+
+    /// Report with at most `top` rows per section
+    pub fn render(&self, top: usize) -> String {
+        if self.total == 0 {
+            return "No history to analyze\n".to_string();
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(out, "Most used ({} commands)", self.total);
+        for (name, tally) in self.by_program.iter().take(top) {
+            let _ = writeln!(out, "  {:6}  {}", tally.runs, name);
+        }
+
+        if !self.slowest.is_empty() {
+            let _ = writeln!(out, "\nSlowest");
+            for entry in self.slowest.iter().take(top) {
+                let took = Duration::from_millis(entry.duration_ms.unwrap_or(0));
+                let _ = writeln!(
+                    out,
+                    "  {:>8}  {}",
+                    history::format_duration(took),
+                    entry.command
+                );
+            }
+        }
+
+        for (title, groups, shorten) in [
+            ("Failure rate by command", &self.by_program, false),
+            ("Failure rate by directory", &self.by_dir, true),
+        ] {
+            let failing = Self::failing(groups);
+            if failing.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "\n{}", title);
+            for (name, tally) in failing.into_iter().take(top) {
+                let name = if shorten {
+                    shorten_cwd(name)
+                } else {
+                    name.clone()
+                };
+                let _ = writeln!(
+                    out,
+                    "  {:>4.0}%  {:>9}  {}",
+                    tally.failure_rate() * 100.0,
+                    format!("{}/{}", tally.failures, tally.finished),
+                    name
+                );
+            }
+        }
+        out
+    }
+}
+
+fn by_runs(groups: HashMap<&str, Tally>) -> Vec<(String, Tally)> {
+    let mut groups: Vec<(String, Tally)> = groups
+        .into_iter()
+        .map(|(name, tally)| (name.to_string(), tally))
+        .collect();
+    groups.sort_by(|(a_name, a), (b_name, b)| b.runs.cmp(&a.runs).then(a_name.cmp(b_name)));
+    groups
+}
+
+/// The program a command line runs: its first word after any `VAR=value`
+pub fn program(command: &str) -> &str {
+    command
+        .split_whitespace()
+        .find(|word| !word.contains('='))
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, exit_code: Option<i32>, cwd: &str, ms: Option<u64>) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(command.to_string());
+        entry.exit_code = exit_code;
+        entry.cwd = Some(cwd.to_string());
+        entry.duration_ms = ms;
+        entry
+    }
+
+    #[test]
+    fn test_program() {
+        assert_eq!(program("git status"), "git");
+        assert_eq!(program("RUST_LOG=debug cargo run"), "cargo");
+        assert_eq!(program("   "), "");
+    }
+
+    #[test]
+    fn test_compute() {
+        let entries = [
+            entry("cargo build", Some(0), "/a", Some(9000)),
+            entry("cargo build", Some(0), "/a", Some(12000)),
+            entry("cargo test", Some(101), "/a", Some(4000)),
+            entry("git push", Some(1), "/b", Some(800)),
+            entry("git status", Some(0), "/b", None),
+            // Imported entries have no exit code and don't count as failures
+            entry("git log", None, "/b", None),
+        ];
+        let refs: Vec<&HistoryEntry> = entries.iter().collect();
+        let stats = HistoryStats::compute(&refs);
+
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.by_program[0].0, "cargo");
+        assert_eq!(
+            stats.by_program[1].1,
+            Tally {
+                runs: 3,
+                finished: 2,
+                failures: 1
+            }
+        );
+
+        // One row per command, keeping its slowest run
+        let slowest: Vec<Option<u64>> = stats.slowest.iter().map(|e| e.duration_ms).collect();
+        assert_eq!(slowest, vec![Some(12000), Some(4000), Some(800)]);
+
+        // git has only two finished runs, so only cargo gets a failure rate
+        let failing = HistoryStats::failing(&stats.by_program);
+        assert_eq!(failing.len(), 1);
+        assert_eq!(failing[0].0, "cargo");
+    }
+
+    #[test]
+    fn test_render() {
+        let entries = [
+            entry("make", Some(2), "/src", Some(65_000)),
+            entry("make", Some(0), "/src", Some(1_000)),
+            entry("make", Some(2), "/src", None),
+        ];
+        let refs: Vec<&HistoryEntry> = entries.iter().collect();
+        let report = HistoryStats::compute(&refs).render(10);
+
+        assert!(report.contains("Most used (3 commands)"));
+        assert!(report.contains("1m 05s  make"));
+        assert!(report.contains("67%        2/3  make"));
+        assert!(report.contains("Failure rate by directory"));
+
+        assert_eq!(
+            HistoryStats::compute(&[]).render(10),
+            "No history to analyze\n"
+        );
+    }
+}
@@ -0,0 +1,862 @@
+//! Store module for Fool Shell
+//! Persistent history backends: the original JSONL file and an indexed SQLite database
+
+use crate::crypt::{Header, RecordCipher};
+use crate::history::HistoryEntry;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use fs2::FileExt;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// How long a writer waits for another terminal holding the database
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Persistent storage behind `History`
+///
+/// `History` keeps the newest entries in memory and only hands finished
+/// entries (with exit codes) to the store.
+pub trait HistoryStore: Send {
+    /// The newest `limit` entries, oldest first
+    fn load_recent(&mut self, limit: usize) -> Result<Vec<HistoryEntry>>;
+
+    /// Entries saved since the last load by any session, oldest first
+    fn load_new(&mut self) -> Result<Vec<HistoryEntry>>;
+
+    /// Persist one finished entry
+    fn append(&mut self, entry: &HistoryEntry) -> Result<()>;
+
+    /// Drop old entries; `window` is what `History` currently keeps in memory
+    fn compact(&mut self, window: &[&HistoryEntry]) -> Result<()>;
+
+    /// Delete every entry for which `keep` returns false, returning how many
+    fn retain(&mut self, keep: &dyn Fn(&HistoryEntry) -> bool) -> Result<usize>;
+
+    /// Delete stored copies of `entry`, matched by timestamp and command
+    fn remove(&mut self, entry: &HistoryEntry) -> Result<usize> {
+        self.retain(&|e| e.timestamp != entry.timestamp || e.command != entry.command)
+    }
+
+    /// Delete every entry that ran exactly `command`
+    fn remove_command(&mut self, command: &str) -> Result<usize> {
+        self.retain(&|e| e.command != command)
+    }
+
+    /// Delete every entry whose command matches `pattern`
+    fn remove_matching(&mut self, pattern: &Regex) -> Result<usize> {
+        self.retain(&|e| !pattern.is_match(&e.command))
+    }
+
+    /// Remove every stored entry
+    fn clear(&mut self) -> Result<()>;
+
+    /// Add entries from elsewhere in timestamp order, skipping ones already
+    /// stored, and return how many were added
+    fn merge(&mut self, entries: &[HistoryEntry]) -> Result<usize>;
+}
+
+fn ensure_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create history directory: {:?}", parent))?;
+    }
+    Ok(())
+}
+
+/// Append-only JSON Lines file, rewritten on compaction
+///
+/// When encrypted, the file starts with a `crypt::Header` line and every
+/// following line is one encrypted entry.
+pub struct JsonlStore {
+    file_path: PathBuf,
+    offset: u64,                         // Bytes already read
+    file_id: Option<u64>,                // Inode `offset` refers to; compaction replaces the file
+    synced_until: Option<DateTime<Utc>>, // Newest timestamp read so far
+    cipher: Option<RecordCipher>,
+}
+
+impl JsonlStore {
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        ensure_parent_dir(&file_path)?;
+        if Header::read(&file_path)?.is_some() {
+            bail!(
+                "history file {:?} is encrypted; set history.encryption.enabled = true to use it",
+                file_path
+            );
+        }
+        Ok(Self {
+            file_path,
+            offset: 0,
+            file_id: None,
+            synced_until: None,
+            cipher: None,
+        })
+    }
+
+    /// Open a file whose entries are encrypted with `cipher`
+    ///
+    /// A plain file is converted first; returns how many entries that took.
+    pub fn open_encrypted(file_path: PathBuf, cipher: RecordCipher) -> Result<(Self, usize)> {
+        ensure_parent_dir(&file_path)?;
+        let mut store = Self {
+            file_path,
+            offset: 0,
+            file_id: None,
+            synced_until: None,
+            cipher: Some(cipher),
+        };
+
+        let _lock_file = Self::acquire_lock(&store.file_path)?;
+        if !store.file_path.exists() || Header::read(&store.file_path)?.is_some() {
+            return Ok((store, 0));
+        }
+        let plain = read_jsonl(&store.file_path)?;
+        store.rewrite(&plain.iter().collect::<Vec<_>>())?;
+        Ok((store, plain.len()))
+    }
+
+    /// One line of the file for `entry`
+    fn encode(&self, entry: &HistoryEntry) -> Result<String> {
+        let json =
+            serde_json::to_string(entry).with_context(|| "Failed to serialize history entry")?;
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&json),
+            None => Ok(json),
+        }
+    }
+
+    /// The entry on one line of the file; `None` for headers and bad lines
+    fn decode(&self, line: &str) -> Option<HistoryEntry> {
+        match &self.cipher {
+            Some(cipher) => serde_json::from_str(&cipher.decrypt(line)?).ok(),
+            None => serde_json::from_str(line).ok(),
+        }
+    }
+
+    /// Every readable entry in the file
+    fn read_all(&self) -> Result<Vec<HistoryEntry>> {
+        if !self.file_path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = fs::read_to_string(&self.file_path)
+            .with_context(|| format!("Failed to read history file: {:?}", self.file_path))?;
+        Ok(text.lines().filter_map(|line| self.decode(line)).collect())
+    }
+
+    /// Read whole lines from `start` and move the offset past them
+    fn read_from(&mut self, start: u64) -> Result<Vec<HistoryEntry>> {
+        let mut file = File::open(&self.file_path)
+            .with_context(|| format!("Failed to open history file: {:?}", self.file_path))?;
+        let file_id = file_id(&file.metadata()?);
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        // Another session may be in the middle of writing its last line
+        let complete = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let entries: Vec<HistoryEntry> = String::from_utf8_lossy(&bytes[..complete])
+            .lines()
+            .filter_map(|line| self.decode(line))
+            .collect();
+
+        self.offset = start + complete as u64;
+        self.file_id = file_id;
+        if let Some(newest) = entries.iter().map(|e| e.timestamp).max() {
+            self.synced_until = self.synced_until.max(Some(newest));
+        }
+        Ok(entries)
+    }
+
+    /// Replace the file with `entries`; the caller must hold the lock
+    fn rewrite(&mut self, entries: &[&HistoryEntry]) -> Result<()> {
+        let temp_path = self.file_path.with_extension("tmp");
+
+        {
+            // Create temp file with secure permissions (0o600)
+            let mut options = OpenOptions::new();
+            options.create(true).write(true).truncate(true);
+
+            #[cfg(unix)]
+            options.mode(0o600);
+
+            let mut file = options
+                .open(&temp_path)
+                .with_context(|| format!("Failed to create temp history file: {:?}", temp_path))?;
+
+            if let Some(cipher) = &self.cipher {
+                writeln!(file, "{}", cipher.header_line())?;
+            }
+            for entry in entries {
+                writeln!(file, "{}", self.encode(entry)?)?;
+            }
+
+            file.flush()?;
+        }
+
+        // The new file has a new inode, so the next `load_new` re-reads it and
+        // still picks up entries other sessions appended before the rewrite
+        fs::rename(&temp_path, &self.file_path)
+            .with_context(|| "Failed to rename temp history file")
+    }
+
+    /// Get the path to the lock file (sidecar file for coordinating access)
+    fn get_lock_path(file_path: &Path) -> PathBuf {
+        file_path.with_extension("lock")
+    }
+
+    /// Acquire exclusive lock on the sidecar lock file
+    /// M-07: Using a sidecar lock file ensures that after rename operations,
+    /// all processes still coordinate through the same persistent lock file
+    fn acquire_lock(file_path: &Path) -> Result<File> {
+        let lock_path = Self::get_lock_path(file_path);
+
+        let mut lock_options = OpenOptions::new();
+        lock_options.read(true).write(true).create(true);
+
+        #[cfg(unix)]
+        lock_options.mode(0o600);
+
+        let lock_file = lock_options
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file: {:?}", lock_path))?;
+
+        lock_file
+            .lock_exclusive()
+            .with_context(|| format!("Failed to acquire lock: {:?}", lock_path))?;
+
+        Ok(lock_file)
+    }
+}
+
+/// Read every parseable entry of a JSONL history file, skipping bad lines
+pub fn read_jsonl(file_path: &Path) -> Result<Vec<HistoryEntry>> {
+    let file = File::open(file_path)
+        .with_context(|| format!("Failed to open history file: {:?}", file_path))?;
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(&line).ok())
+        .collect())
+}
+
+/// The entries of `incoming` not already in `existing` (same command run in
+/// the same second), without repeats
+fn new_entries<'a>(
+    existing: &[HistoryEntry],
+    incoming: &'a [HistoryEntry],
+) -> Vec<&'a HistoryEntry> {
+    let key = |e: &HistoryEntry| (e.timestamp.timestamp(), e.command.clone());
+    let mut seen: HashSet<(i64, String)> = existing.iter().map(key).collect();
+    incoming.iter().filter(|e| seen.insert(key(e))).collect()
+}
+
+impl HistoryStore for JsonlStore {
+    fn load_recent(&mut self, limit: usize) -> Result<Vec<HistoryEntry>> {
+        if !self.file_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        // The whole file has to be parsed to find its end
+        let mut entries = VecDeque::with_capacity(limit);
+        for entry in self.read_from(0)? {
+            entries.push_back(entry);
+            if entries.len() > limit {
+                entries.pop_front();
+            }
+        }
+        Ok(entries.into())
+    }
+
+    fn load_new(&mut self) -> Result<Vec<HistoryEntry>> {
+        let metadata = match fs::metadata(&self.file_path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        // After another session compacts, the file is new: re-read it and keep
+        // only what is newer than anything seen before
+        let replaced = file_id(&metadata) != self.file_id || metadata.len() < self.offset;
+        if !replaced {
+            return self.read_from(self.offset);
+        }
+        let synced_until = self.synced_until;
+        let mut entries = self.read_from(0)?;
+        entries.retain(|e| synced_until.is_none_or(|t| e.timestamp > t));
+        Ok(entries)
+    }
+
+    fn append(&mut self, entry: &HistoryEntry) -> Result<()> {
+        // M-07: Acquire lock via sidecar lock file
+        let _lock_file = Self::acquire_lock(&self.file_path)?;
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+
+        // Set secure permissions (0o600) on Unix to protect command history
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&self.file_path).with_context(|| {
+            format!(
+                "Failed to open history file for writing: {:?}",
+                self.file_path
+            )
+        })?;
+
+        // A new (or cleared) encrypted file needs its header first
+        if let Some(cipher) = &self.cipher {
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "{}", cipher.header_line())
+                    .with_context(|| "Failed to write history header")?;
+            }
+        }
+        let line = self.encode(entry)?;
+        writeln!(file, "{}", line).with_context(|| "Failed to write history entry")?;
+
+        // Ensure data is flushed before releasing lock
+        file.flush()
+            .with_context(|| "Failed to flush history entry")?;
+
+        // Lock released when _lock_file is dropped
+        Ok(())
+    }
+
+    /// Rewrite the file with only the in-memory window
+    /// M-07 FIX: Use sidecar lock file to prevent concurrent compaction conflicts
+    fn compact(&mut self, window: &[&HistoryEntry]) -> Result<()> {
+        // M-07: Acquire exclusive lock via sidecar lock file
+        // Using a sidecar file ensures all processes coordinate through the same
+        // persistent lock file even after rename operations
+        let _lock_file = Self::acquire_lock(&self.file_path)?;
+        self.rewrite(window)
+        // Lock released when _lock_file is dropped
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&HistoryEntry) -> bool) -> Result<usize> {
+        let _lock_file = Self::acquire_lock(&self.file_path)?;
+        if !self.file_path.exists() {
+            return Ok(0);
+        }
+
+        let entries = self.read_all()?;
+        let kept: Vec<&HistoryEntry> = entries.iter().filter(|e| keep(e)).collect();
+        let removed = entries.len() - kept.len();
+        if removed > 0 {
+            self.rewrite(&kept)?;
+        }
+        Ok(removed)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        if self.file_path.exists() {
+            fs::remove_file(&self.file_path)
+                .with_context(|| format!("Failed to remove history file: {:?}", self.file_path))?;
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, entries: &[HistoryEntry]) -> Result<usize> {
+        let _lock_file = Self::acquire_lock(&self.file_path)?;
+        let existing = self.read_all()?;
+
+        let added = new_entries(&existing, entries);
+        if added.is_empty() {
+            return Ok(0);
+        }
+        let mut merged: Vec<&HistoryEntry> = existing.iter().chain(added.iter().copied()).collect();
+        merged.sort_by_key(|e| e.timestamp);
+        self.rewrite(&merged)?;
+        Ok(added.len())
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// SQLite database in WAL mode, safe for several terminals writing at once
+///
+/// Commands, directories and timestamps are indexed columns; the full entry
+/// is kept as JSON so new `HistoryEntry` fields need no schema change.
+pub struct SqliteStore {
+    conn: Connection,
+    max_entries: usize,
+    last_id: i64, // Newest row already loaded
+}
+
+impl SqliteStore {
+    pub fn open(db_path: PathBuf, max_entries: usize) -> Result<Self> {
+        ensure_parent_dir(&db_path)?;
+
+        // Create the file ourselves so it (and its -wal/-shm files) stay private
+        #[cfg(unix)]
+        if !db_path.exists() {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o600)
+                .open(&db_path)
+                .with_context(|| format!("Failed to create history database: {:?}", db_path))?;
+        }
+
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open history database: {:?}", db_path))?;
+        conn.busy_timeout(SQLITE_BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history (
+                 id        INTEGER PRIMARY KEY AUTOINCREMENT,
+                 command   TEXT NOT NULL,
+                 cwd       TEXT,
+                 exit_code INTEGER,
+                 timestamp INTEGER NOT NULL,
+                 entry     TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS history_command ON history(command);
+             CREATE INDEX IF NOT EXISTS history_cwd ON history(cwd);
+             CREATE INDEX IF NOT EXISTS history_timestamp ON history(timestamp);
+             CREATE TABLE IF NOT EXISTS meta (
+                 key   TEXT PRIMARY KEY,
+                 value TEXT NOT NULL
+             );",
+        )
+        .with_context(|| "Failed to create history tables")?;
+
+        Ok(Self {
+            conn,
+            max_entries,
+            last_id: 0,
+        })
+    }
+
+    /// Import a JSONL history file once, into an empty database
+    ///
+    /// Returns the number of imported entries; the JSONL file is left untouched.
+    pub fn migrate_jsonl(&mut self, jsonl_path: &Path) -> Result<usize> {
+        let migrated: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'migrated_from'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?;
+        if migrated.is_some() || count > 0 || !jsonl_path.exists() {
+            return Ok(0);
+        }
+
+        let entries = read_jsonl(jsonl_path)?;
+        let tx = self.conn.transaction()?;
+        for entry in &entries {
+            insert_entry(&tx, entry)?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('migrated_from', ?1)",
+            params![jsonl_path.to_string_lossy()],
+        )?;
+        tx.commit()
+            .with_context(|| "Failed to import JSONL history")?;
+
+        Ok(entries.len())
+    }
+}
+
+fn insert_entry(conn: &Connection, entry: &HistoryEntry) -> Result<()> {
+    let json = serde_json::to_string(entry).with_context(|| "Failed to serialize history entry")?;
+    conn.execute(
+        "INSERT INTO history (command, cwd, exit_code, timestamp, entry)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            entry.command,
+            entry.cwd,
+            entry.exit_code,
+            entry.timestamp.timestamp_millis(),
+            json
+        ],
+    )
+    .with_context(|| "Failed to write history entry")?;
+    Ok(())
+}
+
+/// Decode a `SELECT id, entry, command, cwd, exit_code, timestamp` row
+fn read_row(row: &Row) -> rusqlite::Result<(i64, HistoryEntry)> {
+    let id: i64 = row.get(0)?;
+    let json: String = row.get(1)?;
+    let entry = serde_json::from_str::<HistoryEntry>(&json).unwrap_or_else(|_| {
+        // Fall back to the indexed columns if the JSON is unreadable
+        HistoryEntry {
+            command: row.get(2).unwrap_or_default(),
+            cwd: row.get(3).unwrap_or_default(),
+            exit_code: row.get(4).unwrap_or_default(),
+            timestamp: row
+                .get::<_, i64>(5)
+                .ok()
+                .and_then(DateTime::from_timestamp_millis)
+                .unwrap_or_default(),
+            stdout_summary: None,
+            session_id: None,
+            duration_ms: None,
+        }
+    });
+    Ok((id, entry))
+}
+
+impl HistoryStore for SqliteStore {
+    fn load_recent(&mut self, limit: usize) -> Result<Vec<HistoryEntry>> {
+        // Only the newest rows are read, however large the table is
+        let mut stmt = self.conn.prepare(
+            "SELECT id, entry, command, cwd, exit_code, timestamp
+             FROM history ORDER BY timestamp DESC, id DESC LIMIT ?1",
+        )?;
+        let mut rows = stmt
+            .query_map(params![limit as i64], read_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.reverse();
+        drop(stmt);
+
+        // Rows left out of the window (such as imported older entries) count
+        // as seen too, so `load_new` doesn't return them
+        let max_id: Option<i64> =
+            self.conn
+                .query_row("SELECT MAX(id) FROM history", [], |row| row.get(0))?;
+        self.last_id = self.last_id.max(max_id.unwrap_or(0));
+        Ok(rows.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    fn load_new(&mut self) -> Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, entry, command, cwd, exit_code, timestamp
+             FROM history WHERE id > ?1 ORDER BY id",
+        )?;
+        let rows = stmt
+            .query_map(params![self.last_id], read_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if let Some((id, _)) = rows.last() {
+            self.last_id = *id;
+        }
+        Ok(rows.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    fn append(&mut self, entry: &HistoryEntry) -> Result<()> {
+        insert_entry(&self.conn, entry)
+    }
+
+    /// Keep the newest `max_entries` rows, including other terminals' entries
+    fn compact(&mut self, _window: &[&HistoryEntry]) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM history WHERE id IN (
+                     SELECT id FROM history ORDER BY timestamp DESC, id DESC LIMIT -1 OFFSET ?1
+                 )",
+                params![self.max_entries as i64],
+            )
+            .with_context(|| "Failed to compact history database")?;
+        Ok(())
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&HistoryEntry) -> bool) -> Result<usize> {
+        // Rows are decoded one at a time; only the ids to delete are kept
+        let tx = self.conn.transaction()?;
+        let mut doomed = Vec::new();
+        {
+            let mut stmt =
+                tx.prepare("SELECT id, entry, command, cwd, exit_code, timestamp FROM history")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let (id, entry) = read_row(row)?;
+                if !keep(&entry) {
+                    doomed.push(id);
+                }
+            }
+        }
+        for id in &doomed {
+            tx.execute("DELETE FROM history WHERE id = ?1", params![id])?;
+        }
+        tx.commit()
+            .with_context(|| "Failed to delete history entries")?;
+        Ok(doomed.len())
+    }
+
+    fn remove(&mut self, entry: &HistoryEntry) -> Result<usize> {
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM history WHERE command = ?1 AND timestamp = ?2",
+                params![entry.command, entry.timestamp.timestamp_millis()],
+            )
+            .with_context(|| "Failed to delete history entry")?;
+        Ok(removed)
+    }
+
+    fn remove_command(&mut self, command: &str) -> Result<usize> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM history WHERE command = ?1", params![command])
+            .with_context(|| "Failed to delete history entries")?;
+        Ok(removed)
+    }
+
+    /// Only the command column is read; the JSON entries are never decoded
+    fn remove_matching(&mut self, pattern: &Regex) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut doomed = Vec::new();
+        {
+            let mut stmt = tx.prepare("SELECT id, command FROM history")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let command: String = row.get(1)?;
+                if pattern.is_match(&command) {
+                    doomed.push(row.get::<_, i64>(0)?);
+                }
+            }
+        }
+        for id in &doomed {
+            tx.execute("DELETE FROM history WHERE id = ?1", params![id])?;
+        }
+        tx.commit()
+            .with_context(|| "Failed to delete history entries")?;
+        Ok(doomed.len())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM history", [])
+            .with_context(|| "Failed to clear history database")?;
+        Ok(())
+    }
+
+    /// Rows are read back in timestamp order, so older entries can simply be
+    /// inserted; duplicates are looked up by command and second, like
+    /// `new_entries`, using the indexed columns
+    fn merge(&mut self, entries: &[HistoryEntry]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        for entry in entries {
+            let second = entry.timestamp.timestamp() * 1000;
+            let known: bool = tx.query_row(
+                "SELECT EXISTS (
+                     SELECT 1 FROM history
+                     WHERE command = ?1 AND timestamp >= ?2 AND timestamp < ?2 + 1000
+                 )",
+                params![entry.command, second],
+                |row| row.get(0),
+            )?;
+            // Rows inserted earlier in this loop are visible, so repeats
+            // within `entries` are skipped too
+            if !known {
+                insert_entry(&tx, entry)?;
+                added += 1;
+            }
+        }
+        tx.commit()
+            .with_context(|| "Failed to import history entries")?;
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(command: &str) -> HistoryEntry {
+        HistoryEntry::new(command.to_string()).with_exit_code(0)
+    }
+
+    #[test]
+    fn test_sqlite_load_recent_and_compact() {
+        let dir = tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("history.db"), 3).unwrap();
+        for i in 0..5 {
+            store.append(&entry(&format!("cmd{}", i))).unwrap();
+        }
+
+        let recent = store.load_recent(2).unwrap();
+        let commands: Vec<&str> = recent.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["cmd3", "cmd4"]);
+
+        store.compact(&[]).unwrap();
+        assert_eq!(store.load_recent(10).unwrap().len(), 3);
+        assert_eq!(store.load_recent(10).unwrap()[0].command, "cmd2");
+    }
+
+    #[test]
+    fn test_sqlite_concurrent_writers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let mut first = SqliteStore::open(path.clone(), 100).unwrap();
+        let mut second = SqliteStore::open(path, 100).unwrap();
+
+        first.append(&entry("from first")).unwrap();
+        second.append(&entry("from second")).unwrap();
+
+        assert_eq!(first.load_recent(10).unwrap().len(), 2);
+
+        second.append(&entry("later")).unwrap();
+        let new = first.load_new().unwrap();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].command, "later");
+    }
+
+    #[test]
+    fn test_jsonl_load_new_follows_appends_and_compaction() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history");
+        let mut ours = JsonlStore::open(path.clone()).unwrap();
+        let mut theirs = JsonlStore::open(path.clone()).unwrap();
+        assert!(ours.load_recent(10).unwrap().is_empty());
+
+        theirs.append(&entry("one")).unwrap();
+        theirs.append(&entry("two")).unwrap();
+        let new = ours.load_new().unwrap();
+        assert_eq!(new.len(), 2);
+        assert!(ours.load_new().unwrap().is_empty());
+
+        // A half-written line is left for the next sync
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"command\":\"par").unwrap();
+        assert!(ours.load_new().unwrap().is_empty());
+        drop(file);
+
+        // Compaction elsewhere replaces the file; only newer entries come through
+        let first = &new[0];
+        theirs.compact(&[first, &new[1]]).unwrap();
+        theirs.append(&entry("three")).unwrap();
+        let new = ours.load_new().unwrap();
+        let commands: Vec<&str> = new.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["three"]);
+    }
+
+    #[test]
+    fn test_migrate_jsonl_once() {
+        let dir = tempdir().unwrap();
+        let jsonl_path = dir.path().join("history");
+        let mut jsonl = JsonlStore::open(jsonl_path.clone()).unwrap();
+        jsonl.append(&entry("ls")).unwrap();
+        jsonl.append(&entry("pwd")).unwrap();
+
+        let mut store = SqliteStore::open(dir.path().join("history.db"), 100).unwrap();
+        assert_eq!(store.migrate_jsonl(&jsonl_path).unwrap(), 2);
+        assert_eq!(store.migrate_jsonl(&jsonl_path).unwrap(), 0);
+
+        let loaded = store.load_recent(10).unwrap();
+        assert_eq!(loaded[1].command, "pwd");
+        assert_eq!(loaded[1].exit_code, Some(0));
+        assert!(jsonl_path.exists());
+    }
+
+    #[test]
+    fn test_merge_orders_by_timestamp_and_skips_known() {
+        let dir = tempdir().unwrap();
+        let at = |command: &str, secs: i64| {
+            let mut e = entry(command);
+            e.timestamp = DateTime::from_timestamp(secs, 0).unwrap();
+            e
+        };
+        let imported = vec![at("old", 100), at("mid", 200), at("old", 100)];
+
+        let stores: Vec<Box<dyn HistoryStore>> = vec![
+            Box::new(JsonlStore::open(dir.path().join("history")).unwrap()),
+            Box::new(SqliteStore::open(dir.path().join("history.db"), 100).unwrap()),
+        ];
+        for mut store in stores {
+            store.append(&at("new", 300)).unwrap();
+            assert_eq!(store.merge(&imported).unwrap(), 2);
+            assert_eq!(store.merge(&imported).unwrap(), 0);
+
+            let commands: Vec<String> = store
+                .load_recent(10)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.command)
+                .collect();
+            assert_eq!(commands, vec!["old", "mid", "new"]);
+        }
+    }
+
+    #[test]
+    fn test_sqlite_targeted_deletes() {
+        let dir = tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("history.db"), 100).unwrap();
+        let mut first = entry("ls");
+        first.timestamp = DateTime::from_timestamp(100, 0).unwrap();
+        store.append(&first).unwrap();
+        for command in ["ls", "ssh prod", "ssh dev", "pwd"] {
+            store.append(&entry(command)).unwrap();
+        }
+
+        assert_eq!(store.remove(&first).unwrap(), 1);
+        assert_eq!(store.remove_command("pwd").unwrap(), 1);
+        let pattern = Regex::new("^ssh").unwrap();
+        assert_eq!(store.remove_matching(&pattern).unwrap(), 2);
+
+        let commands: Vec<String> = store
+            .load_recent(10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["ls"]);
+    }
+
+    #[test]
+    fn test_encrypted_jsonl() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history");
+        let mut plain = JsonlStore::open(path.clone()).unwrap();
+        plain.append(&entry("ssh prod.example.com")).unwrap();
+
+        // The plain file is converted on first use
+        let cipher = RecordCipher::create(b"passphrase").unwrap();
+        let (mut store, converted) = JsonlStore::open_encrypted(path.clone(), cipher).unwrap();
+        assert_eq!(converted, 1);
+        store.append(&entry("cat ~/.aws/credentials")).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("example") && !text.contains("aws"));
+        assert!(JsonlStore::open(path.clone()).is_err());
+
+        let header = Header::read(&path).unwrap().unwrap();
+        let cipher = RecordCipher::unlock(&header, b"passphrase").unwrap();
+        let (mut reopened, converted) = JsonlStore::open_encrypted(path.clone(), cipher).unwrap();
+        assert_eq!(converted, 0);
+        let commands: Vec<String> = reopened
+            .load_recent(10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(
+            commands,
+            vec!["ssh prod.example.com", "cat ~/.aws/credentials"]
+        );
+
+        // Rewrites keep the header
+        assert_eq!(
+            reopened.retain(&|e| !e.command.starts_with("ssh")).unwrap(),
+            1
+        );
+        assert!(Header::read(&path).unwrap().is_some());
+        assert_eq!(reopened.load_recent(10).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,378 @@
+//! AI autosuggestion module for Fool Shell
+//! Completes the partially typed command line in the background and serves
+//! the result as ghost text through the line editor's hinter
+
+use crate::ai::{AiAgent, ChatMessage};
+use crate::config::{AiConfig, AiSuggestConfig};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::AbortHandle;
+
+const SUGGEST_SYSTEM_PROMPT: &str = "You complete partially typed shell commands. \
+     Reply with exactly one complete command line that starts with the user's \
+     partial input. No explanation, no markdown, no surrounding quotes.";
+
+/// Bounded cache of completed command lines keyed by the typed prefix
+struct SuggestionCache {
+    entries: HashMap<String, String>,
+    order: VecDeque<String>, // Insertion order for eviction
+    capacity: usize,
+}
+
+impl SuggestionCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Find a completion for `line`, either cached for it directly or for an
+    /// earlier prefix the user is still typing along
+    fn lookup(&self, line: &str) -> Option<&str> {
+        if let Some(full) = self.entries.get(line) {
+            return Some(full.as_str());
+        }
+        self.order
+            .iter()
+            .rev()
+            .filter(|key| line.starts_with(key.as_str()))
+            .filter_map(|key| self.entries.get(key))
+            .find(|full| full.starts_with(line) && full.len() > line.len())
+            .map(|full| full.as_str())
+    }
+
+    fn insert(&mut self, line: String, full: String) {
+        if self.entries.insert(line.clone(), full).is_none() {
+            self.order.push_back(line);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Redraws the edited line so the hinter runs again
+pub type Repaint = Arc<dyn Fn() + Send + Sync>;
+
+/// Shared state between the hinter and its background requests
+struct SuggestState {
+    cache: SuggestionCache,
+    pending: Option<(String, AbortHandle)>, // Line being completed and its task
+    current: String,                        // Line the editor last asked about
+    repaint: Option<Repaint>,
+}
+
+/// Inline AI autosuggester
+///
+/// `hint` never waits on the network: it answers from the cache and otherwise
+/// schedules a debounced request. When the completion arrives for the line
+/// still being edited, the editor is asked to repaint so it shows right away.
+pub struct AiSuggester {
+    agent: AiAgent,
+    config: AiSuggestConfig,
+    state: Arc<Mutex<SuggestState>>,
+    runtime: Handle,
+}
+
+impl AiSuggester {
+    /// Create a suggester, or `None` when suggestions are switched off,
+    /// AI isn't configured, or there is no async runtime to run requests on
+    ///
+    /// Requests go through `agent`'s HTTP client.
+    pub fn new(agent: &AiAgent, ai_config: &AiConfig) -> Option<Self> {
+        let config = ai_config.suggest.clone();
+        if !config.is_enabled() {
+            return None;
+        }
+
+        let mut agent_config = ai_config.clone();
+        if !config.model.is_empty() {
+            agent_config.model = config.model.clone();
+        }
+        if !config.api_base.is_empty() {
+            agent_config.api_base = config.api_base.clone();
+        }
+        // Completions should be predictable rather than creative
+        agent_config.temperature = 0.0;
+
+        let agent = agent.with_config(agent_config);
+        if !agent.is_configured() {
+            return None;
+        }
+
+        let runtime = Handle::try_current().ok()?;
+        let state = SuggestState {
+            cache: SuggestionCache::new(config.cache_size),
+            pending: None,
+            current: String::new(),
+            repaint: None,
+        };
+
+        Some(Self {
+            agent,
+            config,
+            state: Arc::new(Mutex::new(state)),
+            runtime,
+        })
+    }
+
+    /// Set how to redraw the editor once a background completion arrives
+    pub fn set_repaint(&self, repaint: Repaint) {
+        if let Ok(mut state) = self.state.lock() {
+            state.repaint = Some(repaint);
+        }
+    }
+
+    /// Return the ghost text for `line` if a completion is known, otherwise
+    /// request one in the background (cancelling any stale request)
+    pub fn hint(&self, line: &str, recent: &[String]) -> Option<String> {
+        let mut state = self.state.lock().ok()?;
+        if state.current != line {
+            state.current = line.to_string();
+        }
+
+        if line.trim().chars().count() < self.config.min_chars {
+            if let Some((_, task)) = state.pending.take() {
+                task.abort();
+            }
+            return None;
+        }
+
+        if let Some(full) = state.cache.lookup(line) {
+            return Some(full[line.len()..].to_string());
+        }
+
+        if matches!(&state.pending, Some((pending, _)) if pending == line) {
+            return None;
+        }
+        if let Some((_, task)) = state.pending.take() {
+            task.abort();
+        }
+
+        let task = self.runtime.spawn(Self::fetch(
+            self.agent.clone(),
+            Arc::clone(&self.state),
+            line.to_string(),
+            recent.to_vec(),
+            self.config.clone(),
+        ));
+        state.pending = Some((line.to_string(), task.abort_handle()));
+        None
+    }
+
+    /// Debounce, ask the model, cache the completion, and repaint if the
+    /// user hasn't moved on from `line`
+    async fn fetch(
+        agent: AiAgent,
+        state: Arc<Mutex<SuggestState>>,
+        line: String,
+        recent: Vec<String>,
+        config: AiSuggestConfig,
+    ) {
+        // Aborting during the sleep means no request is ever sent
+        tokio::time::sleep(Duration::from_millis(config.debounce_ms)).await;
+
+        let messages = build_messages(&line, &recent, config.max_dir_entries);
+        let reply = tokio::time::timeout(
+            Duration::from_millis(config.timeout_ms),
+            agent.complete(messages),
+        )
+        .await;
+
+        let repaint = {
+            let Ok(mut state) = state.lock() else {
+                return;
+            };
+            if matches!(&state.pending, Some((pending, _)) if *pending == line) {
+                state.pending = None;
+            }
+            let Some(full) = reply
+                .ok()
+                .and_then(Result::ok)
+                .and_then(|reply| extract_completion(&line, &reply))
+            else {
+                return;
+            };
+            let current = state.current == line;
+            state.cache.insert(line, full);
+            if !current {
+                return;
+            }
+            state.repaint.clone()
+        };
+        // Unlocked: the redraw calls back into `hint`
+        if let Some(repaint) = repaint {
+            repaint();
+        }
+    }
+}
+
+/// Build the completion request from cwd, recent history and a directory listing
+fn build_messages(line: &str, recent: &[String], max_dir_entries: usize) -> Vec<ChatMessage> {
+    let cwd = std::env::current_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "?".to_string());
+
+    let mut listing: Vec<String> = std::fs::read_dir(".")
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    if e.path().is_dir() {
+                        format!("{}/", name)
+                    } else {
+                        name
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    listing.sort();
+    listing.truncate(max_dir_entries);
+
+    let context = format!(
+        "Current directory: {}\nDirectory contents: {}\nRecent commands:\n{}\n\nPartial input: {}",
+        cwd,
+        listing.join(" "),
+        recent.join("\n"),
+        line
+    );
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: SUGGEST_SYSTEM_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: context,
+        },
+    ]
+}
+
+/// Pull a usable completion out of a model reply; it must extend `line`
+fn extract_completion(line: &str, reply: &str) -> Option<String> {
+    let candidate = reply
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("```"))?;
+    let candidate = candidate.trim_matches('`');
+    let candidate = candidate.strip_prefix("$ ").unwrap_or(candidate);
+
+    if candidate.starts_with(line) && candidate.len() > line.len() && !candidate.contains('\n') {
+        Some(candidate.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_completion() {
+        assert_eq!(
+            extract_completion("git ch", "git checkout main"),
+            Some("git checkout main".to_string())
+        );
+        assert_eq!(
+            extract_completion("git ch", "```bash\n$ git checkout main\n```"),
+            Some("git checkout main".to_string())
+        );
+        // Replies that don't extend the typed input are useless as ghost text
+        assert_eq!(extract_completion("git ch", "ls -la"), None);
+        assert_eq!(extract_completion("git ch", "git ch"), None);
+    }
+
+    #[test]
+    fn test_cache_lookup_follows_typing() {
+        let mut cache = SuggestionCache::new(8);
+        cache.insert("car".to_string(), "cargo build --release".to_string());
+
+        assert_eq!(cache.lookup("car"), Some("cargo build --release"));
+        assert_eq!(cache.lookup("cargo b"), Some("cargo build --release"));
+        assert_eq!(cache.lookup("cargo t"), None);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let mut cache = SuggestionCache::new(2);
+        cache.insert("a".to_string(), "ab".to_string());
+        cache.insert("b".to_string(), "bc".to_string());
+        cache.insert("c".to_string(), "cd".to_string());
+
+        assert_eq!(cache.lookup("a"), None);
+        assert_eq!(cache.lookup("c"), Some("cd"));
+    }
+
+    #[tokio::test]
+    async fn test_hint_repaints_when_completion_arrives() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        // A chat completions endpoint that answers once
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while let Ok(n) = stream.read(&mut buf) {
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let body = r#"{"choices":[{"message":{"content":"git checkout main"}}]}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        });
+
+        let mut config = AiConfig {
+            api_base,
+            api_key: "test".to_string(),
+            ..AiConfig::default()
+        };
+        config.suggest.enabled = true;
+        config.suggest.debounce_ms = 0;
+        let suggester = AiSuggester::new(&AiAgent::new(config.clone()), &config).unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        suggester.set_repaint(Arc::new(move || {
+            let _ = tx.send(());
+        }));
+
+        assert_eq!(suggester.hint("git ch", &[]), None);
+        // The editor is told to redraw without another key being typed
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            suggester.hint("git ch", &[]).as_deref(),
+            Some("eckout main")
+        );
+    }
+}
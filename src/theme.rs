@@ -0,0 +1,777 @@
+//! Theme module for Fool Shell
+//! Colour palettes for highlighting, prompts and messages, adapted to what
+//! the terminal can display
+//!
+//! A theme gives a style to each `Role`. Styles are written the same way as
+//! in prompt templates: `green bold`, `bg:blue`, `208`, `#ff8700`. Besides
+//! the built-in themes, `~/.config/fool/themes/NAME.toml` defines a theme:
+//!
+//! ```toml
+//! inherits = "nord"          # Built-in theme for roles not listed (default: dracula)
+//! [styles]
+//! command = "#a3be8c bold"
+//! ```
+
+use crate::config::{expand_path, ColorMode, Config, UiConfig};
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::style::{Attribute, Color, ContentStyle, StyledContent};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Built-in theme names, the first is the default
+pub const BUILTIN_THEMES: &[&str] = &["dracula", "monokai", "solarized", "nord", "no-color"];
+
+/// What a piece of text is, which decides its style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    // Line editor
+    Command,        // Builtin, alias or program on PATH
+    UnknownCommand, // Command that can't be found
+    Flag,
+    Variable,
+    String,
+    Operator, // | < > >>
+    Redirect, // File after a redirection
+    SyntaxError,
+    AiTrigger,
+    AiQuery,
+    Hint,
+    // Prompt
+    User,
+    Cwd,
+    Duration,
+    Symbol,
+    // Messages
+    Success,
+    Error,
+    Warning,
+    Info,
+    Accent,
+    Muted,
+    // Markdown in AI answers
+    Heading,
+    Code,
+}
+
+impl Role {
+    pub const ALL: [Role; 23] = [
+        Role::Command,
+        Role::UnknownCommand,
+        Role::Flag,
+        Role::Variable,
+        Role::String,
+        Role::Operator,
+        Role::Redirect,
+        Role::SyntaxError,
+        Role::AiTrigger,
+        Role::AiQuery,
+        Role::Hint,
+        Role::User,
+        Role::Cwd,
+        Role::Duration,
+        Role::Symbol,
+        Role::Success,
+        Role::Error,
+        Role::Warning,
+        Role::Info,
+        Role::Accent,
+        Role::Muted,
+        Role::Heading,
+        Role::Code,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Command => "command",
+            Role::UnknownCommand => "unknown_command",
+            Role::Flag => "flag",
+            Role::Variable => "variable",
+            Role::String => "string",
+            Role::Operator => "operator",
+            Role::Redirect => "redirect",
+            Role::SyntaxError => "syntax_error",
+            Role::AiTrigger => "ai_trigger",
+            Role::AiQuery => "ai_query",
+            Role::Hint => "hint",
+            Role::User => "user",
+            Role::Cwd => "cwd",
+            Role::Duration => "duration",
+            Role::Symbol => "symbol",
+            Role::Success => "success",
+            Role::Error => "error",
+            Role::Warning => "warning",
+            Role::Info => "info",
+            Role::Accent => "accent",
+            Role::Muted => "muted",
+            Role::Heading => "heading",
+            Role::Code => "code",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.name() == name)
+    }
+}
+
+const DRACULA: &[(&str, &str)] = &[
+    ("command", "#50fa7b bold"),
+    ("unknown_command", "#ff5555"),
+    ("flag", "#8be9fd"),
+    ("variable", "#bd93f9"),
+    ("string", "#f1fa8c"),
+    ("operator", "#ff79c6"),
+    ("redirect", "#8be9fd italic"),
+    ("syntax_error", "#ff5555 underline"),
+    ("ai_trigger", "#ffb86c bold"),
+    ("ai_query", "#8be9fd"),
+    ("hint", "#6272a4"),
+    ("user", "#50fa7b bold"),
+    ("cwd", "#bd93f9 bold"),
+    ("duration", "#f1fa8c"),
+    ("symbol", "#ff79c6 bold"),
+    ("success", "#50fa7b"),
+    ("error", "#ff5555"),
+    ("warning", "#ffb86c"),
+    ("info", "#8be9fd"),
+    ("accent", "#ff79c6"),
+    ("muted", "#6272a4"),
+    ("heading", "#bd93f9 bold"),
+    ("code", "#f1fa8c"),
+];
+
+const MONOKAI: &[(&str, &str)] = &[
+    ("command", "#a6e22e bold"),
+    ("unknown_command", "#f92672"),
+    ("flag", "#66d9ef"),
+    ("variable", "#fd971f"),
+    ("string", "#e6db74"),
+    ("operator", "#f92672"),
+    ("redirect", "#66d9ef italic"),
+    ("syntax_error", "#f92672 underline"),
+    ("ai_trigger", "#fd971f bold"),
+    ("ai_query", "#66d9ef"),
+    ("hint", "#75715e"),
+    ("user", "#a6e22e bold"),
+    ("cwd", "#66d9ef bold"),
+    ("duration", "#e6db74"),
+    ("symbol", "#f92672 bold"),
+    ("success", "#a6e22e"),
+    ("error", "#f92672"),
+    ("warning", "#fd971f"),
+    ("info", "#66d9ef"),
+    ("accent", "#ae81ff"),
+    ("muted", "#75715e"),
+    ("heading", "#f92672 bold"),
+    ("code", "#e6db74"),
+];
+
+const SOLARIZED: &[(&str, &str)] = &[
+    ("command", "#859900 bold"),
+    ("unknown_command", "#dc322f"),
+    ("flag", "#268bd2"),
+    ("variable", "#b58900"),
+    ("string", "#2aa198"),
+    ("operator", "#d33682"),
+    ("redirect", "#268bd2 italic"),
+    ("syntax_error", "#dc322f underline"),
+    ("ai_trigger", "#cb4b16 bold"),
+    ("ai_query", "#268bd2"),
+    ("hint", "#586e75"),
+    ("user", "#859900 bold"),
+    ("cwd", "#268bd2 bold"),
+    ("duration", "#b58900"),
+    ("symbol", "#d33682 bold"),
+    ("success", "#859900"),
+    ("error", "#dc322f"),
+    ("warning", "#b58900"),
+    ("info", "#2aa198"),
+    ("accent", "#6c71c4"),
+    ("muted", "#586e75"),
+    ("heading", "#cb4b16 bold"),
+    ("code", "#2aa198"),
+];
+
+const NORD: &[(&str, &str)] = &[
+    ("command", "#88c0d0 bold"),
+    ("unknown_command", "#bf616a"),
+    ("flag", "#8fbcbb"),
+    ("variable", "#b48ead"),
+    ("string", "#a3be8c"),
+    ("operator", "#81a1c1"),
+    ("redirect", "#8fbcbb italic"),
+    ("syntax_error", "#bf616a underline"),
+    ("ai_trigger", "#ebcb8b bold"),
+    ("ai_query", "#88c0d0"),
+    ("hint", "#616e88"),
+    ("user", "#a3be8c bold"),
+    ("cwd", "#81a1c1 bold"),
+    ("duration", "#ebcb8b"),
+    ("symbol", "#b48ead bold"),
+    ("success", "#a3be8c"),
+    ("error", "#bf616a"),
+    ("warning", "#d08770"),
+    ("info", "#88c0d0"),
+    ("accent", "#b48ead"),
+    ("muted", "#616e88"),
+    ("heading", "#88c0d0 bold"),
+    ("code", "#a3be8c"),
+];
+
+/// Attributes only; also used for every theme when colour is off
+const NO_COLOR: &[(&str, &str)] = &[
+    ("command", "bold"),
+    ("unknown_command", ""),
+    ("flag", ""),
+    ("variable", ""),
+    ("string", ""),
+    ("operator", "bold"),
+    ("redirect", "italic"),
+    ("syntax_error", "bold underline"),
+    ("ai_trigger", "bold"),
+    ("ai_query", ""),
+    ("hint", "dim"),
+    ("user", "bold"),
+    ("cwd", "bold"),
+    ("duration", ""),
+    ("symbol", "bold"),
+    ("success", ""),
+    ("error", "bold"),
+    ("warning", "bold"),
+    ("info", ""),
+    ("accent", "bold"),
+    ("muted", "dim"),
+    ("heading", "bold underline"),
+    ("code", ""),
+];
+
+fn builtin_palette(name: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    match name {
+        "dracula" => Some(DRACULA),
+        "monokai" => Some(MONOKAI),
+        "solarized" => Some(SOLARIZED),
+        "nord" => Some(NORD),
+        "no-color" => Some(NO_COLOR),
+        _ => None,
+    }
+}
+
+/// Colours the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    None,
+    Basic, // The 16 named ANSI colours
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// `ui.color`, or for "auto" the environment: `NO_COLOR`, `COLORTERM` and `TERM`
+    pub fn detect(mode: ColorMode) -> Self {
+        match mode {
+            ColorMode::Never => Self::None,
+            ColorMode::Basic => Self::Basic,
+            ColorMode::Ansi256 => Self::Ansi256,
+            ColorMode::Truecolor => Self::TrueColor,
+            ColorMode::Auto if !std::io::stdout().is_terminal() => Self::None,
+            ColorMode::Auto => Self::from_env(|name| std::env::var(name).ok()),
+        }
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        // https://no-color.org: any non-empty value turns colour off
+        if var("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Self::None;
+        }
+        let colorterm = var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Self::TrueColor;
+        }
+        let term = var("TERM").unwrap_or_default();
+        if term == "dumb" {
+            Self::None
+        } else if term.ends_with("-direct") || term.contains("truecolor") {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Basic
+        }
+    }
+
+    /// `style` with its colours replaced by the closest ones available
+    pub fn adapt(self, mut style: ContentStyle) -> ContentStyle {
+        style.foreground_color = style.foreground_color.and_then(|c| self.adapt_color(c));
+        style.background_color = style.background_color.and_then(|c| self.adapt_color(c));
+        style.underline_color = style.underline_color.and_then(|c| self.adapt_color(c));
+        style
+    }
+
+    fn adapt_color(self, color: Color) -> Option<Color> {
+        match (self, color) {
+            (Self::None, _) => None,
+            (Self::TrueColor, _) => Some(color),
+            (Self::Ansi256, Color::Rgb { r, g, b }) => {
+                Some(Color::AnsiValue(rgb_to_ansi256(r, g, b)))
+            }
+            (Self::Basic, Color::Rgb { r, g, b }) => Some(rgb_to_basic(r, g, b)),
+            (Self::Basic, Color::AnsiValue(n)) => {
+                let (r, g, b) = ansi256_to_rgb(n);
+                Some(match BASIC_COLORS.get(n as usize) {
+                    Some((color, _)) => *color,
+                    None => rgb_to_basic(r, g, b),
+                })
+            }
+            _ => Some(color),
+        }
+    }
+}
+
+/// The 16 ANSI colours in palette order, with xterm's values
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => BASIC_COLORS[n as usize].1,
+        16..=231 => {
+            let n = n - 16;
+            (
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (n - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// Closest entry of the 6x6x6 cube or the grey ramp
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let cube = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (v - 35) / 40,
+    };
+    let (cr, cg, cb) = (cube(r), cube(g), cube(b));
+    let cube_index = 16 + 36 * cr + 6 * cg + cb;
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = if average < 8 {
+        232
+    } else {
+        232 + ((average - 8) / 10).min(23) as u8
+    };
+
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    if distance(ansi256_to_rgb(grey_index)) < distance(ansi256_to_rgb(cube_index)) {
+        grey_index
+    } else {
+        cube_index
+    }
+}
+
+/// Closest named colour by hue and brightness; plain distance would turn
+/// most pastel colours grey
+fn rgb_to_basic(r: u8, g: u8, b: u8) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = (max - min) as f32;
+
+    if chroma < 40.0 {
+        return match max {
+            0..=63 => Color::Black,
+            64..=159 => Color::DarkGrey,
+            160..=219 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let hue = if max as f32 == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max as f32 == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    let bright = max >= 200;
+    match (((hue + 30.0) / 60.0) as usize % 6, bright) {
+        (0, true) => Color::Red,
+        (0, false) => Color::DarkRed,
+        (1, true) => Color::Yellow,
+        (1, false) => Color::DarkYellow,
+        (2, true) => Color::Green,
+        (2, false) => Color::DarkGreen,
+        (3, true) => Color::Cyan,
+        (3, false) => Color::DarkCyan,
+        (4, true) => Color::Blue,
+        (4, false) => Color::DarkBlue,
+        (_, true) => Color::Magenta,
+        (_, false) => Color::DarkMagenta,
+    }
+}
+
+/// A theme file in the themes directory
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    #[serde(default)]
+    inherits: Option<String>,
+    #[serde(default)]
+    styles: BTreeMap<String, String>,
+}
+
+/// Styles for every role, already adapted to the terminal
+#[derive(Debug, Clone)]
+pub struct Theme {
+    support: ColorSupport,
+    styles: [ContentStyle; Role::ALL.len()],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::builtin(BUILTIN_THEMES[0], ColorSupport::detect(ColorMode::Auto))
+            .expect("default theme is built in")
+    }
+}
+
+impl Theme {
+    /// The theme named by `ui.theme`: a built-in, a file in the themes
+    /// directory, or a path to a `.toml` file
+    pub fn load(ui: &UiConfig) -> Result<Self> {
+        Self::load_named(&ui.theme, ColorSupport::detect(ui.color))
+    }
+
+    /// Themes were checked by `Config::load`; one that still fails to load
+    /// falls back to the default theme
+    pub fn from_config(ui: &UiConfig) -> Self {
+        Self::load(ui).unwrap_or_default()
+    }
+
+    fn load_named(name: &str, support: ColorSupport) -> Result<Self> {
+        if let Some(theme) = Self::builtin(name, support) {
+            return Ok(theme);
+        }
+        let path = if name.ends_with(".toml") || name.contains('/') {
+            expand_path(name)
+        } else {
+            themes_dir().join(format!("{}.toml", name))
+        };
+        if !path.exists() {
+            bail!(
+                "unknown theme '{}' (built-in: {}; or create {})",
+                name,
+                BUILTIN_THEMES.join(", "),
+                path.display()
+            );
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read theme file: {:?}", path))?;
+        Self::from_toml(&content, support)
+            .with_context(|| format!("Invalid theme file: {:?}", path))
+    }
+
+    pub fn builtin(name: &str, support: ColorSupport) -> Option<Self> {
+        let palette = builtin_palette(name)?;
+        let mut theme = Self {
+            support: if name == "no-color" {
+                ColorSupport::None
+            } else {
+                support
+            },
+            styles: [ContentStyle::new(); Role::ALL.len()],
+        };
+        // Without colour every theme falls back to attributes only
+        let palette = if theme.support == ColorSupport::None {
+            NO_COLOR
+        } else {
+            palette
+        };
+        for (role, spec) in palette {
+            let role = Role::parse(role).expect("built-in theme roles are valid");
+            theme.set(role, parse_spec(spec).expect("built-in theme styles parse"));
+        }
+        Some(theme)
+    }
+
+    fn from_toml(content: &str, support: ColorSupport) -> Result<Self> {
+        let file: ThemeFile = toml::from_str(content)?;
+        let base = file.inherits.as_deref().unwrap_or(BUILTIN_THEMES[0]);
+        let mut theme = Self::builtin(base, support)
+            .ok_or_else(|| anyhow!("'inherits' must be a built-in theme, not '{}'", base))?;
+        for (name, spec) in &file.styles {
+            let role = Role::parse(name).ok_or_else(|| anyhow!("unknown style '{}'", name))?;
+            let style = parse_spec(spec).with_context(|| format!("in style '{}'", name))?;
+            // Without colour the inherited attribute-only styles are kept
+            if theme.support != ColorSupport::None {
+                theme.set(role, style);
+            }
+        }
+        Ok(theme)
+    }
+
+    fn set(&mut self, role: Role, style: ContentStyle) {
+        self.styles[role as usize] = self.support.adapt(style);
+    }
+
+    pub fn get(&self, role: Role) -> ContentStyle {
+        self.styles[role as usize]
+    }
+
+    pub fn support(&self) -> ColorSupport {
+        self.support
+    }
+
+    /// `content` in the style of `role`
+    pub fn paint<D: Display>(&self, role: Role, content: D) -> StyledContent<D> {
+        StyledContent::new(self.get(role), content)
+    }
+
+    /// Parse a style spec whose words may also name roles, e.g. `cwd underline`
+    pub fn style(&self, spec: &str) -> Result<ContentStyle> {
+        let mut style = ContentStyle::new();
+        for word in spec.split([' ', ',']).filter(|w| !w.is_empty()) {
+            match Role::parse(word) {
+                Some(role) => style = merge(style, self.get(role)),
+                None => apply_word(&mut style, word)?,
+            }
+        }
+        Ok(self.support.adapt(style))
+    }
+}
+
+/// User theme files live next to config.toml
+pub fn themes_dir() -> PathBuf {
+    Config::default_path()
+        .parent()
+        .map(|dir| dir.join("themes"))
+        .unwrap_or_else(|| PathBuf::from("themes"))
+}
+
+/// `green bold`, `bg:blue`, `208`, `#ff8700`, separated by spaces or commas
+pub fn parse_spec(spec: &str) -> Result<ContentStyle> {
+    let mut style = ContentStyle::new();
+    for word in spec.split([' ', ',']).filter(|w| !w.is_empty()) {
+        apply_word(&mut style, word)?;
+    }
+    Ok(style)
+}
+
+fn apply_word(style: &mut ContentStyle, word: &str) -> Result<()> {
+    match word {
+        "bold" => style.attributes.set(Attribute::Bold),
+        "dim" => style.attributes.set(Attribute::Dim),
+        "italic" => style.attributes.set(Attribute::Italic),
+        "underline" => style.attributes.set(Attribute::Underlined),
+        "reverse" => style.attributes.set(Attribute::Reverse),
+        _ => match word.strip_prefix("bg:") {
+            Some(color) => style.background_color = Some(parse_color(color)?),
+            None => style.foreground_color = Some(parse_color(word)?),
+        },
+    }
+    Ok(())
+}
+
+fn parse_color(name: &str) -> Result<Color> {
+    if let Ok(n) = name.parse::<u8>() {
+        return Ok(Color::AnsiValue(n));
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        if let (6, Ok(rgb)) = (hex.len(), u32::from_str_radix(hex, 16)) {
+            return Ok(Color::Rgb {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+            });
+        }
+    }
+    Color::try_from(name).map_err(|_| anyhow!("unknown color '{}'", name))
+}
+
+/// `inner` on top of `outer`: colours are replaced, attributes add up
+pub fn merge(outer: ContentStyle, inner: ContentStyle) -> ContentStyle {
+    let mut style = outer;
+    style.foreground_color = inner.foreground_color.or(outer.foreground_color);
+    style.background_color = inner.background_color.or(outer.background_color);
+    style.attributes.extend(inner.attributes);
+    style
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Set the theme for the rest of the process; call once at startup
+pub fn init(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+/// The theme set by `init`, or the default one
+pub fn current() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// `content` in the style of `role` in the current theme
+pub fn paint<D: Display>(role: Role, content: D) -> StyledContent<D> {
+    current().paint(role, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn test_detect_color_support() {
+        let detect = |vars: &[(&str, &str)]| ColorSupport::from_env(env(vars));
+        assert_eq!(detect(&[("TERM", "xterm")]), ColorSupport::Basic);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), ColorSupport::Ansi256);
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("COLORTERM", "truecolor")]),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(detect(&[("TERM", "dumb")]), ColorSupport::None);
+        assert_eq!(
+            detect(&[("NO_COLOR", "1"), ("COLORTERM", "truecolor")]),
+            ColorSupport::None
+        );
+        // An empty NO_COLOR doesn't count
+        assert_eq!(
+            detect(&[("NO_COLOR", ""), ("TERM", "xterm-256color")]),
+            ColorSupport::Ansi256
+        );
+    }
+
+    #[test]
+    fn test_adapt_colors() {
+        let orange = Color::Rgb {
+            r: 0xff,
+            g: 0x87,
+            b: 0,
+        };
+        let adapt = |support: ColorSupport, color| support.adapt_color(color);
+        assert_eq!(adapt(ColorSupport::TrueColor, orange), Some(orange));
+        assert_eq!(
+            adapt(ColorSupport::Ansi256, orange),
+            Some(Color::AnsiValue(208))
+        );
+        assert_eq!(adapt(ColorSupport::Basic, orange), Some(Color::Yellow));
+        assert_eq!(adapt(ColorSupport::None, orange), None);
+        assert_eq!(adapt(ColorSupport::Basic, Color::Green), Some(Color::Green));
+        assert_eq!(
+            adapt(ColorSupport::Basic, Color::AnsiValue(9)),
+            Some(Color::Red)
+        );
+
+        assert_eq!(rgb_to_ansi256(0x80, 0x80, 0x80), 244);
+        assert_eq!(rgb_to_basic(0x50, 0xfa, 0x7b), Color::Green); // Dracula green
+        assert_eq!(rgb_to_basic(0x62, 0x72, 0xa4), Color::DarkBlue);
+        assert_eq!(rgb_to_basic(0x58, 0x58, 0x58), Color::DarkGrey);
+    }
+
+    #[test]
+    fn test_builtin_themes_are_complete() {
+        for name in BUILTIN_THEMES {
+            let palette = builtin_palette(name).unwrap();
+            for role in Role::ALL {
+                assert!(
+                    palette.iter().any(|(r, _)| *r == role.name()),
+                    "{} has no {} style",
+                    name,
+                    role.name()
+                );
+            }
+            assert!(Theme::builtin(name, ColorSupport::TrueColor).is_some());
+        }
+    }
+
+    #[test]
+    fn test_no_color_keeps_attributes() {
+        let theme = Theme::builtin("dracula", ColorSupport::None).unwrap();
+        assert_eq!(theme.get(Role::Command).foreground_color, None);
+        assert!(theme.get(Role::Command).attributes.has(Attribute::Bold));
+        assert!(theme.get(Role::Hint).attributes.has(Attribute::Dim));
+        assert_eq!(theme.style("cwd red").unwrap().foreground_color, None);
+
+        let theme = Theme::builtin("no-color", ColorSupport::TrueColor).unwrap();
+        assert_eq!(theme.support(), ColorSupport::None);
+    }
+
+    #[test]
+    fn test_theme_file() {
+        let theme = Theme::from_toml(
+            "inherits = \"nord\"\n[styles]\ncommand = \"red underline\"\n",
+            ColorSupport::TrueColor,
+        )
+        .unwrap();
+        let command = theme.get(Role::Command);
+        assert_eq!(command.foreground_color, Some(Color::Red));
+        assert!(command.attributes.has(Attribute::Underlined));
+        let nord = Theme::builtin("nord", ColorSupport::TrueColor).unwrap();
+        assert_eq!(theme.get(Role::Error), nord.get(Role::Error));
+
+        for bad in [
+            "[styles]\nnope = \"red\"",
+            "[styles]\ncommand = \"purple\"",
+            "inherits = \"mine\"",
+            "colour = 1",
+        ] {
+            assert!(
+                Theme::from_toml(bad, ColorSupport::TrueColor).is_err(),
+                "{} should not load",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_style_with_roles() {
+        let theme = Theme::builtin("dracula", ColorSupport::TrueColor).unwrap();
+        let style = theme.style("cwd underline").unwrap();
+        assert_eq!(
+            style.foreground_color,
+            theme.get(Role::Cwd).foreground_color
+        );
+        assert!(style.attributes.has(Attribute::Bold));
+        assert!(style.attributes.has(Attribute::Underlined));
+
+        let theme = Theme::builtin("dracula", ColorSupport::Ansi256).unwrap();
+        assert_eq!(
+            theme.style("#ff8700").unwrap().foreground_color,
+            Some(Color::AnsiValue(208))
+        );
+    }
+}